@@ -1,5 +1,12 @@
+use crate::lexer::NumberSuffix;
+use crate::source::Span;
 use std::fmt;
 
+mod shadowing;
+mod visit;
+
+pub use visit::{for_each_expression, for_each_statement};
+
 #[derive(Debug, Clone)]
 pub struct Program {
     pub statements: Vec<Statement>,
@@ -7,35 +14,96 @@ pub struct Program {
 
 impl Program {
     pub fn format(&self) -> String {
-        self.statements
-            .iter()
-            .map(|s| s.format(0))
-            .collect::<Vec<_>>()
-            .join("\n")
+        let mut output = format_lines(&self.statements, 0)
+            .trim_start_matches('\n')
+            .to_string();
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output
     }
 }
 
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
+    /// `let name  value`, or `let mut name  value` for a variable that can
+    /// be assigned to later. `ty` is the annotated type, as in
+    /// `let x:float  1`.
     Let {
-        name: String,
+        name: Ident,
+        mutable: bool,
+        ty: Option<TypeExpr>,
+        value: Expression,
+    },
+    /// `name = value`, or a compound form such as `name += value` that
+    /// applies `op` to the old and new values. `fields` is the path to a
+    /// record field being updated, as in `p.x = 1`.
+    Assign {
+        target: Ident,
+        fields: Vec<Ident>,
+        op: Option<BinaryOp>,
         value: Expression,
     },
+    /// `type Name` with one `name:type` field per indented line. A generic
+    /// type names its parameters in brackets: `type Pair[A, B]`.
+    Type {
+        name: Ident,
+        params: Vec<Ident>,
+        fields: Vec<FieldDecl>,
+    },
+    /// `enum Name` with one variant per indented line, such as `Empty` or
+    /// `Rect float  float`. Like a type, it may have parameters.
+    Enum {
+        name: Ident,
+        params: Vec<Ident>,
+        variants: Vec<VariantDecl>,
+    },
+    /// `func name a:int  b -> int`, where every annotation is optional.
     Function {
-        name: String,
-        params: Vec<String>,
+        name: Ident,
+        params: Vec<Param>,
+        return_type: Option<TypeExpr>,
         body: Vec<Statement>,
     },
     If {
         condition: Expression,
         then_body: Vec<Statement>,
+        /// `else if` branches, tried in order while the conditions before
+        /// them are false.
+        else_ifs: Vec<ElseIf>,
         else_body: Option<Vec<Statement>>,
     },
     For {
-        var: String,
+        label: Option<Ident>,
+        var: Ident,
         iterable: Expression,
         body: Vec<Statement>,
     },
+    While {
+        label: Option<Ident>,
+        condition: Expression,
+        body: Vec<Statement>,
+    },
+    /// `break`, leaving the innermost loop or the one named by `label`.
+    Break {
+        label: Option<Ident>,
+    },
+    /// `continue`, starting the next iteration of the innermost loop or the
+    /// one named by `label`.
+    Continue {
+        label: Option<Ident>,
+    },
+    /// `match subject` with one arm per indented line below it.
+    Match {
+        subject: Expression,
+        arms: Vec<MatchArm>,
+    },
     Print {
         args: Vec<Expression>,
     },
@@ -43,85 +111,372 @@ pub enum Statement {
         value: Option<Expression>,
     },
     Expression(Expression),
+    Comment(Comment),
+    /// Placeholder for a statement the parser could not read. Only present in
+    /// programs returned alongside diagnostics.
+    Error,
+}
+
+/// A name written in the source, such as a declared variable or parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+impl Ident {
+    pub fn new(name: impl Into<String>, span: Span) -> Self {
+        Self {
+            name: name.into(),
+            span,
+        }
+    }
+}
+
+impl fmt::Display for Ident {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// An `else if` branch of an `if` statement. `elif` is accepted as a
+/// shorthand and formatted as `else if`.
+#[derive(Debug, Clone)]
+pub struct ElseIf {
+    pub condition: Expression,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+/// A field of a record type, with the comments written above it.
+#[derive(Debug, Clone)]
+pub struct FieldDecl {
+    pub name: Ident,
+    pub ty: TypeExpr,
+    pub comments: Vec<Statement>,
+    pub span: Span,
+}
+
+/// A function parameter and its annotated type, if any.
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: Option<TypeExpr>,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.ty {
+            Some(ty) => write!(f, "{}:{}", self.name, ty),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A variant of an enum, with the types of its fields and the comments
+/// written above it.
+#[derive(Debug, Clone)]
+pub struct VariantDecl {
+    pub name: Ident,
+    pub fields: Vec<TypeExpr>,
+    pub comments: Vec<Statement>,
+    pub span: Span,
+}
+
+/// A type as written in the source: `int`, `Point`, `List[int]`,
+/// `(int, bool)` or `int?`.
+#[derive(Debug, Clone)]
+pub struct TypeExpr {
+    pub kind: TypeExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeExprKind {
+    /// A type name with optional arguments in brackets.
+    Named { name: Ident, args: Vec<TypeExpr> },
+    Tuple(Vec<TypeExpr>),
+    /// `T?`, a value of type `T` or `none`.
+    Optional(Box<TypeExpr>),
+}
+
+impl TypeExpr {
+    pub fn new(kind: TypeExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    pub fn format(&self) -> String {
+        let join = |types: &[TypeExpr]| {
+            types
+                .iter()
+                .map(|t| t.format())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match &self.kind {
+            TypeExprKind::Named { name, args } if args.is_empty() => name.to_string(),
+            TypeExprKind::Named { name, args } => format!("{}[{}]", name, join(args)),
+            TypeExprKind::Tuple(items) => format!("({})", join(items)),
+            TypeExprKind::Optional(inner) => format!("{}?", inner.format()),
+        }
+    }
+}
+
+impl fmt::Display for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format())
+    }
+}
+
+/// One arm of a `match`: a pattern, an optional `if` guard, and the block
+/// that runs when both succeed.
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>,
+    pub body: Vec<Statement>,
+    pub span: Span,
+}
+
+/// A source comment kept in the tree so the formatter can reproduce it.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    /// Text after the `#`/`##` marker.
+    pub text: String,
+    /// True for `##` doc comments.
+    pub doc: bool,
+    /// True for a comment written after code, which stays at the end of the
+    /// line before it.
+    pub trailing: bool,
 }
 
 impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
     pub fn format(&self, indent: usize) -> String {
         let tabs = "\t".repeat(indent);
-        match self {
-            Statement::Let { name, value } => {
-                format!("{}let {}  {}", tabs, name, value.format())
-            }
-            Statement::Function { name, params, body } => {
-                let params_str = params.join("  ");
-                let body_str = body
-                    .iter()
-                    .map(|s| s.format(indent + 1))
-                    .collect::<Vec<_>>()
-                    .join("\n");
-                format!("{}func {} {}\n{}", tabs, name, params_str, body_str)
-            }
-            Statement::If {
+        match &self.kind {
+            StatementKind::Let {
+                name,
+                mutable,
+                ty,
+                value,
+            } => {
+                let keyword = if *mutable { "let mut" } else { "let" };
+                let ty = ty.as_ref().map(|ty| format!(":{}", ty)).unwrap_or_default();
+                format!("{}{} {}{}  {}", tabs, keyword, name, ty, value.format())
+            }
+            StatementKind::Assign {
+                target,
+                fields,
+                op,
+                value,
+            } => {
+                let mut place = target.to_string();
+                for field in fields {
+                    place.push_str(&format!(".{}", field));
+                }
+                let op = op.map(|op| op.format()).unwrap_or_default();
+                format!("{}{} {}= {}", tabs, place, op, value.format())
+            }
+            StatementKind::Type {
+                name,
+                params,
+                fields,
+            } => {
+                let mut result = format!("{}type {}{}", tabs, name, format_type_params(params));
+                for field in fields {
+                    result.push_str(&format_lines(&field.comments, indent + 1));
+                    result.push_str(&format!("\n{}\t{}:{}", tabs, field.name, field.ty));
+                }
+                result
+            }
+            StatementKind::Enum {
+                name,
+                params,
+                variants,
+            } => {
+                let mut result = format!("{}enum {}{}", tabs, name, format_type_params(params));
+                for variant in variants {
+                    result.push_str(&format_lines(&variant.comments, indent + 1));
+                    result.push_str(&format!("\n{}\t{}", tabs, variant.name));
+                    if !variant.fields.is_empty() {
+                        let fields: Vec<String> =
+                            variant.fields.iter().map(|f| f.format()).collect();
+                        result.push_str(&format!(" {}", fields.join("  ")));
+                    }
+                }
+                result
+            }
+            StatementKind::Function {
+                name,
+                params,
+                return_type,
+                body,
+            } => {
+                let mut header = format!("{}func {}", tabs, name);
+                if !params.is_empty() {
+                    header.push(' ');
+                    let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                    header.push_str(&params.join("  "));
+                }
+                if let Some(return_type) = return_type {
+                    header.push_str(&format!(" -> {}", return_type));
+                }
+                format!("{}{}", header, format_lines(body, indent + 1))
+            }
+            StatementKind::If {
                 condition,
                 then_body,
+                else_ifs,
                 else_body,
             } => {
-                let mut result = format!("{}if {}\n", tabs, condition.format());
-                result.push_str(
-                    &then_body
-                        .iter()
-                        .map(|s| s.format(indent + 1))
-                        .collect::<Vec<_>>()
-                        .join("\n"),
-                );
+                let mut result = format!("{}if {}", tabs, condition.format());
+                result.push_str(&format_lines(then_body, indent + 1));
+                for else_if in else_ifs {
+                    let condition = else_if.condition.format();
+                    result.push_str(&format!("\n{}else if {}", tabs, condition));
+                    result.push_str(&format_lines(&else_if.body, indent + 1));
+                }
                 if let Some(else_body) = else_body {
-                    result.push_str(&format!("\n{}else\n", tabs));
-                    result.push_str(
-                        &else_body
-                            .iter()
-                            .map(|s| s.format(indent + 1))
-                            .collect::<Vec<_>>()
-                            .join("\n"),
-                    );
+                    result.push_str(&format!("\n{}else", tabs));
+                    result.push_str(&format_lines(else_body, indent + 1));
                 }
                 result
             }
-            Statement::For { var, iterable, body } => {
-                let mut result = format!("{}for {} in {}\n", tabs, var, iterable.format());
-                result.push_str(
-                    &body
-                        .iter()
-                        .map(|s| s.format(indent + 1))
-                        .collect::<Vec<_>>()
-                        .join("\n"),
+            StatementKind::For {
+                label,
+                var,
+                iterable,
+                body,
+            } => {
+                let header = format!(
+                    "{}{}for {} in {}",
+                    tabs,
+                    format_label(label),
+                    var,
+                    iterable.format()
                 );
+                format!("{}{}", header, format_lines(body, indent + 1))
+            }
+            StatementKind::While {
+                label,
+                condition,
+                body,
+            } => {
+                let header =
+                    format!("{}{}while {}", tabs, format_label(label), condition.format());
+                format!("{}{}", header, format_lines(body, indent + 1))
+            }
+            StatementKind::Break { label: Some(label) } => format!("{}break {}", tabs, label),
+            StatementKind::Break { label: None } => format!("{}break", tabs),
+            StatementKind::Continue { label: Some(label) } => {
+                format!("{}continue {}", tabs, label)
+            }
+            StatementKind::Continue { label: None } => format!("{}continue", tabs),
+            StatementKind::Match { subject, arms } => {
+                let mut result = format!("{}match {}", tabs, subject.format());
+                for arm in arms {
+                    result.push_str(&format!("\n{}\t{}", tabs, arm.pattern.format()));
+                    if let Some(guard) = &arm.guard {
+                        result.push_str(&format!(" if {}", guard.format()));
+                    }
+                    result.push_str(&format_lines(&arm.body, indent + 2));
+                }
                 result
             }
-            Statement::Print { args } => {
-                let args_str = args
-                    .iter()
-                    .map(|e| e.format())
-                    .collect::<Vec<_>>()
-                    .join("  ");
-                format!("{}print {}", tabs, args_str)
+            StatementKind::Print { args } if args.is_empty() => format!("{}print", tabs),
+            StatementKind::Print { args } => {
+                format!("{}print {}", tabs, Expression::format_sequence(args))
             }
-            Statement::Return { value } => {
+            StatementKind::Return { value } => {
                 if let Some(v) = value {
                     format!("{}return {}", tabs, v.format())
                 } else {
                     format!("{}return", tabs)
                 }
             }
-            Statement::Expression(expr) => format!("{}{}", tabs, expr.format()),
+            StatementKind::Expression(expr) => format!("{}{}", tabs, expr.format()),
+            StatementKind::Comment(comment) => {
+                let marker = if comment.doc { "##" } else { "#" };
+                format!("{}{}{}", tabs, marker, comment.text)
+            }
+            StatementKind::Error => format!("{}<error>", tabs),
         }
     }
 }
 
+/// Formats `statements` as lines of a block, each starting with a line
+/// break, except that a trailing comment ends the line before it.
+fn format_lines(statements: &[Statement], indent: usize) -> String {
+    let mut output = String::new();
+    for stmt in statements {
+        match &stmt.kind {
+            StatementKind::Comment(comment) if comment.trailing => {
+                let marker = if comment.doc { "##" } else { "#" };
+                output.push_str(&format!("  {}{}", marker, comment.text));
+            }
+            _ => output.push_str(&format!("\n{}", stmt.format(indent))),
+        }
+    }
+    output
+}
+
+fn format_label(label: &Option<Ident>) -> String {
+    label
+        .as_ref()
+        .map(|label| format!("{}: ", label))
+        .unwrap_or_default()
+}
+
+/// Formats the parameters of a generic type as `[A, B]`, or nothing.
+fn format_type_params(params: &[Ident]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+    format!("[{}]", params.join(", "))
+}
+
+#[derive(Debug, Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
-pub enum Expression {
-    Number(i64),
-    Float(f64),
-    String(String),
+pub enum ExpressionKind {
+    /// Integer literal. `raw` keeps the spelling (`0xff`, `1_000u8`) for
+    /// the formatter.
+    Number {
+        value: i64,
+        suffix: Option<NumberSuffix>,
+        raw: String,
+    },
+    Float {
+        value: f64,
+        suffix: Option<NumberSuffix>,
+        raw: String,
+    },
+    Bool(bool),
+    None,
+    /// Character literal; `raw` is the literal as written, quotes included.
+    Char {
+        value: char,
+        raw: String,
+    },
+    /// String literal; `raw` is the literal as written, quotes included.
+    String {
+        value: String,
+        raw: String,
+    },
+    /// String literal with `{expr}` interpolations.
+    Interpolated {
+        parts: Vec<InterpolatedPart>,
+        raw: String,
+    },
     Variable(String),
     Binary {
         left: Box<Expression>,
@@ -133,32 +488,398 @@ pub enum Expression {
         expr: Box<Expression>,
     },
     Call {
-        name: String,
+        name: Ident,
+        args: Vec<Expression>,
+    },
+    /// `start..end` or `start..=end`, optionally with ` step n`.
+    Range {
+        start: Box<Expression>,
+        end: Box<Expression>,
+        inclusive: bool,
+        step: Option<Box<Expression>>,
+    },
+    /// `if condition then a else b`, choosing one of two values.
+    Conditional {
+        condition: Box<Expression>,
+        then_value: Box<Expression>,
+        else_value: Box<Expression>,
+    },
+    /// Items separated by two spaces outside an argument list: `1  "one"`.
+    Tuple(Vec<Expression>),
+    /// `[1  2  3]`.
+    List(Vec<Expression>),
+    /// `{"one": 1  "two": 2}`, as key and value pairs.
+    Map(Vec<(Expression, Expression)>),
+    /// `target[index]`: an item of a list or string, or a map value.
+    Index {
+        target: Box<Expression>,
+        index: Box<Expression>,
+    },
+    /// `target[start..end]`, where either bound may be left out.
+    Slice {
+        target: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+        inclusive: bool,
+    },
+    /// An enum variant, built like a call: `Empty` or `Rect 2.0  3.0`.
+    Variant {
+        name: Ident,
         args: Vec<Expression>,
     },
+    /// A record built from its type name and every field:
+    /// `Point x: 1  y: 2`.
+    Record {
+        name: Ident,
+        fields: Vec<(Ident, Expression)>,
+    },
+    /// `target.field`; tuple items are numbered from zero: `pair.0`.
+    Field {
+        target: Box<Expression>,
+        field: Ident,
+    },
+    /// Placeholder for an expression the parser could not read.
+    Error,
+}
+
+/// A piece of an interpolated string.
+#[derive(Debug, Clone)]
+pub enum InterpolatedPart {
+    /// Text with its escapes expanded.
+    Literal(String),
+    /// An interpolated `{expr}`.
+    Expression(Expression),
 }
 
 impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
     pub fn format(&self) -> String {
-        match self {
-            Expression::Number(n) => n.to_string(),
-            Expression::Float(f) => f.to_string(),
-            Expression::String(s) => format!("\"{}\"", s),
-            Expression::Variable(v) => v.clone(),
-            Expression::Binary { left, op, right } => {
-                format!("{} {} {}", left.format(), op.format(), right.format())
+        match &self.kind {
+            ExpressionKind::Number { raw, .. } | ExpressionKind::Float { raw, .. } => raw.clone(),
+            ExpressionKind::Bool(value) => value.to_string(),
+            ExpressionKind::None => "none".to_string(),
+            ExpressionKind::Char { raw, .. }
+            | ExpressionKind::String { raw, .. }
+            | ExpressionKind::Interpolated { raw, .. } => raw.clone(),
+            ExpressionKind::Variable(v) => v.clone(),
+            ExpressionKind::Binary { left, op, right } => {
+                let left_str = left.format_operand(op.precedence(), false);
+                let right_str = right.format_operand(op.precedence(), true);
+                format!("{} {} {}", left_str, op.format(), right_str)
+            }
+            ExpressionKind::Unary { op, expr } => match &expr.kind {
+                ExpressionKind::Binary { .. }
+                | ExpressionKind::Range { .. }
+                | ExpressionKind::Conditional { .. }
+                | ExpressionKind::Tuple(_) => format!("{}({})", op.format(), expr.format()),
+                _ => format!("{}{}", op.format(), expr.format()),
+            },
+            ExpressionKind::Range {
+                start,
+                end,
+                inclusive,
+                step,
+            } => {
+                // A start ending in an open call would take the range as its
+                // argument.
+                let start = match &start.kind {
+                    ExpressionKind::Range { .. }
+                    | ExpressionKind::Conditional { .. }
+                    | ExpressionKind::Tuple(_) => format!("({})", start.format()),
+                    _ if start.open_calls() > 0 => format!("({})", start.format()),
+                    _ => start.format(),
+                };
+                let end = match &end.kind {
+                    ExpressionKind::Range { .. }
+                    | ExpressionKind::Conditional { .. }
+                    | ExpressionKind::Tuple(_) => format!("({})", end.format()),
+                    _ => end.format(),
+                };
+                let operator = if *inclusive { "..=" } else { ".." };
+                match step {
+                    Some(step) => format!("{}{}{} step {}", start, operator, end, step.format()),
+                    None => format!("{}{}{}", start, operator, end),
+                }
+            }
+            ExpressionKind::Call { name, args } => {
+                format!("{} {}", name, Expression::format_sequence(args))
+            }
+            ExpressionKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                // The else value reaches to the end of the expression, so a
+                // conditional anywhere else needs parentheses.
+                let inner = |expr: &Expression| match &expr.kind {
+                    ExpressionKind::Conditional { .. } | ExpressionKind::Tuple(_) => {
+                        format!("({})", expr.format())
+                    }
+                    _ => expr.format(),
+                };
+                let else_value = match &else_value.kind {
+                    ExpressionKind::Tuple(_) => format!("({})", else_value.format()),
+                    _ => else_value.format(),
+                };
+                format!(
+                    "if {} then {} else {}",
+                    inner(condition),
+                    inner(then_value),
+                    else_value
+                )
+            }
+            ExpressionKind::Tuple(items) => Expression::format_sequence(items),
+            ExpressionKind::List(items) => format!("[{}]", Expression::format_sequence(items)),
+            ExpressionKind::Map(entries) => {
+                let mut output = String::new();
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        output.push_str(&" ".repeat(2 + entries[i - 1].1.open_calls()));
+                    }
+                    let key = match &key.kind {
+                        ExpressionKind::Tuple(_) => format!("({})", key.format()),
+                        _ => key.format(),
+                    };
+                    let value = match &value.kind {
+                        ExpressionKind::Tuple(_) => format!("({})", value.format()),
+                        _ => value.format(),
+                    };
+                    output.push_str(&format!("{}: {}", key, value));
+                }
+                format!("{{{}}}", output)
+            }
+            ExpressionKind::Index { target, index } => {
+                let index = match &index.kind {
+                    ExpressionKind::Tuple(_) => format!("({})", index.format()),
+                    _ => index.format(),
+                };
+                format!("{}[{}]", target.format_postfix_target(), index)
+            }
+            ExpressionKind::Slice {
+                target,
+                start,
+                end,
+                inclusive,
+            } => {
+                // The bounds are read like those of a range.
+                let bound = |bound: &Option<Box<Expression>>, is_start: bool| match bound {
+                    Some(b) => match &b.kind {
+                        ExpressionKind::Range { .. }
+                        | ExpressionKind::Conditional { .. }
+                        | ExpressionKind::Tuple(_) => format!("({})", b.format()),
+                        _ if is_start && b.open_calls() > 0 => format!("({})", b.format()),
+                        _ => b.format(),
+                    },
+                    None => String::new(),
+                };
+                let operator = if *inclusive { "..=" } else { ".." };
+                format!(
+                    "{}[{}{}{}]",
+                    target.format_postfix_target(),
+                    bound(start, true),
+                    operator,
+                    bound(end, false)
+                )
+            }
+            ExpressionKind::Variant { name, args } if args.is_empty() => name.to_string(),
+            ExpressionKind::Variant { name, args } => {
+                format!("{} {}", name, Expression::format_sequence(args))
+            }
+            ExpressionKind::Record { name, fields } => {
+                let mut output = format!("{} ", name);
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        output.push_str(&" ".repeat(2 + fields[i - 1].1.open_calls()));
+                    }
+                    let value = match &value.kind {
+                        ExpressionKind::Tuple(_) => format!("({})", value.format()),
+                        _ => value.format(),
+                    };
+                    output.push_str(&format!("{}: {}", field, value));
+                }
+                output
+            }
+            ExpressionKind::Field { target, field } => {
+                format!("{}.{}", target.format_postfix_target(), field)
+            }
+            ExpressionKind::Error => "<error>".to_string(),
+        }
+    }
+
+    /// Formats items separated the way the parser reads them back: two
+    /// spaces, plus one for every call left open at the end of the item.
+    pub fn format_sequence(items: &[Expression]) -> String {
+        let mut output = String::new();
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                output.push_str(&" ".repeat(2 + items[i - 1].open_calls()));
+            }
+            match &item.kind {
+                ExpressionKind::Tuple(_) => output.push_str(&format!("({})", item.format())),
+                _ => output.push_str(&item.format()),
+            }
+        }
+        output
+    }
+
+    /// Number of calls whose argument lists are still open at the end of
+    /// this expression's formatted text, as in `f x  g y`.
+    fn open_calls(&self) -> usize {
+        match &self.kind {
+            ExpressionKind::Call { args, .. } => 1 + args.last().map_or(0, |a| a.open_calls()),
+            ExpressionKind::Variant { args, .. } if !args.is_empty() => {
+                1 + args.last().map_or(0, |a| a.open_calls())
+            }
+            ExpressionKind::Record { fields, .. } => {
+                1 + fields.last().map_or(0, |(_, value)| value.open_calls())
+            }
+            ExpressionKind::Binary { right, .. } => right.open_calls(),
+            ExpressionKind::Range { end, step, .. } => {
+                step.as_ref().map_or_else(|| end.open_calls(), |step| step.open_calls())
+            }
+            ExpressionKind::Conditional { else_value, .. } => else_value.open_calls(),
+            ExpressionKind::Unary { expr, .. } => match &expr.kind {
+                ExpressionKind::Binary { .. }
+                | ExpressionKind::Range { .. }
+                | ExpressionKind::Conditional { .. }
+                | ExpressionKind::Tuple(_) => 0,
+                _ => expr.open_calls(),
+            },
+            _ => 0,
+        }
+    }
+
+    /// Formats the expression before `[index]` or `.field`, which must be a
+    /// single operand; a number needs parentheses so that `(1).0` does not
+    /// read as a float.
+    fn format_postfix_target(&self) -> String {
+        match &self.kind {
+            ExpressionKind::Variable(_)
+            | ExpressionKind::Bool(_)
+            | ExpressionKind::None
+            | ExpressionKind::Char { .. }
+            | ExpressionKind::String { .. }
+            | ExpressionKind::Interpolated { .. }
+            | ExpressionKind::List(_)
+            | ExpressionKind::Map(_)
+            | ExpressionKind::Index { .. }
+            | ExpressionKind::Slice { .. }
+            | ExpressionKind::Field { .. } => self.format(),
+            _ => format!("({})", self.format()),
+        }
+    }
+
+    /// Formats a binary operand, parenthesizing it when it binds looser than
+    /// the surrounding operator, or when it is a left operand ending in a
+    /// call that would otherwise swallow the operator as an argument.
+    fn format_operand(&self, parent_precedence: u8, is_right: bool) -> String {
+        match &self.kind {
+            ExpressionKind::Binary { op, .. }
+                if op.precedence() < parent_precedence
+                    || (is_right && op.precedence() == parent_precedence) =>
+            {
+                format!("({})", self.format())
+            }
+            ExpressionKind::Range { .. }
+            | ExpressionKind::Conditional { .. }
+            | ExpressionKind::Tuple(_) => format!("({})", self.format()),
+            _ if !is_right && self.open_calls() > 0 => format!("({})", self.format()),
+            _ => self.format(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKind {
+    /// `_`, matching anything without binding it.
+    Wildcard,
+    /// A lowercase name, matching anything and binding it.
+    Binding(String),
+    /// A number, string, character, `true`, `false` or `none`, including
+    /// negative numbers such as `-1`.
+    Literal(Expression),
+    /// `start..end` or `start..=end` over integer or character literals.
+    Range {
+        start: Expression,
+        end: Expression,
+        inclusive: bool,
+    },
+    /// Items separated by two spaces: `0  _`, or `(x  y)` when nested.
+    Tuple(Vec<Pattern>),
+    /// A capitalised name with optional fields: `Rect w  h`.
+    Variant {
+        name: Ident,
+        fields: Vec<Pattern>,
+    },
+}
+
+impl Pattern {
+    pub fn new(kind: PatternKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    pub fn format(&self) -> String {
+        match &self.kind {
+            PatternKind::Tuple(items) => Pattern::format_items(items),
+            _ => self.format_nested(),
+        }
+    }
+
+    /// Formats the pattern as an item of a tuple or variant, where tuples
+    /// need parentheses.
+    fn format_nested(&self) -> String {
+        match &self.kind {
+            PatternKind::Wildcard => "_".to_string(),
+            PatternKind::Binding(name) => name.clone(),
+            PatternKind::Literal(expr) => expr.format(),
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let operator = if *inclusive { "..=" } else { ".." };
+                format!("{}{}{}", start.format(), operator, end.format())
             }
-            Expression::Unary { op, expr } => format!("{}{}", op.format(), expr.format()),
-            Expression::Call { name, args } => {
-                let args_str = args
-                    .iter()
-                    .map(|e| e.format())
-                    .collect::<Vec<_>>()
-                    .join("  ");
-                format!("{} {}", name, args_str)
+            PatternKind::Tuple(items) => format!("({})", Pattern::format_items(items)),
+            PatternKind::Variant { name, fields } if fields.is_empty() => name.to_string(),
+            PatternKind::Variant { name, fields } => {
+                format!("{} {}", name, Pattern::format_items(fields))
             }
         }
     }
+
+    /// Formats items separated by two spaces. A variant with fields takes
+    /// every item after it, so one that is not last is parenthesised.
+    fn format_items(items: &[Pattern]) -> String {
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| match &item.kind {
+                PatternKind::Variant { fields, .. }
+                    if !fields.is_empty() && i + 1 < items.len() =>
+                {
+                    format!("({})", item.format_nested())
+                }
+                _ => item.format_nested(),
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format())
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -169,18 +890,42 @@ pub enum BinaryOp {
     Divide,
     Greater,
     Less,
+    GreaterEqual,
+    LessEqual,
     Equal,
+    NotEqual,
+    And,
+    Or,
 }
 
 impl BinaryOp {
     pub fn precedence(&self) -> u8 {
         match self {
-            BinaryOp::Add | BinaryOp::Subtract => 1,
-            BinaryOp::Multiply | BinaryOp::Divide => 2,
-            BinaryOp::Greater | BinaryOp::Less | BinaryOp::Equal => 0,
+            BinaryOp::Or => 0,
+            BinaryOp::And => 1,
+            BinaryOp::Greater
+            | BinaryOp::Less
+            | BinaryOp::GreaterEqual
+            | BinaryOp::LessEqual
+            | BinaryOp::Equal
+            | BinaryOp::NotEqual => 2,
+            BinaryOp::Add | BinaryOp::Subtract => 3,
+            BinaryOp::Multiply | BinaryOp::Divide => 4,
         }
     }
 
+    pub fn is_comparison(&self) -> bool {
+        matches!(
+            self,
+            BinaryOp::Greater
+                | BinaryOp::Less
+                | BinaryOp::GreaterEqual
+                | BinaryOp::LessEqual
+                | BinaryOp::Equal
+                | BinaryOp::NotEqual
+        )
+    }
+
     pub fn format(&self) -> String {
         match self {
             BinaryOp::Add => "+".to_string(),
@@ -189,7 +934,12 @@ impl BinaryOp {
             BinaryOp::Divide => "/".to_string(),
             BinaryOp::Greater => ">".to_string(),
             BinaryOp::Less => "<".to_string(),
+            BinaryOp::GreaterEqual => ">=".to_string(),
+            BinaryOp::LessEqual => "<=".to_string(),
             BinaryOp::Equal => "==".to_string(),
+            BinaryOp::NotEqual => "!=".to_string(),
+            BinaryOp::And => "&&".to_string(),
+            BinaryOp::Or => "||".to_string(),
         }
     }
 }
@@ -197,13 +947,75 @@ impl BinaryOp {
 #[derive(Debug, Clone, Copy)]
 pub enum UnaryOp {
     Negate,
+    Not,
 }
 
 impl UnaryOp {
     pub fn format(&self) -> String {
         match self {
             UnaryOp::Negate => "-".to_string(),
+            UnaryOp::Not => "!".to_string(),
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn format(source: &str) -> String {
+        let tokens = Lexer::new().tokenize(source).expect("source should lex");
+        let program = Parser::new().parse(tokens).expect("source should parse");
+        program.format()
+    }
+
+    /// Formats `source`, checks the result, then checks that formatting it
+    /// again changes nothing.
+    fn assert_round_trip(source: &str, expected: &str) {
+        let formatted = format(source);
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    fn keeps_comments_on_their_own_lines() {
+        let source = "# header\n## Adds two numbers.\nfunc add a  b\n\t# inside\n\treturn a + b\n";
+        assert_round_trip(source, source);
+    }
+
+    #[test]
+    fn keeps_trailing_comments_on_their_line() {
+        let source = "let x  1  # trailing\n\
+                      func add a  b\n\
+                      \treturn a + b  # plain comment\n\
+                      print add x  2\n";
+        assert_round_trip(source, source);
+    }
+
+    #[test]
+    fn keeps_trailing_comments_on_block_headers() {
+        let source = "if x > 0  # positive\n\
+                      \tprint x\n\
+                      else  # the rest\n\
+                      \tprint 0  # zero\n\
+                      match x\n\
+                      \t1  # one\n\
+                      \t\tprint 1\n\
+                      \t_\n\
+                      \t\tprint 2\n";
+        assert_round_trip(source, source);
+    }
+
+    #[test]
+    fn keeps_trailing_comments_on_fields() {
+        let source = "type Point  # a point\n\tx:int  # across\n\ty:int\n";
+        assert_round_trip(source, source);
+    }
+
+    #[test]
+    fn normalizes_spacing_before_trailing_comments() {
+        assert_round_trip("let x  1     # spaced\n", "let x  1  # spaced\n");
+    }
+}
@@ -0,0 +1,236 @@
+//! Renaming of shadowed names for the backends.
+//!
+//! A `let` may declare a name that is already in scope, and the new variable
+//! hides the old one until the end of its block. C cannot declare a name
+//! twice in one block, and WASM has a single set of locals for all of
+//! `main`, so both would mix the two variables up. Each declaration that
+//! shadows a visible name is given a name of its own instead, `x__1` for the
+//! first one to shadow `x`, and the uses it reaches are renamed with it.
+
+use super::*;
+use std::collections::HashMap;
+
+impl Program {
+    /// The program with every declaration that shadows a name in scope,
+    /// and the uses of it, renamed. Spans are kept, so the types that the
+    /// typechecker found still apply.
+    pub fn without_shadowing(&self) -> Program {
+        let mut renamer = Renamer::default();
+        let mut statements = self.statements.clone();
+        renamer.block(&mut statements);
+        Program { statements }
+    }
+}
+
+#[derive(Default)]
+struct Renamer {
+    /// The name that each name in scope has been given, innermost scope
+    /// last.
+    scopes: Vec<HashMap<String, String>>,
+    /// How many declarations have shadowed each name so far.
+    counts: HashMap<String, usize>,
+}
+
+impl Renamer {
+    fn block(&mut self, statements: &mut [Statement]) {
+        self.scopes.push(HashMap::new());
+        for stmt in statements {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    /// The name that `name` has been given where it is used.
+    fn lookup(&self, name: &str) -> Option<&String> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Declares `name` in the innermost scope, renaming it when it shadows
+    /// a name in scope.
+    fn declare(&mut self, name: &mut String) {
+        let original = name.clone();
+        if self.lookup(name).is_some() {
+            let count = self.counts.entry(original.clone()).or_default();
+            *count += 1;
+            *name = format!("{}__{}", original, count);
+        }
+        let scope = self.scopes.last_mut().expect("a scope is open");
+        scope.insert(original, name.clone());
+    }
+
+    fn rename(&self, name: &mut String) {
+        if let Some(renamed) = self.lookup(name) {
+            *name = renamed.clone();
+        }
+    }
+
+    fn statement(&mut self, stmt: &mut Statement) {
+        match &mut stmt.kind {
+            // The value is computed before the new variable exists.
+            StatementKind::Let { name, value, .. } => {
+                self.expression(value);
+                self.declare(&mut name.name);
+            }
+            StatementKind::Assign { target, value, .. } => {
+                self.expression(value);
+                self.rename(&mut target.name);
+            }
+            StatementKind::Print { args } => {
+                for arg in args {
+                    self.expression(arg);
+                }
+            }
+            StatementKind::Function { params, body, .. } => {
+                self.scopes.push(HashMap::new());
+                for param in params {
+                    self.declare(&mut param.name.name);
+                }
+                self.block(body);
+                self.scopes.pop();
+            }
+            StatementKind::If {
+                condition,
+                then_body,
+                else_ifs,
+                else_body,
+            } => {
+                self.expression(condition);
+                self.block(then_body);
+                for else_if in else_ifs {
+                    self.expression(&mut else_if.condition);
+                    self.block(&mut else_if.body);
+                }
+                if let Some(else_body) = else_body {
+                    self.block(else_body);
+                }
+            }
+            StatementKind::While {
+                condition, body, ..
+            } => {
+                self.expression(condition);
+                self.block(body);
+            }
+            StatementKind::For {
+                var,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expression(iterable);
+                self.scopes.push(HashMap::new());
+                self.declare(&mut var.name);
+                self.block(body);
+                self.scopes.pop();
+            }
+            StatementKind::Match { subject, arms } => {
+                self.expression(subject);
+                for arm in arms {
+                    self.scopes.push(HashMap::new());
+                    self.pattern(&mut arm.pattern);
+                    if let Some(guard) = &mut arm.guard {
+                        self.expression(guard);
+                    }
+                    self.block(&mut arm.body);
+                    self.scopes.pop();
+                }
+            }
+            StatementKind::Return { value: Some(value) } | StatementKind::Expression(value) => {
+                self.expression(value)
+            }
+            StatementKind::Type { .. }
+            | StatementKind::Enum { .. }
+            | StatementKind::Return { value: None }
+            | StatementKind::Break { .. }
+            | StatementKind::Continue { .. }
+            | StatementKind::Comment(_)
+            | StatementKind::Error => {}
+        }
+    }
+
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        match &mut pattern.kind {
+            PatternKind::Binding(name) => self.declare(name),
+            PatternKind::Tuple(items) | PatternKind::Variant { fields: items, .. } => {
+                for item in items {
+                    self.pattern(item);
+                }
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expression) {
+        match &mut expr.kind {
+            ExpressionKind::Variable(name) => self.rename(name),
+            ExpressionKind::Interpolated { parts, .. } => {
+                for part in parts {
+                    if let InterpolatedPart::Expression(part) = part {
+                        self.expression(part);
+                    }
+                }
+            }
+            ExpressionKind::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            ExpressionKind::Unary { expr, .. } => self.expression(expr),
+            ExpressionKind::Field { target, .. } => self.expression(target),
+            ExpressionKind::Range {
+                start, end, step, ..
+            } => {
+                self.expression(start);
+                self.expression(end);
+                if let Some(step) = step {
+                    self.expression(step);
+                }
+            }
+            ExpressionKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                self.expression(condition);
+                self.expression(then_value);
+                self.expression(else_value);
+            }
+            ExpressionKind::Call { args: items, .. }
+            | ExpressionKind::Tuple(items)
+            | ExpressionKind::List(items)
+            | ExpressionKind::Variant { args: items, .. } => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            ExpressionKind::Map(entries) => {
+                for (key, value) in entries {
+                    self.expression(key);
+                    self.expression(value);
+                }
+            }
+            ExpressionKind::Record { fields, .. } => {
+                for (_, value) in fields {
+                    self.expression(value);
+                }
+            }
+            ExpressionKind::Index { target, index } => {
+                self.expression(target);
+                self.expression(index);
+            }
+            ExpressionKind::Slice {
+                target, start, end, ..
+            } => {
+                self.expression(target);
+                for bound in [start, end].into_iter().flatten() {
+                    self.expression(bound);
+                }
+            }
+            ExpressionKind::Number { .. }
+            | ExpressionKind::Float { .. }
+            | ExpressionKind::Bool(_)
+            | ExpressionKind::None
+            | ExpressionKind::Char { .. }
+            | ExpressionKind::String { .. }
+            | ExpressionKind::Error => {}
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Walks over the statements and expressions of a tree.
+
+use super::*;
+
+/// Calls `f` on each of `statements` and on the statements nested in them.
+pub fn for_each_statement<'a>(statements: &'a [Statement], f: &mut impl FnMut(&'a Statement)) {
+    for stmt in statements {
+        f(stmt);
+        match &stmt.kind {
+            StatementKind::Function { body, .. }
+            | StatementKind::For { body, .. }
+            | StatementKind::While { body, .. } => for_each_statement(body, f),
+            StatementKind::If {
+                then_body,
+                else_ifs,
+                else_body,
+                ..
+            } => {
+                for_each_statement(then_body, f);
+                for else_if in else_ifs {
+                    for_each_statement(&else_if.body, f);
+                }
+                if let Some(else_body) = else_body {
+                    for_each_statement(else_body, f);
+                }
+            }
+            StatementKind::Match { arms, .. } => {
+                for arm in arms {
+                    for_each_statement(&arm.body, f);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Calls `f` on each expression in `statements`, including those in nested
+/// statements, and on the expressions nested in them, outermost first.
+pub fn for_each_expression<'a>(statements: &'a [Statement], f: &mut impl FnMut(&'a Expression)) {
+    for_each_statement(statements, &mut |stmt| {
+        let mut exprs: Vec<&Expression> = Vec::new();
+        match &stmt.kind {
+            StatementKind::Let { value, .. } | StatementKind::Assign { value, .. } => {
+                exprs.push(value)
+            }
+            StatementKind::Print { args } => exprs.extend(args),
+            StatementKind::If {
+                condition,
+                else_ifs,
+                ..
+            } => {
+                exprs.push(condition);
+                exprs.extend(else_ifs.iter().map(|else_if| &else_if.condition));
+            }
+            StatementKind::While { condition, .. } => exprs.push(condition),
+            StatementKind::For { iterable, .. } => exprs.push(iterable),
+            StatementKind::Match { subject, arms } => {
+                exprs.push(subject);
+                exprs.extend(arms.iter().filter_map(|arm| arm.guard.as_ref()));
+            }
+            StatementKind::Return { value: Some(value) } | StatementKind::Expression(value) => {
+                exprs.push(value)
+            }
+            _ => {}
+        }
+        for expr in exprs {
+            for_each_expression_in(expr, f);
+        }
+    });
+}
+
+/// Calls `f` on `expr` and on each expression nested in it.
+fn for_each_expression_in<'a>(expr: &'a Expression, f: &mut impl FnMut(&'a Expression)) {
+    f(expr);
+    match &expr.kind {
+        ExpressionKind::Interpolated { parts, .. } => {
+            for part in parts {
+                if let InterpolatedPart::Expression(part) = part {
+                    for_each_expression_in(part, f);
+                }
+            }
+        }
+        ExpressionKind::Binary { left, right, .. } => {
+            for_each_expression_in(left, f);
+            for_each_expression_in(right, f);
+        }
+        ExpressionKind::Unary { expr, .. } => for_each_expression_in(expr, f),
+        ExpressionKind::Field { target, .. } => for_each_expression_in(target, f),
+        ExpressionKind::Range {
+            start, end, step, ..
+        } => {
+            for_each_expression_in(start, f);
+            for_each_expression_in(end, f);
+            if let Some(step) = step {
+                for_each_expression_in(step, f);
+            }
+        }
+        ExpressionKind::Conditional {
+            condition,
+            then_value,
+            else_value,
+        } => {
+            for_each_expression_in(condition, f);
+            for_each_expression_in(then_value, f);
+            for_each_expression_in(else_value, f);
+        }
+        ExpressionKind::Call { args: items, .. }
+        | ExpressionKind::Tuple(items)
+        | ExpressionKind::List(items)
+        | ExpressionKind::Variant { args: items, .. } => {
+            for item in items {
+                for_each_expression_in(item, f);
+            }
+        }
+        ExpressionKind::Map(entries) => {
+            for (key, value) in entries {
+                for_each_expression_in(key, f);
+                for_each_expression_in(value, f);
+            }
+        }
+        ExpressionKind::Record { fields, .. } => {
+            for (_, value) in fields {
+                for_each_expression_in(value, f);
+            }
+        }
+        ExpressionKind::Index { target, index } => {
+            for_each_expression_in(target, f);
+            for_each_expression_in(index, f);
+        }
+        ExpressionKind::Slice {
+            target, start, end, ..
+        } => {
+            for_each_expression_in(target, f);
+            for bound in [start, end].into_iter().flatten() {
+                for_each_expression_in(bound, f);
+            }
+        }
+        ExpressionKind::Variable(_)
+        | ExpressionKind::Number { .. }
+        | ExpressionKind::Float { .. }
+        | ExpressionKind::Bool(_)
+        | ExpressionKind::None
+        | ExpressionKind::Char { .. }
+        | ExpressionKind::String { .. }
+        | ExpressionKind::Error => {}
+    }
+}
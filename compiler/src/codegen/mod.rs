@@ -1,209 +1,2686 @@
 use crate::ast::*;
+use crate::diagnostics::{codes, Diagnostic, DiagnosticResult};
+use crate::source::Span;
+use crate::typechecker::{Type, TypeTable};
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use tabula_runtime::{Range, Value};
 
+#[derive(Clone)]
 pub struct Codegen {
     // LLVM context and module would go here
+    /// The enum of each variant and the tag that tells it apart from the
+    /// other variants of that enum.
+    variants: HashMap<String, (String, usize)>,
+    /// Generic record types and enums by name. C has no generics, so each
+    /// instance the program names, such as `Box[int]`, becomes its own type.
+    generics: HashMap<String, Statement>,
+    /// The functions the program declares, by name, with the type that the
+    /// typechecker found for them, generic over the type variables left in
+    /// it even in an instance. They take the place of built-in functions of
+    /// the same name.
+    functions: HashMap<String, Option<Type>>,
+    /// The types that each generic function is called with, as the types
+    /// its type variables stand for. Each list becomes an instance of the
+    /// function, as `first_int` for `first [1]`.
+    instances: HashMap<String, Vec<Vec<(usize, Type)>>>,
+    /// The types the typechecker found, which give variables without an
+    /// annotation their C type. Empty when the program was not checked.
+    types: TypeTable,
+}
+
+impl Default for Codegen {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Codegen {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            variants: HashMap::new(),
+            generics: HashMap::new(),
+            functions: HashMap::new(),
+            instances: HashMap::new(),
+            types: TypeTable::default(),
+        }
     }
 
-    pub fn generate_native(&self, program: &Program, output: &Path) -> Result<()> {
+    pub fn generate_native(
+        &mut self,
+        program: &Program,
+        types: &TypeTable,
+        output: &Path,
+    ) -> Result<()> {
         // TODO: Implement LLVM code generation
         // For now, generate a simple C representation
-        let mut code = String::from("#include <stdio.h>\n#include <stdlib.h>\n\n");
-        code.push_str("int main() {\n");
-        
-        for stmt in &program.statements {
-            code.push_str(&self.generate_statement_c(stmt, 1)?);
-        }
-        
-        code.push_str("  return 0;\n");
-        code.push_str("}\n");
-        
+        let code = self.generate_c(program, types)?;
         std::fs::write(output.with_extension("c"), code)?;
-        
+
         // In a real implementation, we would:
         // 1. Create LLVM module
         // 2. Generate LLVM IR
         // 3. Compile to native binary
-        
+
         Ok(())
     }
 
-    fn generate_statement_c(&self, stmt: &Statement, indent: usize) -> Result<String> {
+    /// Lowers `program`, as `Compiler::lower` leaves it, to C. The C relies
+    /// on GCC extensions: functions nest inside `main` as they nest in the
+    /// program, and statement expressions compute values that take more
+    /// than one C expression.
+    pub fn generate_c(&mut self, program: &Program, types: &TypeTable) -> Result<String> {
+        self.types = types.clone();
+        let mut code = String::from(PRELUDE_C);
+        // Top-level types come first so that any statement can use them.
+        let (types, statements): (Vec<&Statement>, Vec<&Statement>) = program
+            .statements
+            .iter()
+            .partition(|stmt| {
+                matches!(stmt.kind, StatementKind::Type { .. } | StatementKind::Enum { .. })
+            });
+        for stmt in &types {
+            if let StatementKind::Enum { name, variants, .. } = &stmt.kind {
+                for (tag, variant) in variants.iter().enumerate() {
+                    self.variants
+                        .insert(variant.name.name.clone(), (name.name.clone(), tag));
+                }
+            }
+            match &stmt.kind {
+                StatementKind::Type { name, params, .. }
+                | StatementKind::Enum { name, params, .. }
+                    if !params.is_empty() =>
+                {
+                    self.generics.insert(name.name.clone(), (*stmt).clone());
+                }
+                _ => {}
+            }
+        }
+        let mut used = Vec::new();
+        let mut functions = HashMap::new();
+        for_each_statement(&program.statements, &mut |stmt| {
+            used.extend(self.type_exprs(stmt));
+            if let StatementKind::Function { name, body, .. } = &stmt.kind {
+                functions.insert(name.name.clone(), (name.span, body.as_slice()));
+            }
+        });
+        let declared = functions
+            .iter()
+            .map(|(name, (span, _))| (name.clone(), self.types.get(*span).cloned()))
+            .collect();
+        self.functions = declared;
+        // Values such as list literals have types that no statement spells,
+        // and the instances of generic functions have types of their own.
+        let mut tables = self.collect_function_instances(&program.statements, &functions);
+        tables.push(self.types.clone());
+        let span = Span::default();
+        for table in &tables {
+            used.extend(table.all().into_iter().filter_map(|ty| type_expr(ty, span)));
+        }
+        let mut instances = Vec::new();
+        for ty in &used {
+            self.collect_instances(ty, &mut instances);
+        }
+        // An instance is declared as soon as the types it holds are.
+        let mut declared = HashSet::new();
+        code.push_str(&self.instances_c(&instances, &mut declared)?);
+        for stmt in types {
+            match &stmt.kind {
+                StatementKind::Type { name, .. } | StatementKind::Enum { name, .. }
+                    if !self.generics.contains_key(&name.name) =>
+                {
+                    code.push_str(&self.generate_statement_c(stmt, 0)?);
+                    code.push_str(&show_declaration_c(&name.name, &name.name, stmt));
+                    declared.insert(name.name.clone());
+                    code.push_str(&self.instances_c(&instances, &mut declared)?);
+                }
+                _ => {}
+            }
+        }
+        code.push_str("int main() {\n");
+        let statements: Vec<Statement> = statements.into_iter().cloned().collect();
+        code.push_str(&self.block_c(&statements, 1)?);
+        code.push_str("  return 0;\n");
+        code.push_str("}\n");
+        Ok(code)
+    }
+
+    /// Finds the types that each generic function is called with, from the
+    /// calls in `statements` and then from those in each instance found,
+    /// whose types are known once the function's are. `functions` holds the
+    /// span of the name and the body of each function. Returns the types
+    /// of the instances.
+    fn collect_function_instances(
+        &mut self,
+        statements: &[Statement],
+        functions: &HashMap<String, (Span, &[Statement])>,
+    ) -> Vec<TypeTable> {
+        let mut tables = Vec::new();
+        // The statements to look for calls in, with the types that the
+        // type variables in them stand for.
+        let mut pending = vec![(Vec::new(), statements)];
+        while let Some((context, statements)) = pending.pop() {
+            let codegen = self.instance(&context);
+            let mut calls = Vec::new();
+            for_each_call(statements, &mut |name| calls.push(name.clone()));
+            for name in calls {
+                let Some((_, body)) = functions.get(&name.name) else {
+                    continue;
+                };
+                let Some(bindings) = codegen.call_bindings(&name) else {
+                    continue;
+                };
+                let known = self.instances.entry(name.name.clone()).or_default();
+                if bindings.is_empty() || known.contains(&bindings) {
+                    continue;
+                }
+                known.push(bindings.clone());
+                let context: Vec<_> = context.iter().cloned().chain(bindings).collect();
+                tables.push(self.instance(&context).types);
+                pending.push((context, body));
+            }
+        }
+        tables
+    }
+
+    /// The types that the call by `name` gives the type variables of the
+    /// function it calls, in their order in its type. No types when the
+    /// function is not generic, and `None` when they are not all known.
+    fn call_bindings(&self, name: &Ident) -> Option<Vec<(usize, Type)>> {
+        let Some(Some(declared)) = self.functions.get(&name.name) else {
+            return Some(Vec::new());
+        };
+        let mut vars = Vec::new();
+        bind_vars(declared, declared, &mut vars);
+        if vars.is_empty() {
+            return Some(Vec::new());
+        }
+        let mut bindings = Vec::new();
+        bind_vars(declared, self.types.call(name.span)?, &mut bindings);
+        let known = bindings.len() == vars.len()
+            && bindings.iter().all(|(_, ty)| type_expr(ty, name.span).is_some());
+        known.then_some(bindings)
+    }
+
+    /// This codegen with each type variable in `bindings` standing for its
+    /// type, as in one instance of a generic function.
+    fn instance(&self, bindings: &[(usize, Type)]) -> Codegen {
+        let (vars, types): (Vec<usize>, Vec<Type>) = bindings.iter().cloned().unzip();
+        Codegen {
+            types: self.types.substitute(&vars, &types),
+            ..self.clone()
+        }
+    }
+
+    /// Calls `f` with each version of the function `name` to emit, and the
+    /// codegen to emit it with: the function itself or, when it is generic,
+    /// an instance for each list of types that it is called with.
+    fn for_each_version(
+        &self,
+        name: &Ident,
+        f: &mut impl FnMut(&Codegen, &Ident) -> Result<()>,
+    ) -> Result<()> {
+        let mut vars = Vec::new();
+        if let Some(declared) = self.types.get(name.span) {
+            bind_vars(declared, declared, &mut vars);
+        }
+        if vars.is_empty() {
+            return f(self, name);
+        }
+        for bindings in self.instances.get(&name.name).into_iter().flatten() {
+            let instance_name = Ident::new(instance_name(&name.name, bindings), name.span);
+            f(&self.instance(bindings), &instance_name)?;
+        }
+        Ok(())
+    }
+
+    /// Declares each of `instances` that is not declared yet once the types
+    /// it holds are, until no more can be. `declared` holds the C names of
+    /// the types declared so far.
+    fn instances_c(
+        &self,
+        instances: &[TypeExpr],
+        declared: &mut HashSet<String>,
+    ) -> Result<String> {
+        let mut code = String::new();
+        loop {
+            let ready = instances.iter().find(|ty| {
+                !declared.contains(&mangle(ty))
+                    && self
+                        .parts(ty)
+                        .iter()
+                        .all(|part| !is_struct(part) || declared.contains(&c_type(part)))
+            });
+            let Some(ty) = ready else {
+                return Ok(code);
+            };
+            match &ty.kind {
+                TypeExprKind::Optional(inner) => code.push_str(&format!(
+                    "typedef struct {{ bool some; {} value; }} {};\n",
+                    c_type(inner),
+                    mangle(ty)
+                )),
+                TypeExprKind::Named { name, args } if name.name == "List" => {
+                    code.push_str(&format!(
+                        "typedef struct {{ {}* items; int len; }} {};\n",
+                        c_type(&args[0]),
+                        mangle(ty)
+                    ))
+                }
+                TypeExprKind::Tuple(items) => {
+                    let fields: String = items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, item)| format!("{} _{}; ", c_type(item), i))
+                        .collect();
+                    code.push_str(&format!("typedef struct {{ {}}} {};\n", fields, mangle(ty)));
+                }
+                TypeExprKind::Named { name, .. } => {
+                    if let Some(instance) = self.instantiate(ty, &name.name) {
+                        code.push_str(&self.generate_statement_c(&instance, 0)?);
+                        code.push_str(&show_declaration_c(&mangle(ty), &name.name, &instance));
+                    }
+                }
+            }
+            if !matches!(&ty.kind, TypeExprKind::Named { name, .. } if name.name != "List") {
+                code.push_str(&show_instance_c(ty));
+            }
+            declared.insert(mangle(ty));
+        }
+    }
+
+    /// The types that an instance holds, which C needs declared before it.
+    fn parts(&self, ty: &TypeExpr) -> Vec<TypeExpr> {
+        match &ty.kind {
+            TypeExprKind::Optional(inner) => vec![(**inner).clone()],
+            TypeExprKind::Tuple(items) => items.clone(),
+            TypeExprKind::Named { name, args } if name.name == "List" => args.clone(),
+            TypeExprKind::Named { name, .. } => match self.instantiate(ty, &name.name) {
+                Some(Statement {
+                    kind: StatementKind::Type { fields, .. },
+                    ..
+                }) => fields.into_iter().map(|field| field.ty).collect(),
+                Some(Statement {
+                    kind: StatementKind::Enum { variants, .. },
+                    ..
+                }) => variants.into_iter().flat_map(|variant| variant.fields).collect(),
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    /// Emits a block of statements. GCC only lets a nested function be
+    /// called ahead of its definition when it is declared first, so the
+    /// functions of the block, which any statement in it may call, are
+    /// declared at its top.
+    fn block_c(&self, statements: &[Statement], indent: usize) -> Result<String> {
         let tabs = "  ".repeat(indent);
-        match stmt {
-            Statement::Let { name, value } => {
-                Ok(format!("{}int {} = {};\n", tabs, name, self.generate_expr_c(value)?))
+        let mut code = String::new();
+        for stmt in statements {
+            if let StatementKind::Function {
+                name,
+                params,
+                return_type,
+                ..
+            } = &stmt.kind
+            {
+                self.for_each_version(name, &mut |codegen, name| {
+                    let signature = codegen.signature_c(name, params, return_type)?;
+                    code.push_str(&format!("{}auto {};\n", tabs, signature));
+                    Ok(())
+                })?;
             }
-            Statement::Print { args } => {
-                let args_str = args
+        }
+        for stmt in statements {
+            code.push_str(&self.generate_statement_c(stmt, indent)?);
+        }
+        Ok(code)
+    }
+
+    /// The C declaration of a function without its body. A function that
+    /// returns nothing is `void`.
+    fn signature_c(
+        &self,
+        name: &Ident,
+        params: &[Param],
+        return_type: &Option<TypeExpr>,
+    ) -> Result<String> {
+        let annotated = |ty: &Option<TypeExpr>| ty.as_ref().map_or("int".to_string(), c_type);
+        let (param_types, ret) = match self.types.get(name.span) {
+            Some(Type::Function(param_types, ret)) => {
+                // Types that the body leaves open make the function generic.
+                let generic = || {
+                    unsupported(name.span, "generic functions are not supported by the C backend")
+                };
+                let c_type = |ty: &Type| type_expr(ty, name.span).map(|ty| c_type(&ty));
+                let param_types = param_types
                     .iter()
-                    .map(|e| self.generate_expr_c(e))
-                    .collect::<Result<Vec<_>>>()?
-                    .join(", ");
-                Ok(format!("{}printf(\"%d\\n\", {});\n", tabs, args_str))
+                    .map(c_type)
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(generic)?;
+                let ret = match **ret {
+                    Type::None => "void".to_string(),
+                    ref ret => c_type(ret).ok_or_else(generic)?,
+                };
+                (param_types, ret)
             }
-            Statement::Function { name, params, body } => {
-                let params_str = params.join(", ");
-                let mut func = format!("{}int {}({}) {{\n", tabs, name, params_str);
-                for stmt in body {
-                    func.push_str(&self.generate_statement_c(stmt, indent + 1)?);
+            // Without a check, parameters and results are `int` unless
+            // annotated.
+            _ => (
+                params.iter().map(|param| annotated(&param.ty)).collect(),
+                annotated(return_type),
+            ),
+        };
+        let params: Vec<String> = params
+            .iter()
+            .zip(param_types)
+            .map(|(param, ty)| format!("{} {}", ty, param.name))
+            .collect();
+        Ok(format!("{} {}({})", ret, name, params.join(", ")))
+    }
+
+    fn generate_statement_c(&self, stmt: &Statement, indent: usize) -> Result<String> {
+        let tabs = "  ".repeat(indent);
+        match &stmt.kind {
+            StatementKind::Let {
+                name,
+                mutable,
+                ty,
+                value,
+            } => {
+                // Without an annotation or a checked type, only records and
+                // variants built in place have a known type; everything else
+                // is an `int`. A generic one needs the type to pick its
+                // instance.
+                let ty = self.inferred_type(name.span).or_else(|| ty.clone());
+                let c_type = match (&ty, &value.kind) {
+                    (Some(ty), _) => c_type(ty),
+                    (None, ExpressionKind::Record { name, .. })
+                        if !self.generics.contains_key(&name.name) =>
+                    {
+                        name.to_string()
+                    }
+                    (None, ExpressionKind::Variant { name, .. }) => self
+                        .variants
+                        .get(&name.name)
+                        .filter(|(enum_name, _)| !self.generics.contains_key(enum_name))
+                        .map_or("int".to_string(), |(enum_name, _)| enum_name.clone()),
+                    _ => "int".to_string(),
+                };
+                // An optional variable is given a value already wrapped in its
+                // type, so only a plain one is built as that type here.
+                let plain = |ty: &TypeExpr| !matches!(ty.kind, TypeExprKind::Optional(_));
+                let value = match (&ty, &value.kind) {
+                    (Some(ty), ExpressionKind::Record { fields, .. }) if plain(ty) => {
+                        self.record_c(&c_type, fields)?
+                    }
+                    (Some(ty), ExpressionKind::Variant { name, args }) if plain(ty) => {
+                        self.variant_c(&c_type, name, args)?
+                    }
+                    _ => self.generate_expr_c(value)?,
+                };
+                // A string is already a pointer to `const` characters.
+                let constant = !*mutable && !c_type.starts_with("const ");
+                let qualifier = if constant { "const " } else { "" };
+                Ok(format!("{}{}{} {} = {};\n", tabs, qualifier, c_type, name, value))
+            }
+            StatementKind::Assign {
+                target,
+                fields,
+                op,
+                value,
+            } => {
+                let mut place = target.to_string();
+                for field in fields {
+                    place.push_str(&format!(".{}", field));
+                }
+                let op = op.map(|op| op.format()).unwrap_or_default();
+                Ok(format!("{}{} {}= {};\n", tabs, place, op, self.generate_expr_c(value)?))
+            }
+            StatementKind::Type { name, fields, .. } => {
+                let mut code = format!("{}typedef struct {{\n", tabs);
+                for field in fields {
+                    code.push_str(&format!("{}  {} {};\n", tabs, c_type(&field.ty), field.name));
+                }
+                code.push_str(&format!("{}}} {};\n", tabs, name));
+                Ok(code)
+            }
+            // A tagged union: `tag` is the index of the variant, and `as`
+            // holds the fields of the variants that have any.
+            StatementKind::Enum { name, variants, .. } => {
+                let mut code = format!("{}typedef struct {{\n{}  int tag;\n", tabs, tabs);
+                if variants.iter().any(|variant| !variant.fields.is_empty()) {
+                    code.push_str(&format!("{}  union {{\n", tabs));
+                    for variant in variants.iter().filter(|v| !v.fields.is_empty()) {
+                        let fields: String = variant
+                            .fields
+                            .iter()
+                            .enumerate()
+                            .map(|(i, ty)| format!("{} _{}; ", c_type(ty), i))
+                            .collect();
+                        code.push_str(&format!(
+                            "{}    struct {{ {}}} {};\n",
+                            tabs, fields, variant.name
+                        ));
+                    }
+                    code.push_str(&format!("{}  }} as;\n", tabs));
+                }
+                code.push_str(&format!("{}}} {};\n", tabs, name));
+                Ok(code)
+            }
+            StatementKind::Print { args } => Ok(format!("{}{};\n", tabs, self.print_c(args)?)),
+            StatementKind::Function {
+                name,
+                params,
+                return_type,
+                body,
+            } => {
+                let mut code = String::new();
+                self.for_each_version(name, &mut |codegen, name| {
+                    let signature = codegen.signature_c(name, params, return_type)?;
+                    code.push_str(&format!("{}{} {{\n", tabs, signature));
+                    code.push_str(&codegen.block_c(body, indent + 1)?);
+                    code.push_str(&format!("{}}}\n", tabs));
+                    Ok(())
+                })?;
+                Ok(code)
+            }
+            StatementKind::If {
+                condition,
+                then_body,
+                else_ifs,
+                else_body,
+            } => {
+                let mut code = format!("{}if ({}) {{\n", tabs, self.generate_expr_c(condition)?);
+                code.push_str(&self.block_c(then_body, indent + 1)?);
+                for else_if in else_ifs {
+                    code.push_str(&format!(
+                        "{}}} else if ({}) {{\n",
+                        tabs,
+                        self.generate_expr_c(&else_if.condition)?
+                    ));
+                    code.push_str(&self.block_c(&else_if.body, indent + 1)?);
+                }
+                if let Some(else_body) = else_body {
+                    code.push_str(&format!("{}}} else {{\n", tabs));
+                    code.push_str(&self.block_c(else_body, indent + 1)?);
                 }
-                func.push_str(&format!("{}}}\n", tabs));
-                Ok(func)
+                code.push_str(&format!("{}}}\n", tabs));
+                Ok(code)
             }
-            _ => Ok(format!("{}// TODO: {}\n", tabs, format!("{:?}", stmt))),
+            StatementKind::While {
+                label,
+                condition,
+                body,
+            } => {
+                let mut code =
+                    format!("{}while ({}) {{\n", tabs, self.generate_expr_c(condition)?);
+                code.push_str(&self.generate_loop_body_c(label, body, indent)?);
+                Ok(code)
+            }
+            StatementKind::For {
+                label,
+                var,
+                iterable:
+                    Expression {
+                        kind:
+                            ExpressionKind::Range {
+                                start,
+                                end,
+                                inclusive,
+                                step,
+                            },
+                        ..
+                    },
+                body,
+            } => {
+                let end = self.generate_expr_c(end)?;
+                let (up, down) = if *inclusive { ("<=", ">=") } else { ("<", ">") };
+                let (condition, increment) = match step {
+                    Some(step) => {
+                        let step = self.generate_expr_c(step)?;
+                        let condition = format!(
+                            "({} > 0) ? {} {} {} : {} {} {}",
+                            step, var, up, end, var, down, end
+                        );
+                        (condition, format!("{} += {}", var, step))
+                    }
+                    None => (format!("{} {} {}", var, up, end), format!("{}++", var)),
+                };
+                let mut code = format!(
+                    "{}for (int {} = {}; {}; {}) {{\n",
+                    tabs,
+                    var,
+                    self.generate_expr_c(start)?,
+                    condition,
+                    increment
+                );
+                code.push_str(&self.generate_loop_body_c(label, body, indent)?);
+                Ok(code)
+            }
+            // Anything else to iterate over is a list, whose items are
+            // taken in turn.
+            StatementKind::For {
+                label,
+                var,
+                iterable,
+                body,
+            } => {
+                if let Some(ty) = self.types.get(iterable.span) {
+                    if !matches!(ty, Type::List(_)) {
+                        return Err(unsupported(
+                            iterable.span,
+                            format!("iterating over a `{}` is not supported by the C backend", ty),
+                        ));
+                    }
+                }
+                let list = format!("for_{}", stmt.span.start);
+                let mut code = format!(
+                    "{0}{{\n{0}  __auto_type {1} = {2};\n\
+                     {0}  for (int {1}_i = 0; {1}_i < {1}.len; {1}_i++) {{\n\
+                     {0}    __auto_type {3} = {1}.items[{1}_i];\n",
+                    tabs,
+                    list,
+                    self.generate_expr_c(iterable)?,
+                    var
+                );
+                code.push_str(&indent_c(&self.generate_loop_body_c(label, body, indent)?));
+                code.push_str(&format!("{}}}\n", tabs));
+                Ok(code)
+            }
+            StatementKind::Match { subject, arms } => {
+                // Named after the source offset, which is unique per match.
+                let value = format!("match_{}", stmt.span.start);
+                let mut code = format!("{}{{\n", tabs);
+                code.push_str(&format!(
+                    "{}  __auto_type {} = {};\n",
+                    tabs,
+                    value,
+                    self.generate_expr_c(subject)?
+                ));
+                let optional = matches!(self.types.get(subject.span), Some(Type::Option(_)));
+                for arm in arms {
+                    let (condition, place) = if optional {
+                        self.optional_pattern_c(&arm.pattern, &value)?
+                    } else {
+                        (self.pattern_condition_c(&arm.pattern, &value)?, value.clone())
+                    };
+                    code.push_str(&format!("{}  if ({}) {{\n", tabs, condition));
+                    let mut bindings = Vec::new();
+                    pattern_bindings_c(&arm.pattern, &place, &mut bindings);
+                    for (name, place) in bindings {
+                        code.push_str(&format!("{}    __auto_type {} = {};\n", tabs, name, place));
+                    }
+                    let mut body = self.block_c(&arm.body, indent + 2)?;
+                    body.push_str(&format!("{}    goto {}_end;\n", tabs, value));
+                    match &arm.guard {
+                        Some(guard) => {
+                            code.push_str(&format!(
+                                "{}    if ({}) {{\n",
+                                tabs,
+                                self.generate_expr_c(guard)?
+                            ));
+                            code.push_str(&indent_c(&body));
+                            code.push_str(&format!("{}    }}\n", tabs));
+                        }
+                        None => code.push_str(&body),
+                    }
+                    code.push_str(&format!("{}  }}\n", tabs));
+                }
+                code.push_str(&format!("{}  {}_end: ;\n{}}}\n", tabs, value, tabs));
+                Ok(code)
+            }
+            // C can only leave the innermost loop, so labeled jumps go to the
+            // labels that `generate_loop_body_c` places around the loop.
+            StatementKind::Break { label: Some(label) } => {
+                Ok(format!("{}goto {}_break;\n", tabs, label))
+            }
+            StatementKind::Break { label: None } => Ok(format!("{}break;\n", tabs)),
+            StatementKind::Continue { label: Some(label) } => {
+                Ok(format!("{}goto {}_continue;\n", tabs, label))
+            }
+            StatementKind::Continue { label: None } => Ok(format!("{}continue;\n", tabs)),
+            // A function that returns nothing is `void`, so `none` is not
+            // returned as a value unless it becomes an optional.
+            StatementKind::Return { value: Some(value) }
+                if !matches!(value.kind, ExpressionKind::None)
+                    || self.types.conversion(value.span).is_some() =>
+            {
+                Ok(format!("{}return {};\n", tabs, self.generate_expr_c(value)?))
+            }
+            StatementKind::Return { .. } => Ok(format!("{}return;\n", tabs)),
+            StatementKind::Expression(expr) => {
+                Ok(format!("{}{};\n", tabs, self.generate_expr_c(expr)?))
+            }
+            StatementKind::Comment(comment) => Ok(format!("{}//{}\n", tabs, comment.text)),
+            StatementKind::Error => Err(unsupported(stmt.span, "the statement has syntax errors")),
+        }
+    }
+
+    /// A `printf` call that prints `args` on one line, separated by spaces.
+    fn print_c(&self, args: &[Expression]) -> Result<String> {
+        let mut formats = Vec::new();
+        let mut values = String::new();
+        for arg in args {
+            let value = self.generate_expr_c(arg)?;
+            let (format, value) = self.format_c(self.types.get(arg.span), value, arg.span)?;
+            formats.push(format);
+            values.push_str(&format!(", {}", value));
+        }
+        Ok(format!("printf(\"{}\\n\"{})", formats.join(" "), values))
+    }
+
+    /// The `printf` conversion and argument that print `value`, the C for a
+    /// value of type `ty`, as the interpreter prints it.
+    fn format_c(
+        &self,
+        ty: Option<&Type>,
+        value: String,
+        span: Span,
+    ) -> Result<(&'static str, String)> {
+        let Some(ty) = ty else {
+            return Ok(("%d", value));
+        };
+        match type_expr(ty, span) {
+            Some(ty) => Ok(format_type_c(&ty, value)),
+            None => Err(unsupported(
+                span,
+                format!("printing a `{}` is not supported by the C backend", ty),
+            )),
         }
     }
 
+    /// Emits a loop body and its closing brace. A labeled loop gets a
+    /// `<label>_continue` label at the end of its body and a `<label>_break`
+    /// label after it.
+    fn generate_loop_body_c(
+        &self,
+        label: &Option<Ident>,
+        body: &[Statement],
+        indent: usize,
+    ) -> Result<String> {
+        let tabs = "  ".repeat(indent);
+        let mut code = self.block_c(body, indent + 1)?;
+        if let Some(label) = label {
+            code.push_str(&format!("{}  {}_continue: ;\n", tabs, label));
+        }
+        code.push_str(&format!("{}}}\n", tabs));
+        if let Some(label) = label {
+            code.push_str(&format!("{}{}_break: ;\n", tabs, label));
+        }
+        Ok(code)
+    }
+
     fn generate_expr_c(&self, expr: &Expression) -> Result<String> {
-        match expr {
-            Expression::Number(n) => Ok(n.to_string()),
-            Expression::Variable(v) => Ok(v.clone()),
-            Expression::Binary { left, op, right } => {
+        let conversion = self.types.conversion(expr.span);
+        let Some(target) = conversion.and_then(|ty| type_expr(ty, expr.span)) else {
+            return self.generate_value_c(expr);
+        };
+        // A value given where an optional type is expected is wrapped in it.
+        let found = self.inferred_type(expr.span);
+        let (optional, value_type) = match &target.kind {
+            TypeExprKind::Optional(inner)
+                if !matches!(found, Some(TypeExpr { kind: TypeExprKind::Optional(_), .. })) =>
+            {
+                (true, (**inner).clone())
+            }
+            _ => (false, target.clone()),
+        };
+        // Other conversions, such as from a `List[int]` to a `List[float]`,
+        // would need a new struct built item by item.
+        let none = matches!(expr.kind, ExpressionKind::None);
+        if let (true, false, Some(found)) = (is_struct(&value_type), none, &found) {
+            if c_type(found) != c_type(&value_type) {
+                return Err(unsupported(
+                    expr.span,
+                    format!(
+                        "converting a `{}` to a `{}` is not supported by the C backend",
+                        found, value_type
+                    ),
+                ));
+            }
+        }
+        Ok(match expr.kind {
+            _ if !optional => self.generate_value_c(expr)?,
+            ExpressionKind::None => format!("(({}){{.some = false}})", c_type(&target)),
+            _ => format!(
+                "(({}){{.some = true, .value = {}}})",
+                c_type(&target),
+                self.generate_value_c(expr)?
+            ),
+        })
+    }
+
+    /// The C for `expr` itself, before any conversion to an optional type.
+    fn generate_value_c(&self, expr: &Expression) -> Result<String> {
+        match &expr.kind {
+            ExpressionKind::Number { value, .. } => Ok(value.to_string()),
+            // The debug form always has a `.` or an exponent, as C needs.
+            ExpressionKind::Float { value, .. } => Ok(format!("{:?}", value)),
+            ExpressionKind::Bool(value) => Ok(value.to_string()),
+            ExpressionKind::None => Ok("0".to_string()),
+            ExpressionKind::Char { value, .. } => Ok(c_char(*value)),
+            ExpressionKind::String { value, .. } => Ok(c_string(value)),
+            ExpressionKind::Interpolated { parts, .. } => {
+                let mut format = String::new();
+                let mut values = String::new();
+                for part in parts {
+                    match part {
+                        InterpolatedPart::Literal(text) => {
+                            format.push_str(&text.replace('%', "%%"))
+                        }
+                        InterpolatedPart::Expression(part) => {
+                            let value = self.generate_expr_c(part)?;
+                            let (conversion, value) =
+                                self.format_c(self.types.get(part.span), value, part.span)?;
+                            format.push_str(conversion);
+                            values.push_str(&format!(", {}", value));
+                        }
+                    }
+                }
+                Ok(format!("format_string({}{})", c_string(&format), values))
+            }
+            ExpressionKind::Variable(v) if self.types.is_narrowed(expr.span) => {
+                Ok(format!("{}.value", v))
+            }
+            ExpressionKind::Variable(v) => Ok(v.clone()),
+            ExpressionKind::Binary { left, op, right } => {
+                let equality = match op {
+                    BinaryOp::Equal | BinaryOp::NotEqual => self.optional_equality_c(left, right)?,
+                    _ => None,
+                };
+                let comparison = matches!(
+                    op,
+                    BinaryOp::Equal
+                        | BinaryOp::NotEqual
+                        | BinaryOp::Greater
+                        | BinaryOp::Less
+                        | BinaryOp::GreaterEqual
+                        | BinaryOp::LessEqual
+                );
+                let strings = comparison && self.types.get(left.span) == Some(&Type::String);
+                Ok(match equality {
+                    Some(equality) if *op == BinaryOp::Equal => format!("({})", equality),
+                    Some(equality) => format!("(!({}))", equality),
+                    // C compares strings by their characters with `strcmp`.
+                    None if strings => format!(
+                        "(strcmp({}, {}) {} 0)",
+                        self.generate_expr_c(left)?,
+                        self.generate_expr_c(right)?,
+                        op.format()
+                    ),
+                    None => format!(
+                        "({} {} {})",
+                        self.generate_expr_c(left)?,
+                        op.format(),
+                        self.generate_expr_c(right)?
+                    ),
+                })
+            }
+            ExpressionKind::Unary { op, expr } => {
+                Ok(format!("({}{})", op.format(), self.generate_expr_c(expr)?))
+            }
+            ExpressionKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => Ok(format!(
+                "({} ? {} : {})",
+                self.generate_expr_c(condition)?,
+                self.generate_expr_c(then_value)?,
+                self.generate_expr_c(else_value)?
+            )),
+            // A generic record or variant is built as the instance of its type.
+            ExpressionKind::Record { name, fields } if self.generics.contains_key(&name.name) => {
+                self.record_c(&self.c_type_of(expr)?, fields)
+            }
+            ExpressionKind::Record { name, fields } => self.record_c(&name.name, fields),
+            ExpressionKind::Variant { name, args } => {
+                let Some((enum_name, _)) = self.variants.get(&name.name) else {
+                    return Err(unsupported(name.span, format!("undefined variant `{}`", name)));
+                };
+                if self.generics.contains_key(enum_name) {
+                    return self.variant_c(&self.c_type_of(expr)?, name, args);
+                }
+                self.variant_c(enum_name, name, args)
+            }
+            // Tuple items are the fields `_0`, `_1` and so on.
+            ExpressionKind::Field { target, field } if field.name.parse::<usize>().is_ok() => {
+                Ok(format!("{}._{}", self.generate_expr_c(target)?, field))
+            }
+            ExpressionKind::Field { target, field } => {
+                Ok(format!("{}.{}", self.generate_expr_c(target)?, field))
+            }
+            ExpressionKind::Call { name, args } if !self.functions.contains_key(&name.name) => {
+                match (name.name.as_str(), args.as_slice()) {
+                    ("print", _) => self.print_c(args),
+                    ("len", [list]) => Ok(format!("{}.len", self.generate_expr_c(list)?)),
+                    ("get", [list, index]) => self.index_c(list, index, expr.span),
+                    _ => Err(unsupported(name.span, format!("undefined function `{}`", name))),
+                }
+            }
+            // A generic function is called through the instance for the
+            // types of the call.
+            ExpressionKind::Call { name, args } => {
+                let callee = match self.call_bindings(name) {
+                    Some(bindings) if bindings.is_empty() => name.to_string(),
+                    Some(bindings) => instance_name(&name.name, &bindings),
+                    None => return Err(unknown_type(name.span)),
+                };
+                let args = args
+                    .iter()
+                    .map(|arg| self.generate_expr_c(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(format!("{}({})", callee, args.join(", ")))
+            }
+            ExpressionKind::Tuple(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.generate_expr_c(item))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(format!("(({}){{{}}})", self.c_type_of(expr)?, items.join(", ")))
+            }
+            // The items are copied to the heap, so that the list outlives
+            // the block it is built in.
+            ExpressionKind::List(items) => {
+                let list = self.c_type_of(expr)?;
+                if items.is_empty() {
+                    return Ok(format!("(({}){{NULL, 0}})", list));
+                }
+                let Some(Type::List(item)) = self.types.get(expr.span) else {
+                    return Err(unknown_type(expr.span));
+                };
+                let item = type_expr(item, expr.span).ok_or_else(|| unknown_type(expr.span))?;
+                let array = format!("{}[{}]", c_type(&item), items.len());
+                let items = items
+                    .iter()
+                    .map(|item| self.generate_expr_c(item))
+                    .collect::<Result<Vec<_>>>()?;
                 Ok(format!(
-                    "({} {} {})",
-                    self.generate_expr_c(left)?,
-                    op.format(),
-                    self.generate_expr_c(right)?
+                    "(({}){{memcpy(malloc(sizeof({})), ({}[]){{{}}}, sizeof({})), {}}})",
+                    list,
+                    array,
+                    c_type(&item),
+                    items.join(", "),
+                    array,
+                    items.len()
+                ))
+            }
+            ExpressionKind::Index { target, index } => match self.types.get(target.span) {
+                Some(Type::String) => Err(unsupported(
+                    expr.span,
+                    "indexing a string is not supported by the C backend",
+                )),
+                Some(Type::Map(..)) => Err(unsupported(
+                    target.span,
+                    "maps are not supported by the C backend",
+                )),
+                _ => self.index_c(target, index, expr.span),
+            },
+            ExpressionKind::Slice {
+                target,
+                start,
+                end,
+                inclusive,
+            } => {
+                if let Some(Type::String) = self.types.get(target.span) {
+                    return Err(unsupported(
+                        expr.span,
+                        "slicing a string is not supported by the C backend",
+                    ));
+                }
+                let slice = format!("slice_{}", expr.span.start);
+                let from = match start {
+                    Some(start) => self.generate_expr_c(start)?,
+                    None => "0".to_string(),
+                };
+                let to = match end {
+                    Some(end) if *inclusive => format!("{} + 1", self.generate_expr_c(end)?),
+                    Some(end) => self.generate_expr_c(end)?,
+                    None => format!("{}.len", slice),
+                };
+                // The slice shares the items of the list it is taken from.
+                Ok(format!(
+                    "({{ __auto_type {0} = {1}; int {0}_from = {2}; int {0}_to = {3}; \
+                     check_slice({0}_from, {0}_to, {0}.len); \
+                     (__typeof__({0})){{{0}.items + {0}_from, {0}_to - {0}_from}}; }})",
+                    slice,
+                    self.generate_expr_c(target)?,
+                    from,
+                    to
                 ))
             }
-            _ => Ok("0".to_string()),
+            ExpressionKind::Map(_) => Err(unsupported(
+                expr.span,
+                "maps are not supported by the C backend",
+            )),
+            ExpressionKind::Range { .. } => Err(unsupported(
+                expr.span,
+                "the C backend only supports a range as what a `for` loop iterates over",
+            )),
+            ExpressionKind::Error => {
+                Err(unsupported(expr.span, "the expression has syntax errors"))
+            }
+        }
+    }
+
+    /// The C for the item of the list `target` at `index`, which must be in
+    /// bounds.
+    fn index_c(&self, target: &Expression, index: &Expression, span: Span) -> Result<String> {
+        Ok(format!(
+            "({{ __auto_type {0} = {1}; {0}.items[check_index({2}, {0}.len)]; }})",
+            format!("index_{}", span.start),
+            self.generate_expr_c(target)?,
+            self.generate_expr_c(index)?
+        ))
+    }
+
+    /// The C type of the value of `expr`, which C needs spelled out to build
+    /// a tuple or a list.
+    fn c_type_of(&self, expr: &Expression) -> Result<String> {
+        self.types
+            .get(expr.span)
+            .and_then(|ty| type_expr(ty, expr.span))
+            .map(|ty| c_type(&ty))
+            .ok_or_else(|| unknown_type(expr.span))
+    }
+
+    /// The C for `left == right` when either side is optional or `none`, which
+    /// C cannot compare directly: optionals are equal when both are `none`
+    /// or both hold equal values.
+    fn optional_equality_c(&self, left: &Expression, right: &Expression) -> Result<Option<String>> {
+        let types = (self.types.get(left.span), self.types.get(right.span));
+        let optional = |ty: Option<&Type>| matches!(ty, Some(Type::Option(_) | Type::None));
+        if !optional(types.0) && !optional(types.1) {
+            return Ok(None);
+        }
+        let (l, r) = (self.generate_expr_c(left)?, self.generate_expr_c(right)?);
+        let strings = matches!(
+            types,
+            (Some(Type::Option(inner)), _) | (_, Some(Type::Option(inner)))
+                if **inner == Type::String
+        );
+        let equal = |a: String, b: String| match strings {
+            true => format!("strcmp({}, {}) == 0", a, b),
+            false => format!("{} == {}", a, b),
+        };
+        Ok(Some(match types {
+            (Some(Type::None), Some(Type::None)) => "1".to_string(),
+            (Some(Type::Option(_)), Some(Type::None)) => format!("!{}.some", l),
+            (Some(Type::None), Some(Type::Option(_))) => format!("!{}.some", r),
+            // A value that is not optional is never `none`.
+            (Some(Type::None), _) | (_, Some(Type::None)) => "0".to_string(),
+            (Some(Type::Option(_)), Some(Type::Option(_))) => format!(
+                "{0}.some == {1}.some && (!{0}.some || {2})",
+                l,
+                r,
+                equal(format!("{}.value", l), format!("{}.value", r))
+            ),
+            (Some(Type::Option(_)), _) => {
+                format!("{}.some && {}", l, equal(format!("{}.value", l), r))
+            }
+            _ => format!("{}.some && {}", r, equal(format!("{}.value", r), l)),
+        }))
+    }
+
+    /// The type the typechecker found for the variable declared at `span`,
+    /// written as an annotation would be.
+    fn inferred_type(&self, span: Span) -> Option<TypeExpr> {
+        type_expr(self.types.get(span)?, span)
+    }
+
+    /// The types that `stmt` itself writes or, for variables without an
+    /// annotation, that the typechecker found: those that can name
+    /// instances of generic types.
+    fn type_exprs(&self, stmt: &Statement) -> Vec<TypeExpr> {
+        match &stmt.kind {
+            StatementKind::Let { name, ty, .. } => {
+                self.inferred_type(name.span).or_else(|| ty.clone()).into_iter().collect()
+            }
+            StatementKind::Type { params, fields, .. } if params.is_empty() => {
+                fields.iter().map(|field| field.ty.clone()).collect()
+            }
+            StatementKind::Enum {
+                params, variants, ..
+            } if params.is_empty() => variants
+                .iter()
+                .flat_map(|variant| variant.fields.iter().cloned())
+                .collect(),
+            StatementKind::Function {
+                name,
+                params,
+                return_type,
+                ..
+            } => match self.types.get(name.span) {
+                Some(Type::Function(param_types, ret)) => param_types
+                    .iter()
+                    .chain([&**ret])
+                    .filter_map(|ty| type_expr(ty, name.span))
+                    .collect(),
+                _ => params
+                    .iter()
+                    .filter_map(|param| param.ty.clone())
+                    .chain(return_type.clone())
+                    .collect(),
+            },
+            _ => Vec::new(),
         }
     }
+
+    /// Builds a record of the C type `type_name` from its fields.
+    fn record_c(&self, type_name: &str, fields: &[(Ident, Expression)]) -> Result<String> {
+        let fields = fields
+            .iter()
+            .map(|(field, value)| Ok(format!(".{} = {}", field, self.generate_expr_c(value)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("(({}){{{}}})", type_name, fields.join(", ")))
+    }
+
+    /// Builds the variant `name` of the C type `type_name`, the enum or the
+    /// instance of a generic enum that it belongs to.
+    fn variant_c(&self, type_name: &str, name: &Ident, args: &[Expression]) -> Result<String> {
+        let Some((_, tag)) = self.variants.get(&name.name) else {
+            return Err(unsupported(name.span, format!("undefined variant `{}`", name)));
+        };
+        if args.is_empty() {
+            return Ok(format!("(({}){{.tag = {}}})", type_name, tag));
+        }
+        let args = args
+            .iter()
+            .map(|arg| self.generate_expr_c(arg))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!(
+            "(({}){{.tag = {}, .as.{} = {{{}}}}})",
+            type_name,
+            tag,
+            name,
+            args.join(", ")
+        ))
+    }
+
+    /// Adds to `instances` each instance of a generic type that `ty` names,
+    /// together with those its fields name in turn. Lists, tuples and
+    /// optional types are instances too: each is a struct of its own.
+    fn collect_instances(&self, ty: &TypeExpr, instances: &mut Vec<TypeExpr>) {
+        let (args, generic) = match &ty.kind {
+            TypeExprKind::Named { name, args } => (
+                args,
+                name.name == "List" || self.generics.contains_key(&name.name),
+            ),
+            TypeExprKind::Tuple(items) => (items, true),
+            TypeExprKind::Optional(inner) => {
+                self.collect_instances(inner, instances);
+                if !instances.iter().any(|instance| mangle(instance) == mangle(ty)) {
+                    instances.push(ty.clone());
+                }
+                return;
+            }
+        };
+        for arg in args {
+            self.collect_instances(arg, instances);
+        }
+        if args.is_empty()
+            || !generic
+            || instances.iter().any(|instance| mangle(instance) == mangle(ty))
+        {
+            return;
+        }
+        instances.push(ty.clone());
+        for part in self.parts(ty) {
+            self.collect_instances(&part, instances);
+        }
+    }
+
+    /// The declaration of the instance `ty` of the generic type `name`: the
+    /// generic declaration with its parameters replaced by the arguments
+    /// and its name by the instance's C name. `None` when `ty` is an
+    /// instance of some other type.
+    fn instantiate(&self, ty: &TypeExpr, name: &str) -> Option<Statement> {
+        let TypeExprKind::Named { name: base, args } = &ty.kind else {
+            return None;
+        };
+        let declaration = self.generics.get(name).filter(|_| base.name == name)?;
+        let instance_name = Ident::new(mangle(ty), base.span);
+        let kind = match &declaration.kind {
+            StatementKind::Type { params, fields, .. } => StatementKind::Type {
+                name: instance_name,
+                params: Vec::new(),
+                fields: fields
+                    .iter()
+                    .map(|field| FieldDecl {
+                        ty: substitute_type(&field.ty, params, args),
+                        ..field.clone()
+                    })
+                    .collect(),
+            },
+            StatementKind::Enum {
+                params, variants, ..
+            } => StatementKind::Enum {
+                name: instance_name,
+                params: Vec::new(),
+                variants: variants
+                    .iter()
+                    .map(|variant| VariantDecl {
+                        fields: variant
+                            .fields
+                            .iter()
+                            .map(|field| substitute_type(field, params, args))
+                            .collect(),
+                        ..variant.clone()
+                    })
+                    .collect(),
+            },
+            _ => return None,
+        };
+        Some(Statement::new(kind, declaration.span))
+    }
+
+    /// Builds the C condition under which the optional `value` matches
+    /// `pattern`, and the C expression that the pattern's bindings take
+    /// apart: `none` only matches an empty optional, and other patterns
+    /// match the value inside.
+    fn optional_pattern_c(&self, pattern: &Pattern, value: &str) -> Result<(String, String)> {
+        let inner = format!("{}.value", value);
+        Ok(match &pattern.kind {
+            PatternKind::Wildcard => ("1".to_string(), value.to_string()),
+            // A binding after a `none` arm holds the value inside.
+            PatternKind::Binding(_) => match self.types.get(pattern.span) {
+                Some(Type::Option(_)) => ("1".to_string(), value.to_string()),
+                _ => ("1".to_string(), inner),
+            },
+            PatternKind::Literal(expr) if matches!(expr.kind, ExpressionKind::None) => {
+                (format!("!{}.some", value), value.to_string())
+            }
+            _ => {
+                let condition = self.pattern_condition_c(pattern, &inner)?;
+                (format!("{}.some && ({})", value, condition), inner)
+            }
+        })
+    }
+
+    /// Builds the C condition under which `value` matches `pattern`.
+    fn pattern_condition_c(&self, pattern: &Pattern, value: &str) -> Result<String> {
+        let literal = |expr: &Expression| match &expr.kind {
+            ExpressionKind::Number { value, .. } => Some(value.to_string()),
+            ExpressionKind::Bool(value) => Some(value.to_string()),
+            ExpressionKind::None => Some("0".to_string()),
+            ExpressionKind::Char { value, .. } => Some(c_char(*value)),
+            ExpressionKind::Unary {
+                op: UnaryOp::Negate,
+                expr,
+            } => match &expr.kind {
+                ExpressionKind::Number { value, .. } => Some(format!("-{}", value)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let unsupported_pattern =
+            || unsupported(pattern.span, "this pattern is not supported by the C backend");
+        Ok(match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding(_) => "1".to_string(),
+            // C compares strings by their characters with `strcmp`.
+            PatternKind::Literal(Expression {
+                kind: ExpressionKind::String { value: text, .. },
+                ..
+            }) => format!("strcmp({}, {}) == 0", value, c_string(text)),
+            PatternKind::Literal(expr) => literal(expr)
+                .map(|literal| format!("{} == {}", value, literal))
+                .ok_or_else(unsupported_pattern)?,
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => match (literal(start), literal(end)) {
+                (Some(start), Some(end)) => {
+                    let operator = if *inclusive { "<=" } else { "<" };
+                    format!("{} >= {} && {} {} {}", value, start, value, operator, end)
+                }
+                _ => return Err(unsupported_pattern()),
+            },
+            PatternKind::Variant { name, fields } => {
+                let Some((_, tag)) = self.variants.get(&name.name) else {
+                    return Err(unsupported(name.span, format!("undefined variant `{}`", name)));
+                };
+                let mut condition = format!("{}.tag == {}", value, tag);
+                for (i, field) in fields.iter().enumerate() {
+                    let field_value = format!("{}.as.{}._{}", value, name, i);
+                    let field_condition = self.pattern_condition_c(field, &field_value)?;
+                    if field_condition != "1" {
+                        condition.push_str(&format!(" && ({})", field_condition));
+                    }
+                }
+                condition
+            }
+            PatternKind::Tuple(items) => {
+                let mut conditions = Vec::new();
+                for (i, item) in items.iter().enumerate() {
+                    let item_value = format!("{}._{}", value, i);
+                    let item_condition = self.pattern_condition_c(item, &item_value)?;
+                    if item_condition != "1" {
+                        conditions.push(format!("({})", item_condition));
+                    }
+                }
+                if conditions.is_empty() {
+                    "1".to_string()
+                } else {
+                    conditions.join(" && ")
+                }
+            }
+        })
+    }
+}
+
+/// Declared at the top of every C program: the headers that the generated
+/// code uses, and the helpers it calls.
+const PRELUDE_C: &str = "\
+#define _GNU_SOURCE
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+static char* format_string(const char* format, ...) {
+  va_list args;
+  va_start(args, format);
+  char* result;
+  if (vasprintf(&result, format, args) < 0) abort();
+  va_end(args);
+  return result;
+}
+
+static int check_index(int index, int len) {
+  if (index < 0 || index >= len) {
+    fprintf(stderr, \"error: index %d is out of bounds for length %d\\n\", index, len);
+    exit(1);
+  }
+  return index;
+}
+
+static void check_slice(int from, int to, int len) {
+  if (from < 0 || from > to || to > len) {
+    fprintf(stderr, \"error: slice %d..%d is out of bounds for length %d\\n\", from, to, len);
+    exit(1);
+  }
+}
+
+";
+
+/// The error for a construct at `span` that the C backend cannot lower.
+fn unsupported(span: Span, message: impl Into<String>) -> anyhow::Error {
+    Diagnostic::error(codes::UNSUPPORTED_BY_BACKEND, message)
+        .with_label(span, "cannot be compiled to C")
+        .into()
+}
+
+/// The error for a value whose C type depends on a type that is not known,
+/// as when the program was not typechecked.
+fn unknown_type(span: Span) -> anyhow::Error {
+    unsupported(span, "the C backend needs the type of this value, which is not known")
+}
+
+/// The `printf` conversion and argument that print `value`, a C value of
+/// type `ty`. Structs are printed by their `show_` function.
+fn format_type_c(ty: &TypeExpr, value: String) -> (&'static str, String) {
+    if is_struct(ty) {
+        return ("%s", format!("show_{}({})", c_type(ty), value));
+    }
+    let TypeExprKind::Named { name, .. } = &ty.kind else {
+        return ("%d", value);
+    };
+    match name.name.as_str() {
+        "float" => ("%g", value),
+        "string" => ("%s", value),
+        "char" => ("%c", value),
+        "bool" => ("%s", format!("({} ? \"true\" : \"false\")", value)),
+        "none" => ("%s", c_string("none")),
+        _ => ("%d", value),
+    }
+}
+
+/// The function `show_<name>` that renders a value of the C type `name` as
+/// a new string, given the statements that write it to the stream `out`.
+fn show_c(name: &str, body: &str) -> String {
+    format!(
+        "static char* show_{0}({0} value) {{\n\
+         \x20 char* text;\n\
+         \x20 size_t size;\n\
+         \x20 FILE* out = open_memstream(&text, &size);\n\
+         {1}\
+         \x20 fclose(out);\n\
+         \x20 return text;\n\
+         }}\n",
+        name, body
+    )
+}
+
+/// The statement that writes `parts` to `out`: each part is literal text,
+/// followed by a C value of the given type, if any.
+fn write_c(parts: &[(String, Option<(&TypeExpr, String)>)]) -> String {
+    let mut format = String::new();
+    let mut values = String::new();
+    for (text, value) in parts {
+        format.push_str(text);
+        if let Some((ty, value)) = value {
+            let (conversion, value) = format_type_c(ty, value.clone());
+            format.push_str(conversion);
+            values.push_str(&format!(", {}", value));
+        }
+    }
+    format!("fprintf(out, {}{});", c_string(&format), values)
+}
+
+/// The text that comes before the `i`th of the items of a list, a tuple or
+/// a variant: `open` before the first and a comma before the others.
+fn separator(i: usize, open: &str) -> String {
+    if i == 0 { open } else { ", " }.to_string()
+}
+
+/// The `show_` function of the record type or enum `declaration`, whose C
+/// name is `name`. Records print as `Point { x: 1, y: 2 }` and variants as
+/// `Circle(2.5)`, under the names that the program gives them.
+fn show_declaration_c(name: &str, display_name: &str, declaration: &Statement) -> String {
+    let body = match &declaration.kind {
+        StatementKind::Type { fields, .. } => {
+            let mut parts = vec![(format!("{} {{ ", display_name), None)];
+            for (i, field) in fields.iter().enumerate() {
+                let text = format!("{}{}: ", separator(i, ""), field.name);
+                parts.push((text, Some((&field.ty, format!("value.{}", field.name)))));
+            }
+            parts.push((" }".to_string(), None));
+            format!("  {}\n", write_c(&parts))
+        }
+        StatementKind::Enum { variants, .. } => {
+            let mut code = "  switch (value.tag) {\n".to_string();
+            for (tag, variant) in variants.iter().enumerate() {
+                let mut parts = vec![(variant.name.to_string(), None)];
+                for (i, field) in variant.fields.iter().enumerate() {
+                    let value = format!("value.as.{}._{}", variant.name, i);
+                    parts.push((separator(i, "("), Some((field, value))));
+                }
+                if !variant.fields.is_empty() {
+                    parts.push((")".to_string(), None));
+                }
+                code.push_str(&format!("  case {}: {} break;\n", tag, write_c(&parts)));
+            }
+            code.push_str("  }\n");
+            code
+        }
+        _ => String::new(),
+    };
+    show_c(name, &body)
+}
+
+/// The `show_` function of a list, tuple or optional type, which prints as
+/// `[1, 2]`, `(1, a)`, or its value or `none`.
+fn show_instance_c(ty: &TypeExpr) -> String {
+    let body = match &ty.kind {
+        TypeExprKind::Named { args, .. } => format!(
+            "  fputs(\"[\", out);\n\
+             \x20 for (int i = 0; i < value.len; i++) {{\n\
+             \x20   if (i > 0) fputs(\", \", out);\n\
+             \x20   {}\n\
+             \x20 }}\n\
+             \x20 fputs(\"]\", out);\n",
+            write_c(&[(String::new(), Some((&args[0], "value.items[i]".to_string())))])
+        ),
+        TypeExprKind::Tuple(items) => {
+            let mut parts: Vec<_> = items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| (separator(i, "("), Some((item, format!("value._{}", i)))))
+                .collect();
+            parts.push((")".to_string(), None));
+            format!("  {}\n", write_c(&parts))
+        }
+        TypeExprKind::Optional(inner) => format!(
+            "  if (value.some) {} else fputs(\"none\", out);\n",
+            write_c(&[(String::new(), Some((inner, "value.value".to_string())))])
+        ),
+    };
+    show_c(&mangle(ty), &body)
+}
+
+/// The C type of values of type `ty`. Values without a closer C type are
+/// `int`.
+fn c_type(ty: &TypeExpr) -> String {
+    match &ty.kind {
+        TypeExprKind::Named { name, args } if args.is_empty() => match name.name.as_str() {
+            "float" => "double".to_string(),
+            "string" => "const char*".to_string(),
+            name if name.starts_with(char::is_uppercase) => name.to_string(),
+            _ => "int".to_string(),
+        },
+        TypeExprKind::Named { name, .. } if name.name != "Map" => mangle(ty),
+        TypeExprKind::Optional(_) | TypeExprKind::Tuple(_) => mangle(ty),
+        _ => "int".to_string(),
+    }
+}
+
+/// True when C values of type `ty` are structs that the program declares,
+/// rather than values of a type that C already has.
+fn is_struct(ty: &TypeExpr) -> bool {
+    match &ty.kind {
+        TypeExprKind::Named { name, .. } => {
+            name.name.starts_with(char::is_uppercase) && name.name != "Map"
+        }
+        TypeExprKind::Tuple(_) | TypeExprKind::Optional(_) => true,
+    }
+}
+
+/// The C name of an instance of a generic type: `Pair[int, Box[float]]` is
+/// `Pair_int_Box_float`, and `Box[int]?` is `Box_int_opt`.
+fn mangle(ty: &TypeExpr) -> String {
+    let (mut mangled, args) = match &ty.kind {
+        TypeExprKind::Named { name, args } => (name.to_string(), args),
+        TypeExprKind::Tuple(items) => ("tuple".to_string(), items),
+        TypeExprKind::Optional(inner) => return format!("{}_opt", mangle(inner)),
+    };
+    for arg in args {
+        mangled.push('_');
+        mangled.push_str(&mangle(arg));
+    }
+    mangled
+}
+
+/// `ty` with each of the type parameters `params` replaced by the matching
+/// type in `args`.
+fn substitute_type(ty: &TypeExpr, params: &[Ident], args: &[TypeExpr]) -> TypeExpr {
+    let kind = match &ty.kind {
+        TypeExprKind::Named { name, args: type_args } if type_args.is_empty() => {
+            match params.iter().position(|param| param.name == name.name) {
+                Some(i) => return args[i].clone(),
+                None => return ty.clone(),
+            }
+        }
+        TypeExprKind::Named { name, args: type_args } => TypeExprKind::Named {
+            name: name.clone(),
+            args: type_args
+                .iter()
+                .map(|arg| substitute_type(arg, params, args))
+                .collect(),
+        },
+        TypeExprKind::Tuple(items) => TypeExprKind::Tuple(
+            items
+                .iter()
+                .map(|item| substitute_type(item, params, args))
+                .collect(),
+        ),
+        TypeExprKind::Optional(inner) => {
+            TypeExprKind::Optional(Box::new(substitute_type(inner, params, args)))
+        }
+    };
+    TypeExpr::new(kind, ty.span)
+}
+
+/// Adds to `bindings` each type variable in `generic`, in order, with the
+/// part of `concrete` in its place, unless it is there already.
+pub(crate) fn bind_vars(generic: &Type, concrete: &Type, bindings: &mut Vec<(usize, Type)>) {
+    match (generic, concrete) {
+        (Type::Var { id, .. }, ty) if !bindings.iter().any(|(var, _)| var == id) => {
+            bindings.push((*id, ty.clone()))
+        }
+        (Type::List(a), Type::List(b)) | (Type::Option(a), Type::Option(b)) => {
+            bind_vars(a, b, bindings)
+        }
+        (Type::Map(k1, v1), Type::Map(k2, v2)) => {
+            bind_vars(k1, k2, bindings);
+            bind_vars(v1, v2, bindings);
+        }
+        (Type::Tuple(xs), Type::Tuple(ys))
+        | (Type::Record(_, xs), Type::Record(_, ys))
+        | (Type::Enum(_, xs), Type::Enum(_, ys)) => {
+            for (x, y) in xs.iter().zip(ys) {
+                bind_vars(x, y, bindings);
+            }
+        }
+        (Type::Function(p1, r1), Type::Function(p2, r2)) => {
+            for (x, y) in p1.iter().zip(p2) {
+                bind_vars(x, y, bindings);
+            }
+            bind_vars(r1, r2, bindings);
+        }
+        _ => {}
+    }
+}
+
+/// The C name of the instance of the generic function `name` whose type
+/// variables stand for the types in `bindings`: `first_int`, or
+/// `pair_string_Box_int`.
+fn instance_name(name: &str, bindings: &[(usize, Type)]) -> String {
+    let mut mangled = name.to_string();
+    for (_, ty) in bindings {
+        if let Some(ty) = type_expr(ty, Span::default()) {
+            mangled.push('_');
+            mangled.push_str(&mangle(&ty));
+        }
+    }
+    mangled
+}
+
+/// Calls `f` with the called name of each call in `statements`, including
+/// those in nested statements.
+pub(crate) fn for_each_call<'a>(statements: &'a [Statement], f: &mut impl FnMut(&'a Ident)) {
+    for_each_expression(statements, &mut |expr| {
+        if let ExpressionKind::Call { name, .. } = &expr.kind {
+            f(name);
+        }
+    });
+}
+
+/// `ty` written as an annotation, at `span`. Types that no annotation can
+/// spell, such as one still unknown in a generic function, have none.
+pub(crate) fn type_expr(ty: &Type, span: Span) -> Option<TypeExpr> {
+    let named = |name: &str, args: &[Type]| {
+        let args = args
+            .iter()
+            .map(|arg| type_expr(arg, span))
+            .collect::<Option<Vec<_>>>()?;
+        let name = Ident::new(name, span);
+        Some(TypeExpr::new(TypeExprKind::Named { name, args }, span))
+    };
+    match ty {
+        Type::Number => named("int", &[]),
+        Type::Float => named("float", &[]),
+        Type::String => named("string", &[]),
+        Type::Boolean => named("bool", &[]),
+        Type::Char => named("char", &[]),
+        Type::None => named("none", &[]),
+        Type::Range => named("range", &[]),
+        Type::List(item) => named("List", std::slice::from_ref(item)),
+        Type::Map(key, value) => named("Map", &[(**key).clone(), (**value).clone()]),
+        Type::Record(name, args) | Type::Enum(name, args) => named(name, args),
+        Type::Tuple(items) => {
+            let items = items
+                .iter()
+                .map(|item| type_expr(item, span))
+                .collect::<Option<Vec<_>>>()?;
+            Some(TypeExpr::new(TypeExprKind::Tuple(items), span))
+        }
+        Type::Option(inner) => Some(TypeExpr::new(
+            TypeExprKind::Optional(Box::new(type_expr(inner, span)?)),
+            span,
+        )),
+        Type::Function(..) | Type::Var { .. } | Type::Unknown => None,
+    }
+}
+
+/// Collects the names that `pattern` binds, each with the C expression for
+/// the part of `value` it binds to.
+fn pattern_bindings_c(pattern: &Pattern, value: &str, bindings: &mut Vec<(String, String)>) {
+    match &pattern.kind {
+        PatternKind::Binding(name) => bindings.push((name.clone(), value.to_string())),
+        PatternKind::Variant { name, fields } => {
+            for (i, field) in fields.iter().enumerate() {
+                pattern_bindings_c(field, &format!("{}.as.{}._{}", value, name, i), bindings);
+            }
+        }
+        PatternKind::Tuple(items) => {
+            for (i, item) in items.iter().enumerate() {
+                pattern_bindings_c(item, &format!("{}._{}", value, i), bindings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Indents generated C code by one more level.
+fn indent_c(code: &str) -> String {
+    code.lines().map(|line| format!("  {}\n", line)).collect()
+}
+
+/// Spells a character as a C expression: a character literal for printable
+/// ASCII, otherwise its code point.
+fn c_char(value: char) -> String {
+    match value {
+        '\'' => "'\\''".to_string(),
+        '\\' => "'\\\\'".to_string(),
+        '\n' => "'\\n'".to_string(),
+        '\t' => "'\\t'".to_string(),
+        '\r' => "'\\r'".to_string(),
+        ' '..='~' => format!("'{}'", value),
+        _ => (value as u32).to_string(),
+    }
+}
+
+/// A C string literal holding `value`.
+fn c_string(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\t' => literal.push_str("\\t"),
+            '\r' => literal.push_str("\\r"),
+            ' '..='~' => literal.push(ch),
+            // Octal escapes stop after three digits, unlike hex ones.
+            _ => {
+                let mut bytes = [0; 4];
+                for byte in ch.encode_utf8(&mut bytes).bytes() {
+                    literal.push_str(&format!("\\{:03o}", byte));
+                }
+            }
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// How a statement finished: normally, or by jumping out of the loop that
+/// encloses it.
+enum ControlFlow {
+    Normal,
+    Break(Option<String>),
+    Continue(Option<String>),
 }
 
 pub struct Interpreter {
-    variables: HashMap<String, i64>,
+    /// The variables of each block being run, innermost last, with whether
+    /// each was declared with `let mut`.
+    scopes: Vec<HashMap<String, (Value, bool)>>,
+    /// Field names of each record type, in declaration order.
+    records: HashMap<String, Vec<String>>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         Self {
-            variables: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            records: HashMap::new(),
         }
     }
 
-    pub fn interpret(&mut self, program: &Program) -> Result<()> {
+    pub fn interpret(&mut self, program: &Program) -> DiagnosticResult<()> {
+        // Records can be built above the declaration of their type.
         for stmt in &program.statements {
-            self.execute_statement(stmt)?;
+            self.declare_type(stmt);
         }
+        // The parser rejects `break` and `continue` outside loops, so every
+        // jump is caught by a loop before it gets here.
+        self.execute_block(&program.statements)?;
         Ok(())
     }
 
-    fn execute_statement(&mut self, stmt: &Statement) -> Result<()> {
-        match stmt {
-            Statement::Let { name, value } => {
-                let val = self.evaluate_expression(value)?;
-                self.variables.insert(name.clone(), val);
+    fn declare_type(&mut self, stmt: &Statement) {
+        if let StatementKind::Type { name, fields, .. } = &stmt.kind {
+            let fields = fields.iter().map(|f| f.name.name.clone()).collect();
+            self.records.insert(name.name.clone(), fields);
+        }
+    }
+
+    /// The value that `target.fields` names, for assigning to.
+    fn place_mut(&mut self, target: &Ident, fields: &[Ident]) -> DiagnosticResult<&mut Value> {
+        let variable = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&target.name));
+        let mut place = variable.map(|(value, _)| value).ok_or_else(|| {
+            Diagnostic::error(
+                codes::RUNTIME_UNDEFINED_VARIABLE,
+                format!("undefined variable `{}`", target),
+            )
+            .with_label(target.span, "no value bound to this name")
+        })?;
+        for field in fields {
+            let type_name = place.type_name();
+            place = match place {
+                Value::Record { fields, .. } => fields
+                    .iter_mut()
+                    .find(|(name, _)| *name == field.name)
+                    .map(|(_, value)| value),
+                _ => None,
+            }
+            .ok_or_else(|| {
+                Diagnostic::error(
+                    codes::RUNTIME_INVALID_OPERAND,
+                    format!("no field `{}` on `{}`", field, type_name),
+                )
+                .with_label(field.span, "unknown field")
+            })?;
+        }
+        Ok(place)
+    }
+
+    fn declare(&mut self, name: &str, value: Value, mutable: bool) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), (value, mutable));
+        }
+    }
+
+    /// The innermost variable named `name`, and whether it is mutable.
+    fn variable(&self, name: &str) -> Option<&(Value, bool)> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    /// Runs `statements` in a scope of their own, so that the variables
+    /// they declare end with them.
+    fn execute_scoped(&mut self, statements: &[Statement]) -> DiagnosticResult<ControlFlow> {
+        self.scopes.push(HashMap::new());
+        let flow = self.execute_block(statements);
+        self.scopes.pop();
+        flow
+    }
+
+    /// Runs statements in order until one of them jumps.
+    fn execute_block(&mut self, statements: &[Statement]) -> DiagnosticResult<ControlFlow> {
+        for stmt in statements {
+            let flow = self.execute_statement(stmt)?;
+            if !matches!(flow, ControlFlow::Normal) {
+                return Ok(flow);
+            }
+        }
+        Ok(ControlFlow::Normal)
+    }
+
+    /// Runs one iteration of a loop body. Returns `Ok(None)` when the loop
+    /// should keep going, or the flow to stop it with: `Normal` for a
+    /// `break` aimed at this loop, or a jump aimed at an outer loop.
+    fn execute_iteration(
+        &mut self,
+        label: &Option<Ident>,
+        body: &[Statement],
+    ) -> DiagnosticResult<Option<ControlFlow>> {
+        let targets_this = |target: &Option<String>| match (target, label) {
+            (None, _) => true,
+            (Some(target), Some(label)) => *target == label.name,
+            (Some(_), None) => false,
+        };
+        Ok(match self.execute_scoped(body)? {
+            ControlFlow::Normal => None,
+            ControlFlow::Continue(target) if targets_this(&target) => None,
+            ControlFlow::Break(target) if targets_this(&target) => Some(ControlFlow::Normal),
+            flow => Some(flow),
+        })
+    }
+
+    fn execute_statement(&mut self, stmt: &Statement) -> DiagnosticResult<ControlFlow> {
+        match &stmt.kind {
+            StatementKind::Let {
+                name,
+                mutable,
+                ty,
+                value,
+            } => {
+                // An integer stored in a `float` variable becomes a float.
+                let float = matches!(
+                    ty.as_ref().map(|ty| &ty.kind),
+                    Some(TypeExprKind::Named { name, .. }) if name.name == "float"
+                );
+                let val = match self.evaluate_expression(value)? {
+                    Value::Number(n) if float => Value::Float(n as f64),
+                    val => val,
+                };
+                self.declare(&name.name, val, *mutable);
+            }
+            StatementKind::Assign {
+                target,
+                fields,
+                op,
+                value,
+            } => {
+                if matches!(self.variable(&target.name), Some((_, false))) {
+                    return Err(Diagnostic::error(
+                        codes::RUNTIME_ASSIGN_TO_IMMUTABLE,
+                        format!("cannot assign twice to immutable variable `{}`", target),
+                    )
+                    .with_label(target.span, "declared without `mut`"));
+                }
+                let mut val = self.evaluate_expression(value)?;
+                if let Some(op) = op {
+                    let old = self.place_mut(target, fields)?.clone();
+                    let whole = Expression::new(ExpressionKind::Error, stmt.span);
+                    val = self.evaluate_binary(&whole, op, value.span, old, val)?;
+                }
+                *self.place_mut(target, fields)? = val;
             }
-            Statement::Print { args } => {
-                let values: Vec<String> = args
+            StatementKind::Type { .. } => self.declare_type(stmt),
+            // Variants carry their own names, so enums need no runtime state.
+            StatementKind::Enum { .. } => {}
+            StatementKind::Print { args } => {
+                let values = args
                     .iter()
-                    .map(|e| {
-                        self.evaluate_expression(e)
-                            .map(|v| v.to_string())
-                            .unwrap_or_else(|_| "?".to_string())
-                    })
-                    .collect();
+                    .map(|e| self.evaluate_expression(e).map(|v| v.to_string()))
+                    .collect::<DiagnosticResult<Vec<_>>>()?;
                 println!("{}", values.join(" "));
             }
-            Statement::Function { .. } => {
+            StatementKind::Function { .. } => {
                 // Function definitions are stored for later
             }
-            Statement::If {
+            StatementKind::If {
                 condition,
                 then_body,
+                else_ifs,
                 else_body,
             } => {
                 let cond_val = self.evaluate_expression(condition)?;
-                if cond_val != 0 {
-                    for stmt in then_body {
-                        self.execute_statement(stmt)?;
+                if cond_val.as_bool() {
+                    return self.execute_scoped(then_body);
+                }
+                for else_if in else_ifs {
+                    if self.evaluate_expression(&else_if.condition)?.as_bool() {
+                        return self.execute_scoped(&else_if.body);
+                    }
+                }
+                if let Some(else_body) = else_body {
+                    return self.execute_scoped(else_body);
+                }
+            }
+            StatementKind::For {
+                label,
+                var,
+                iterable,
+                body,
+            } => {
+                let items: Box<dyn Iterator<Item = Value>> =
+                    match self.evaluate_expression(iterable)? {
+                        Value::Range(range) => Box::new(range.iter().map(Value::Number)),
+                        Value::List(items) => Box::new(items.into_iter()),
+                        Value::String(text) => {
+                            let chars: Vec<char> = text.chars().collect();
+                            Box::new(chars.into_iter().map(Value::Char))
+                        }
+                        other => {
+                            return Err(Diagnostic::error(
+                                codes::RUNTIME_INVALID_OPERAND,
+                                format!("cannot iterate over `{}`", other.type_name()),
+                            )
+                            .with_label(iterable.span, "expected a range, list or string"))
+                        }
+                    };
+                for item in items {
+                    self.scopes.push(HashMap::new());
+                    self.declare(&var.name, item, false);
+                    let flow = self.execute_iteration(label, body);
+                    self.scopes.pop();
+                    if let Some(flow) = flow? {
+                        return Ok(flow);
                     }
-                } else if let Some(else_body) = else_body {
-                    for stmt in else_body {
-                        self.execute_statement(stmt)?;
+                }
+            }
+            StatementKind::While {
+                label,
+                condition,
+                body,
+            } => {
+                while self.evaluate_expression(condition)?.as_bool() {
+                    if let Some(flow) = self.execute_iteration(label, body)? {
+                        return Ok(flow);
                     }
                 }
             }
-            Statement::For { var, iterable, body } => {
-                // Simplified: assume iterable is a number range
-                let count = self.evaluate_expression(iterable)?;
-                for i in 0..count {
-                    self.variables.insert(var.clone(), i);
-                    for stmt in body {
-                        self.execute_statement(stmt)?;
+            StatementKind::Match { subject, arms } => {
+                let value = self.evaluate_expression(subject)?;
+                for arm in arms {
+                    let mut bindings = Vec::new();
+                    if !self.match_pattern(&arm.pattern, &value, &mut bindings)? {
+                        continue;
+                    }
+                    // The bindings are only visible in the guard and the arm.
+                    self.scopes.push(HashMap::new());
+                    for (name, value) in bindings {
+                        self.declare(&name, value, false);
+                    }
+                    let guard = match &arm.guard {
+                        Some(guard) => self.evaluate_expression(guard).map(|v| v.as_bool()),
+                        None => Ok(true),
+                    };
+                    let flow = match guard {
+                        Ok(true) => Some(self.execute_block(&arm.body)),
+                        Ok(false) => None,
+                        Err(diagnostic) => Some(Err(diagnostic)),
+                    };
+                    self.scopes.pop();
+                    if let Some(flow) = flow {
+                        return flow;
                     }
                 }
+                return Err(Diagnostic::error(
+                    codes::NO_MATCHING_ARM,
+                    format!("no `match` arm matches `{}`", value),
+                )
+                .with_label(subject.span, "this value is not covered"));
+            }
+            StatementKind::Break { label } => {
+                return Ok(ControlFlow::Break(label.as_ref().map(|l| l.name.clone())));
             }
-            Statement::Return { .. } => {
+            StatementKind::Continue { label } => {
+                return Ok(ControlFlow::Continue(label.as_ref().map(|l| l.name.clone())));
+            }
+            StatementKind::Return { .. } => {
                 // Return handling
             }
-            Statement::Expression(expr) => {
+            StatementKind::Expression(expr) => {
                 self.evaluate_expression(expr)?;
             }
+            // Error nodes only exist in programs that failed to parse, and
+            // those never reach the interpreter.
+            StatementKind::Comment(_) | StatementKind::Error => {}
         }
-        Ok(())
+        Ok(ControlFlow::Normal)
     }
 
-    fn evaluate_expression(&self, expr: &Expression) -> Result<i64> {
-        match expr {
-            Expression::Number(n) => Ok(*n),
-            Expression::Variable(v) => {
-                self.variables
-                    .get(v)
-                    .copied()
-                    .ok_or_else(|| anyhow::anyhow!("Undefined variable: {}", v))
+    fn evaluate_expression(&self, expr: &Expression) -> DiagnosticResult<Value> {
+        match &expr.kind {
+            ExpressionKind::Number { value, .. } => Ok(Value::Number(*value)),
+            ExpressionKind::Float { value, .. } => Ok(Value::Float(*value)),
+            ExpressionKind::Bool(value) => Ok(Value::Boolean(*value)),
+            ExpressionKind::None => Ok(Value::None),
+            ExpressionKind::Char { value, .. } => Ok(Value::Char(*value)),
+            ExpressionKind::String { value, .. } => Ok(Value::String(value.clone())),
+            ExpressionKind::Interpolated { parts, .. } => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        InterpolatedPart::Literal(literal) => text.push_str(literal),
+                        InterpolatedPart::Expression(expr) => {
+                            text.push_str(&self.evaluate_expression(expr)?.to_string())
+                        }
+                    }
+                }
+                Ok(Value::String(text))
             }
-            Expression::Binary { left, op, right } => {
+            ExpressionKind::Variable(v) => {
+                self.variable(v)
+                    .map(|(value, _)| value.clone())
+                    .ok_or_else(|| {
+                        Diagnostic::error(
+                            codes::RUNTIME_UNDEFINED_VARIABLE,
+                            format!("undefined variable `{}`", v),
+                        )
+                        .with_label(expr.span, "no value bound to this name")
+                    })
+            }
+            ExpressionKind::Binary { left, op, right } => {
                 let left_val = self.evaluate_expression(left)?;
-                let right_val = self.evaluate_expression(right)?;
-                Ok(match op {
-                    BinaryOp::Add => left_val + right_val,
-                    BinaryOp::Subtract => left_val - right_val,
-                    BinaryOp::Multiply => left_val * right_val,
-                    BinaryOp::Divide => {
-                        if right_val == 0 {
-                            return Err(anyhow::anyhow!("Division by zero"));
-                        }
-                        left_val / right_val
+                // The right side of `&&` and `||` only runs when the left
+                // does not decide the result, so it may rely on the left.
+                match (op, left_val.as_bool()) {
+                    (BinaryOp::And, false) => Ok(Value::Boolean(false)),
+                    (BinaryOp::Or, true) => Ok(Value::Boolean(true)),
+                    _ => {
+                        let right_val = self.evaluate_expression(right)?;
+                        self.evaluate_binary(expr, op, right.span, left_val, right_val)
                     }
-                    BinaryOp::Greater => (left_val > right_val) as i64,
-                    BinaryOp::Less => (left_val < right_val) as i64,
-                    BinaryOp::Equal => (left_val == right_val) as i64,
-                })
+                }
             }
-            Expression::Unary { op, expr } => {
-                let val = self.evaluate_expression(expr)?;
-                Ok(match op {
-                    UnaryOp::Negate => -val,
-                })
+            ExpressionKind::Unary { op, expr: operand } => {
+                let val = self.evaluate_expression(operand)?;
+                match (op, val) {
+                    (UnaryOp::Negate, Value::Number(n)) => n
+                        .checked_neg()
+                        .map(Value::Number)
+                        .ok_or_else(|| overflow("negate", &format!("-({})", n), expr.span)),
+                    (UnaryOp::Negate, Value::Float(f)) => Ok(Value::Float(-f)),
+                    (UnaryOp::Not, val) => Ok(Value::Boolean(!val.as_bool())),
+                    (UnaryOp::Negate, val) => Err(Diagnostic::error(
+                        codes::RUNTIME_INVALID_OPERAND,
+                        format!("cannot negate `{}`", val.type_name()),
+                    )
+                    .with_label(expr.span, "expected a number")),
+                }
             }
-            Expression::Call { name, args } => {
+            ExpressionKind::Call { name, args } => {
                 // Built-in functions
-                match name.as_str() {
+                match name.name.as_str() {
                     "print" => {
                         for arg in args {
                             let val = self.evaluate_expression(arg)?;
                             print!("{} ", val);
                         }
                         println!();
-                        Ok(0)
+                        Ok(Value::None)
                     }
-                    _ => Err(anyhow::anyhow!("Unknown function: {}", name)),
+                    "len" | "get" => {
+                        let values = args
+                            .iter()
+                            .map(|arg| self.evaluate_expression(arg))
+                            .collect::<DiagnosticResult<Vec<_>>>()?;
+                        match (name.name.as_str(), values.as_slice()) {
+                            ("len", [Value::List(items)]) => {
+                                tabula_std::collections::len(items).map_err(|err| {
+                                    let message = err.to_string();
+                                    Diagnostic::error(codes::RUNTIME_INVALID_OPERAND, message)
+                                        .with_label(expr.span, "in this call")
+                                })
+                            }
+                            ("get", [Value::List(items), Value::Number(i)]) => {
+                                tabula_std::collections::get(items, *i).map_err(|_| {
+                                    Diagnostic::error(
+                                        codes::INDEX_OUT_OF_BOUNDS,
+                                        format!(
+                                            "index {} is out of bounds for length {}",
+                                            i,
+                                            items.len()
+                                        ),
+                                    )
+                                    .with_label(args[1].span, "no item at this index")
+                                })
+                            }
+                            _ => Err(Diagnostic::error(
+                                codes::RUNTIME_INVALID_OPERAND,
+                                format!("invalid arguments to `{}`", name),
+                            )
+                            .with_label(expr.span, "expected a list and, for `get`, an index")),
+                        }
+                    }
+                    _ => Err(Diagnostic::error(
+                        codes::UNKNOWN_FUNCTION,
+                        format!("unknown function `{}`", name),
+                    )
+                    .with_label(name.span, "no built-in with this name")),
+                }
+            }
+            ExpressionKind::Range {
+                start,
+                end,
+                inclusive,
+                step,
+            } => {
+                let bound = |expr: &Expression| match self.evaluate_expression(expr)? {
+                    Value::Number(n) => Ok(n),
+                    other => Err(Diagnostic::error(
+                        codes::RUNTIME_INVALID_OPERAND,
+                        format!("range bounds must be `int`, found `{}`", other.type_name()),
+                    )
+                    .with_label(expr.span, "expected `int`")),
+                };
+                let step_value = match step {
+                    Some(step) => bound(step)?,
+                    None => 1,
+                };
+                if step_value == 0 {
+                    let span = step.as_ref().map_or(expr.span, |step| step.span);
+                    return Err(Diagnostic::error(codes::ZERO_STEP, "range step cannot be zero")
+                        .with_label(span, "this evaluates to 0"));
                 }
+                Ok(Value::Range(Range {
+                    start: bound(start)?,
+                    end: bound(end)?,
+                    inclusive: *inclusive,
+                    step: step_value,
+                }))
             }
-            Expression::String(_) | Expression::Float(_) => {
-                Err(anyhow::anyhow!("Unsupported expression type in interpreter"))
+            ExpressionKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                if self.evaluate_expression(condition)?.as_bool() {
+                    self.evaluate_expression(then_value)
+                } else {
+                    self.evaluate_expression(else_value)
+                }
+            }
+            ExpressionKind::Tuple(items) => Ok(Value::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.evaluate_expression(item))
+                    .collect::<DiagnosticResult<Vec<_>>>()?,
+            )),
+            ExpressionKind::List(items) => Ok(Value::List(
+                items
+                    .iter()
+                    .map(|item| self.evaluate_expression(item))
+                    .collect::<DiagnosticResult<Vec<_>>>()?,
+            )),
+            ExpressionKind::Map(entries) => {
+                // A repeated key keeps its first position and its last value.
+                let mut map: Vec<(Value, Value)> = Vec::new();
+                for (key, value) in entries {
+                    let key = self.evaluate_expression(key)?;
+                    let value = self.evaluate_expression(value)?;
+                    match map.iter_mut().find(|(k, _)| *k == key) {
+                        Some(entry) => entry.1 = value,
+                        None => map.push((key, value)),
+                    }
+                }
+                Ok(Value::Map(map))
+            }
+            ExpressionKind::Index { target, index } => {
+                let target_value = self.evaluate_expression(target)?;
+                let index_value = self.evaluate_expression(index)?;
+                let out_of_bounds = |len: usize| {
+                    Diagnostic::error(
+                        codes::INDEX_OUT_OF_BOUNDS,
+                        format!("index {} is out of bounds for length {}", index_value, len),
+                    )
+                    .with_label(index.span, "no item at this index")
+                };
+                match (&target_value, &index_value) {
+                    (Value::List(items), Value::Number(i)) => usize::try_from(*i)
+                        .ok()
+                        .and_then(|i| items.get(i).cloned())
+                        .ok_or_else(|| out_of_bounds(items.len())),
+                    (Value::String(text), Value::Number(i)) => usize::try_from(*i)
+                        .ok()
+                        .and_then(|i| text.chars().nth(i))
+                        .map(Value::Char)
+                        .ok_or_else(|| out_of_bounds(text.chars().count())),
+                    (Value::Map(entries), key) => entries
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, v)| v.clone())
+                        .ok_or_else(|| {
+                            Diagnostic::error(
+                                codes::MISSING_KEY,
+                                format!("no entry for key `{}`", key),
+                            )
+                            .with_label(index.span, "key not found in the map")
+                        }),
+                    _ => Err(Diagnostic::error(
+                        codes::RUNTIME_INVALID_OPERAND,
+                        format!(
+                            "cannot index `{}` with `{}`",
+                            target_value.type_name(),
+                            index_value.type_name()
+                        ),
+                    )
+                    .with_label(expr.span, "unsupported operand types")),
+                }
+            }
+            ExpressionKind::Slice {
+                target,
+                start,
+                end,
+                inclusive,
+            } => {
+                let target_value = self.evaluate_expression(target)?;
+                let bound = |bound: &Option<Box<Expression>>| match bound {
+                    Some(bound) => match self.evaluate_expression(bound)? {
+                        Value::Number(n) => Ok(Some(n)),
+                        other => Err(Diagnostic::error(
+                            codes::RUNTIME_INVALID_OPERAND,
+                            format!("slice bounds must be `int`, found `{}`", other.type_name()),
+                        )
+                        .with_label(bound.span, "expected `int`")),
+                    },
+                    None => Ok(None),
+                };
+                let (start, end) = (bound(start)?, bound(end)?);
+                let range = |len: usize| {
+                    let from = start.unwrap_or(0);
+                    // `..=` past the largest `int` is out of bounds anyway.
+                    let to = end.map_or(len as i64, |end| end.saturating_add(*inclusive as i64));
+                    if 0 <= from && from <= to && to <= len as i64 {
+                        Ok(from as usize..to as usize)
+                    } else {
+                        Err(Diagnostic::error(
+                            codes::INDEX_OUT_OF_BOUNDS,
+                            format!("slice {}..{} is out of bounds for length {}", from, to, len),
+                        )
+                        .with_label(expr.span, "slice out of bounds"))
+                    }
+                };
+                match target_value {
+                    Value::List(items) => Ok(Value::List(items[range(items.len())?].to_vec())),
+                    Value::String(text) => {
+                        let chars: Vec<char> = text.chars().collect();
+                        Ok(Value::String(chars[range(chars.len())?].iter().collect()))
+                    }
+                    other => Err(Diagnostic::error(
+                        codes::RUNTIME_INVALID_OPERAND,
+                        format!("cannot slice `{}`", other.type_name()),
+                    )
+                    .with_label(target.span, "expected a list or string")),
+                }
+            }
+            ExpressionKind::Record { name, fields } => {
+                let Some(order) = self.records.get(&name.name) else {
+                    return Err(Diagnostic::error(
+                        codes::RUNTIME_UNDEFINED_TYPE,
+                        format!("undefined type `{}`", name),
+                    )
+                    .with_label(name.span, "no record type with this name"));
+                };
+                let order = order.clone();
+                let mut values = Vec::new();
+                for (field, value) in fields {
+                    values.push((field.name.clone(), self.evaluate_expression(value)?));
+                }
+                values.sort_by_key(|(field, _)| order.iter().position(|f| f == field));
+                Ok(Value::Record {
+                    name: name.name.clone(),
+                    fields: values,
+                })
+            }
+            ExpressionKind::Variant { name, args } => Ok(Value::Variant {
+                name: name.name.clone(),
+                fields: args
+                    .iter()
+                    .map(|arg| self.evaluate_expression(arg))
+                    .collect::<DiagnosticResult<Vec<_>>>()?,
+            }),
+            ExpressionKind::Field { target, field } => {
+                let target_value = self.evaluate_expression(target)?;
+                let item = match &target_value {
+                    Value::Tuple(items) => field
+                        .name
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|n| items.get(n).cloned()),
+                    Value::Record { fields, .. } => fields
+                        .iter()
+                        .find(|(name, _)| *name == field.name)
+                        .map(|(_, value)| value.clone()),
+                    _ => None,
+                };
+                item.ok_or_else(|| {
+                    Diagnostic::error(
+                        codes::RUNTIME_INVALID_OPERAND,
+                        format!("no field `{}` on `{}`", field, target_value.type_name()),
+                    )
+                    .with_label(field.span, "unknown field")
+                })
+            }
+            ExpressionKind::Error => {
+                Err(Diagnostic::error(
+                    codes::UNSUPPORTED_EXPRESSION,
+                    "the interpreter cannot evaluate this expression yet",
+                )
+                .with_label(expr.span, "unsupported expression"))
+            }
+        }
+    }
+
+    /// Tests `value` against `pattern`, collecting the names it binds.
+    fn match_pattern(
+        &self,
+        pattern: &Pattern,
+        value: &Value,
+        bindings: &mut Vec<(String, Value)>,
+    ) -> DiagnosticResult<bool> {
+        match &pattern.kind {
+            PatternKind::Wildcard => Ok(true),
+            PatternKind::Binding(name) => {
+                bindings.push((name.clone(), value.clone()));
+                Ok(true)
+            }
+            PatternKind::Literal(expr) => {
+                Ok(values_equal(&self.evaluate_expression(expr)?, value))
+            }
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let start = self.evaluate_expression(start)?;
+                let end = self.evaluate_expression(end)?;
+                Ok(match (&start, value, &end) {
+                    (Value::Number(low), Value::Number(n), Value::Number(high)) => {
+                        low <= n && if *inclusive { n <= high } else { n < high }
+                    }
+                    (Value::Char(low), Value::Char(c), Value::Char(high)) => {
+                        low <= c && if *inclusive { c <= high } else { c < high }
+                    }
+                    _ => false,
+                })
+            }
+            PatternKind::Tuple(items) => match value {
+                Value::Tuple(values) if values.len() == items.len() => {
+                    self.match_all(items, values, bindings)
+                }
+                _ => Ok(false),
+            },
+            PatternKind::Variant { name, fields } => match value {
+                Value::Variant {
+                    name: variant,
+                    fields: values,
+                } if *variant == name.name && values.len() == fields.len() => {
+                    self.match_all(fields, values, bindings)
+                }
+                _ => Ok(false),
+            },
+        }
+    }
+
+    /// Tests each value against the pattern in the same position.
+    fn match_all(
+        &self,
+        patterns: &[Pattern],
+        values: &[Value],
+        bindings: &mut Vec<(String, Value)>,
+    ) -> DiagnosticResult<bool> {
+        for (pattern, value) in patterns.iter().zip(values) {
+            if !self.match_pattern(pattern, value, bindings)? {
+                return Ok(false);
             }
         }
+        Ok(true)
+    }
+
+    /// Applies a binary operator. Integers stay integers; mixing in a float
+    /// makes the result a float.
+    fn evaluate_binary(
+        &self,
+        expr: &Expression,
+        op: &BinaryOp,
+        right_span: Span,
+        left: Value,
+        right: Value,
+    ) -> DiagnosticResult<Value> {
+        let invalid = || {
+            Diagnostic::error(
+                codes::RUNTIME_INVALID_OPERAND,
+                format!(
+                    "cannot apply `{}` to `{}` and `{}`",
+                    op.format(),
+                    left.type_name(),
+                    right.type_name()
+                ),
+            )
+            .with_label(expr.span, "unsupported operand types")
+        };
+
+        match op {
+            BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
+                match (&left, &right) {
+                    (Value::Number(a), Value::Number(b)) => {
+                        let (result, verb) = match op {
+                            BinaryOp::Add => (a.checked_add(*b), "add"),
+                            BinaryOp::Subtract => (a.checked_sub(*b), "subtract"),
+                            BinaryOp::Multiply => (a.checked_mul(*b), "multiply"),
+                            _ => {
+                                if *b == 0 {
+                                    return Err(Diagnostic::error(
+                                        codes::DIVISION_BY_ZERO,
+                                        "attempt to divide by zero",
+                                    )
+                                    .with_label(expr.span, "division by zero")
+                                    .with_secondary(right_span, "this evaluates to 0"));
+                                }
+                                (a.checked_div(*b), "divide")
+                            }
+                        };
+                        result.map(Value::Number).ok_or_else(|| {
+                            let result = format!("{} {} {}", a, op.format(), b);
+                            overflow(verb, &result, expr.span)
+                        })
+                    }
+                    (Value::Number(_) | Value::Float(_), Value::Number(_) | Value::Float(_)) => {
+                        let (a, b) = (left.as_float().unwrap(), right.as_float().unwrap());
+                        Ok(Value::Float(match op {
+                            BinaryOp::Add => a + b,
+                            BinaryOp::Subtract => a - b,
+                            BinaryOp::Multiply => a * b,
+                            _ => a / b,
+                        }))
+                    }
+                    _ => Err(invalid()),
+                }
+            }
+            BinaryOp::Equal => Ok(Value::Boolean(values_equal(&left, &right))),
+            BinaryOp::NotEqual => Ok(Value::Boolean(!values_equal(&left, &right))),
+            BinaryOp::Greater | BinaryOp::Less | BinaryOp::GreaterEqual | BinaryOp::LessEqual => {
+                let ordering = match (&left, &right) {
+                    (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+                    (Value::Number(_) | Value::Float(_), Value::Number(_) | Value::Float(_)) => {
+                        left.as_float().partial_cmp(&right.as_float())
+                    }
+                    (Value::Char(a), Value::Char(b)) => a.partial_cmp(b),
+                    (Value::String(a), Value::String(b)) => a.partial_cmp(b),
+                    _ => return Err(invalid()),
+                };
+                Ok(Value::Boolean(match op {
+                    BinaryOp::Greater => ordering.is_some_and(|o| o.is_gt()),
+                    BinaryOp::Less => ordering.is_some_and(|o| o.is_lt()),
+                    BinaryOp::GreaterEqual => ordering.is_some_and(|o| o.is_ge()),
+                    _ => ordering.is_some_and(|o| o.is_le()),
+                }))
+            }
+            BinaryOp::And => Ok(Value::Boolean(left.as_bool() && right.as_bool())),
+            BinaryOp::Or => Ok(Value::Boolean(left.as_bool() || right.as_bool())),
+        }
+    }
+}
+
+/// The error for integer arithmetic whose result, written as `result`, does
+/// not fit in an `int`.
+fn overflow(verb: &str, result: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(
+        codes::INTEGER_OVERFLOW,
+        format!("attempt to {} with overflow", verb),
+    )
+    .with_label(span, format!("`{}` does not fit in `int`", result))
+}
+
+/// Equality across values, treating `1` and `1.0` as equal.
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(_), Value::Float(_)) | (Value::Float(_), Value::Number(_)) => {
+            left.as_float() == right.as_float()
+        }
+        _ => left == right,
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::typechecker::TypeChecker;
+    use std::process::Command;
+
+    /// Checks `source` and lowers it to C.
+    fn generate(source: &str) -> Result<String> {
+        let tokens = Lexer::new().tokenize(source)?;
+        let program = Parser::new().parse(tokens)?;
+        let mut checker = TypeChecker::new();
+        checker.check(&program)?;
+        Codegen::new().generate_c(&program.without_shadowing(), checker.types())
+    }
+
+    /// Builds the C for `source` with the system C compiler, runs it and
+    /// returns what it printed. `name` keeps the files of each test apart.
+    fn run(name: &str, source: &str) -> String {
+        let code = generate(source).expect("source should lower to C");
+        let dir = std::env::temp_dir().join(format!("tabula-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (c_file, binary) = (dir.join("main.c"), dir.join("main"));
+        std::fs::write(&c_file, &code).unwrap();
+        let cc = Command::new("cc")
+            .arg("-w")
+            .arg(&c_file)
+            .arg("-o")
+            .arg(&binary)
+            .output()
+            .expect("a C compiler should be installed as `cc`");
+        assert!(
+            cc.status.success(),
+            "cc rejected the C:\n{}\n{}",
+            code,
+            String::from_utf8_lossy(&cc.stderr)
+        );
+        let output = Command::new(&binary).output().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// The code of the error that interpreting `source` reports.
+    fn runtime_error(source: &str) -> &'static str {
+        let tokens = Lexer::new().tokenize(source).expect("source should lex");
+        let program = Parser::new().parse(tokens).expect("source should parse");
+        let error = Interpreter::new()
+            .interpret(&program)
+            .expect_err("source should fail");
+        error.code
+    }
+
+    /// The code of the error that lowering `source` reports.
+    fn error(source: &str) -> &'static str {
+        let error = generate(source).expect_err("source should not lower to C");
+        error
+            .downcast_ref::<Diagnostic>()
+            .expect("a diagnostic")
+            .code
+    }
+
+    #[test]
+    fn functions_are_called_and_return() {
+        let source = "func add a:int  b:int -> int\n\treturn a + b\n\nprint add 2  3\n";
+        assert_eq!(run("call", source), "5\n");
+    }
+
+    #[test]
+    fn functions_can_be_called_above_their_declaration() {
+        let source = "greet \"you\"\n\nfunc greet name:string\n\tprint \"hi\"  name\n";
+        assert_eq!(run("hoist", source), "hi you\n");
+    }
+
+    #[test]
+    fn interpolated_strings_format_their_parts() {
+        let source = "let n  3\nlet ok  true\nprint \"{n} of {ok} at 100%\"\n";
+        assert_eq!(run("interpolate", source), "3 of true at 100%\n");
+    }
+
+    #[test]
+    fn lists_are_indexed_sliced_and_iterated() {
+        let source = "let xs  [1  2  3]\nprint xs[0]  (len xs)  (get xs  2)\n\
+                      for x in xs[1..]\n\tprint x * 10\n";
+        assert_eq!(run("lists", source), "1 3 3\n20\n30\n");
+    }
+
+    #[test]
+    fn labeled_loops_over_lists_break_out() {
+        let source = "outer: for row in [[1  2]  [3  4]]\n\tfor x in row\n\
+                      \t\tif x == 3\n\t\t\tbreak outer\n\t\tprint x\n";
+        assert_eq!(run("labels", source), "1\n2\n");
+    }
+
+    #[test]
+    fn tuples_are_structs() {
+        let source = "let pair  \"total\"  4\nprint pair.0  pair.1\n";
+        assert_eq!(run("tuples", source), "total 4\n");
+    }
+
+    #[test]
+    fn optional_values_print_as_none_or_their_value() {
+        let source = "let a:int?  none\nlet b:int?  3\nprint a  b  \"{b}\"\n";
+        assert_eq!(run("optionals", source), "none 3 3\n");
+    }
+
+    #[test]
+    fn generic_functions_get_an_instance_per_type_they_are_called_with() {
+        let source = "type Box[T]\n\tvalue:T\nfunc first xs\n\treturn xs[0]\n\
+                      func unbox b\n\treturn b.value\nfunc wrap x\n\treturn Box value: x\n\
+                      func twice x\n\treturn unbox (wrap (first [x  x]))\n\
+                      print (first [1  2])  (first [\"a\"  \"b\"])\n\
+                      print (unbox (Box value: 3))  (unbox (Box value: \"c\"))  (twice 4)\n";
+        assert_eq!(run("generic_functions", source), "1 a\n3 c 4\n");
+        let code = generate(source).unwrap();
+        for instance in ["first_int(", "first_string(", "unbox_int(", "unbox_string("] {
+            assert!(code.contains(instance), "{}", instance);
+        }
+    }
+
+    #[test]
+    fn shadowing_declarations_get_names_of_their_own() {
+        let source = "let x  1\nlet x  \"one\"\nlet y  2\nif y > 1\n\tlet y  y * 10\n\
+                      \tprint y\nfor x in 0..1\n\tprint x\nprint x  y\n";
+        assert_eq!(run("shadowing", source), "20\n0\none 2\n");
+        let code = generate(source).unwrap();
+        assert!(code.contains("const char* x__1 = \"one\";"), "{}", code);
+        assert!(!code.contains("const const"), "{}", code);
+    }
+
+    #[test]
+    fn list_items_are_built_as_the_type_they_are_given_as() {
+        let source = "let a:List[int?]  [1  none]\nlet b:List[float]  [1  2]\n\
+                      let c  [1  2.5]\nprint a  b[0] / 2  c\n";
+        assert_eq!(run("list_conversions", source), "[1, none] 0.5 [1, 2.5]\n");
+        assert_eq!(
+            error("let xs  [1]\nlet ys:List[float]  xs\n"),
+            codes::UNSUPPORTED_BY_BACKEND
+        );
+    }
+
+    #[test]
+    fn records_and_variants_print_as_the_interpreter_prints_them() {
+        let source = "type Point\n\tx:int\n\ty:int\nenum Shape\n\tCircle float\n\
+                      \tSquare Point  bool\n\tEmpty\n\
+                      let p  Point x: 1  y: -2\n\
+                      let shapes  [(Circle 2.5)  (Square p  true)  Empty]\n\
+                      print p\nprint \"{shapes}\"  (\"a\"  [1  2])\n";
+        assert_eq!(
+            run("records", source),
+            "Point { x: 1, y: -2 }\n\
+             [Circle(2.5), Square(Point { x: 1, y: -2 }, true), Empty] (a, [1, 2])\n"
+        );
+    }
+
+    #[test]
+    fn string_patterns_compare_characters() {
+        let source = "for w in [\"yes\"  \"no\"  \"maybe\"]\n\tmatch w\n\t\t\"yes\"\n\
+                      \t\t\tprint 1\n\t\t\"no\"\n\t\t\tprint 2\n\t\t_\n\t\t\tprint w\n";
+        assert_eq!(run("string_patterns", source), "1\n2\nmaybe\n");
+    }
+
+    #[test]
+    fn tuple_patterns_test_and_bind_items() {
+        let source = "let pair  1  \"b\"\nmatch pair\n\t0  _\n\t\tprint \"zero\"\n\
+                      \t1  s\n\t\tprint \"one\"  s\n\t_\n\t\tprint \"other\"\n";
+        assert_eq!(run("tuple_patterns", source), "one b\n");
+    }
+
+    #[test]
+    fn strings_compare_by_their_characters() {
+        let source = "let a  \"ab\"\nlet b:string?  \"ab\"\n\
+                      print a == \"ab\"  a < \"b\"  b == a  b != \"ab\"\n";
+        assert_eq!(run("string_comparisons", source), "true true true false\n");
+    }
+
+    #[test]
+    fn maps_are_reported() {
+        assert_eq!(
+            error("let m  {\"a\": 1}\nprint m[\"a\"]\n"),
+            codes::UNSUPPORTED_BY_BACKEND
+        );
+    }
+
+    #[test]
+    fn integer_overflow_is_a_runtime_error() {
+        let max = "let big  9223372036854775807\n";
+        let min = "let small  -9223372036854775807 - 1\n";
+        for source in [
+            format!("{}print big * 2\n", max),
+            format!("{}print big + 1\n", max),
+            "let mut x  9223372036854775807\nx += 1\n".to_string(),
+            format!("{}print small - 1\n", min),
+            format!("{}print small / -1\n", min),
+            format!("{}print -small\n", min),
+        ] {
+            assert_eq!(
+                runtime_error(&source),
+                codes::INTEGER_OVERFLOW,
+                "{}",
+                source
+            );
+        }
+        assert_eq!(runtime_error("print 1 / 0\n"), codes::DIVISION_BY_ZERO);
+    }
+
+    #[test]
+    fn logical_operators_skip_the_right_side_once_the_left_decides() {
+        for source in [
+            "let a:int?  none\nif a != none && a > 2\n\tprint a\n",
+            "let xs  [5]\nlet i  3\nlet first  i < 1 && xs[i] > 0\n",
+            "let xs  [5]\nlet i  3\nlet past  i >= 1 || xs[i] > 0\n",
+        ] {
+            let tokens = Lexer::new().tokenize(source).expect("source should lex");
+            let program = Parser::new().parse(tokens).expect("source should parse");
+            let result = Interpreter::new().interpret(&program);
+            assert!(result.is_ok(), "{}: {:?}", source, result);
+        }
+        let source = "let xs  [5]\nprint true && xs[3] > 0\n";
+        assert_eq!(runtime_error(source), codes::INDEX_OUT_OF_BOUNDS);
+    }
+}
@@ -0,0 +1,410 @@
+use crate::source::{SourceMap, Span};
+use std::fmt;
+
+/// Stable diagnostic codes. The letter names the stage that reports the
+/// problem: `L`exer, `P`arser, `T`ypechecker, `R`untime or `B`ackend. Codes
+/// are never reused once published.
+pub mod codes {
+    pub const UNEXPECTED_CHARACTER: &str = "L0001";
+    pub const UNTERMINATED_STRING: &str = "L0002";
+    pub const INCONSISTENT_INDENTATION: &str = "L0003";
+    pub const MISPLACED_TAB: &str = "L0004";
+    pub const NUMBER_OUT_OF_RANGE: &str = "L0005";
+    pub const INVALID_NUMBER: &str = "L0006";
+    pub const INVALID_ESCAPE: &str = "L0007";
+    pub const INVALID_INTERPOLATION: &str = "L0008";
+    pub const INVALID_MULTILINE_STRING: &str = "L0009";
+    pub const INVALID_CHAR: &str = "L0010";
+
+    pub const UNEXPECTED_TOKEN: &str = "P0001";
+    pub const EXPECTED_NEWLINE: &str = "P0002";
+    pub const EXPECTED_SPACE: &str = "P0003";
+    pub const EXPECTED_IDENTIFIER: &str = "P0004";
+    pub const EXPECTED_TOKEN: &str = "P0005";
+    pub const UNEXPECTED_INDENT: &str = "P0006";
+    pub const EXTRA_SPACES: &str = "P0007";
+    pub const LOOP_EXIT_OUTSIDE_LOOP: &str = "P0008";
+    pub const UNDECLARED_LABEL: &str = "P0009";
+
+    pub const UNDEFINED_VARIABLE: &str = "T0001";
+    pub const UNDEFINED_FUNCTION: &str = "T0002";
+    pub const ARGUMENT_COUNT: &str = "T0003";
+    pub const TYPE_MISMATCH: &str = "T0004";
+    pub const NON_BOOLEAN_CONDITION: &str = "T0005";
+    pub const INVALID_OPERAND: &str = "T0006";
+    pub const NOT_ITERABLE: &str = "T0007";
+    pub const UNDEFINED_VARIANT: &str = "T0008";
+    pub const NON_EXHAUSTIVE_MATCH: &str = "T0009";
+    pub const UNREACHABLE_PATTERN: &str = "T0010";
+    pub const ASSIGN_TO_IMMUTABLE: &str = "T0011";
+    pub const NOT_INDEXABLE: &str = "T0012";
+    pub const UNKNOWN_FIELD: &str = "T0013";
+    pub const UNDEFINED_TYPE: &str = "T0014";
+    pub const MISSING_FIELDS: &str = "T0015";
+    pub const DUPLICATE_FIELD: &str = "T0016";
+    pub const DUPLICATE_DEFINITION: &str = "T0017";
+    pub const ANNOTATION_NEEDED: &str = "T0018";
+    pub const USE_BEFORE_DECLARATION: &str = "T0019";
+    pub const UNUSED_VARIABLE: &str = "T0020";
+    pub const MISSING_RETURN: &str = "T0021";
+    pub const UNREACHABLE_CODE: &str = "T0022";
+    pub const CONSTANT_CONDITION: &str = "T0023";
+    pub const POSSIBLY_NONE: &str = "T0024";
+
+    pub const RUNTIME_UNDEFINED_VARIABLE: &str = "R0001";
+    pub const DIVISION_BY_ZERO: &str = "R0002";
+    pub const UNKNOWN_FUNCTION: &str = "R0003";
+    pub const UNSUPPORTED_EXPRESSION: &str = "R0004";
+    pub const RUNTIME_INVALID_OPERAND: &str = "R0005";
+    pub const ZERO_STEP: &str = "R0006";
+    pub const NO_MATCHING_ARM: &str = "R0007";
+    pub const RUNTIME_ASSIGN_TO_IMMUTABLE: &str = "R0008";
+    pub const INDEX_OUT_OF_BOUNDS: &str = "R0009";
+    pub const MISSING_KEY: &str = "R0010";
+    pub const RUNTIME_UNDEFINED_TYPE: &str = "R0011";
+    pub const INTEGER_OVERFLOW: &str = "R0012";
+
+    pub const UNSUPPORTED_BY_BACKEND: &str = "B0001";
+}
+
+pub type DiagnosticResult<T> = std::result::Result<T, Diagnostic>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+/// A source range highlighted by a diagnostic. Primary labels mark where the
+/// problem is; secondary labels point at related code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub fn with_label(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels
+            .iter()
+            .find(|l| l.primary)
+            .or(self.labels.first())
+            .map(|l| l.span)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity.as_str(), self.code, self.message)
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Several diagnostics reported together, such as every syntax error found
+/// in one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.split_first() {
+            Some((first, [])) => write!(f, "{}", first),
+            Some((first, rest)) => write!(f, "{} (and {} more)", first, rest.len()),
+            None => write!(f, "no diagnostics"),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+/// Renders diagnostics as rustc-style annotated source snippets.
+pub struct DiagnosticRenderer<'a> {
+    sources: &'a SourceMap,
+}
+
+impl<'a> DiagnosticRenderer<'a> {
+    pub fn new(sources: &'a SourceMap) -> Self {
+        Self { sources }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let mut out = format!("{}\n", diagnostic);
+
+        let file = diagnostic
+            .primary_span()
+            .and_then(|span| self.sources.get(span.file_id).map(|file| (span, file)));
+
+        if let Some((primary, file)) = file {
+            let mut lines: Vec<usize> = diagnostic
+                .labels
+                .iter()
+                .filter(|l| l.span.file_id == primary.file_id)
+                .map(|l| file.line_index(l.span.start))
+                .collect();
+            lines.sort_unstable();
+            lines.dedup();
+
+            let gutter = (lines.last().copied().unwrap_or(0) + 1).to_string().len();
+            let pad = " ".repeat(gutter);
+            let start = file.line_col(primary.start);
+            out.push_str(&format!(
+                "{}--> {}:{}:{}\n",
+                pad,
+                file.name(),
+                start.line + 1,
+                start.column + 1
+            ));
+            out.push_str(&format!("{} |\n", pad));
+
+            for line in lines {
+                let text = file.line_text(line);
+                out.push_str(&format!(
+                    "{:>width$} | {}\n",
+                    line + 1,
+                    expand_tabs(text),
+                    width = gutter
+                ));
+
+                let mut labels: Vec<&Label> = diagnostic
+                    .labels
+                    .iter()
+                    .filter(|l| {
+                        l.span.file_id == primary.file_id && file.line_index(l.span.start) == line
+                    })
+                    .collect();
+                labels.sort_by_key(|l| l.span.start);
+
+                for label in labels {
+                    let line_start = label.span.start - file.line_col(label.span.start).column;
+                    let column = label.span.start - line_start;
+                    let end = label.span.end.min(line_start + text.len()).max(label.span.start);
+                    let indent = expand_tabs(&text[..column]).chars().count();
+                    let width = expand_tabs(&text[column..end - line_start])
+                        .chars()
+                        .count()
+                        .max(1);
+                    let marker = if label.primary { "^" } else { "-" };
+                    let mut underline =
+                        format!("{}{}", " ".repeat(indent), marker.repeat(width));
+                    if !label.message.is_empty() {
+                        underline.push(' ');
+                        underline.push_str(&label.message);
+                    }
+                    out.push_str(&format!("{} | {}\n", pad, underline));
+                }
+            }
+
+            if !diagnostic.notes.is_empty() || diagnostic.help.is_some() {
+                out.push_str(&format!("{} |\n", pad));
+            }
+            for note in &diagnostic.notes {
+                out.push_str(&format!("{} = note: {}\n", pad, note));
+            }
+            if let Some(help) = &diagnostic.help {
+                out.push_str(&format!("{} = help: {}\n", pad, help));
+            }
+        } else {
+            for note in &diagnostic.notes {
+                out.push_str(&format!("  = note: {}\n", note));
+            }
+            if let Some(help) = &diagnostic.help {
+                out.push_str(&format!("  = help: {}\n", help));
+            }
+        }
+
+        out
+    }
+
+    /// Renders each diagnostic in turn, followed by a summary line when
+    /// more than one error was reported.
+    pub fn render_all(&self, diagnostics: &[Diagnostic]) -> String {
+        let mut out = diagnostics
+            .iter()
+            .map(|d| self.render(d))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+        if errors > 1 {
+            out.push_str(&format!("\nerror: aborting due to {} previous errors\n", errors));
+        }
+        out
+    }
+}
+
+/// Tabs are significant in Tabula sources, so snippets show them as four
+/// spaces to keep the carets aligned.
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', "    ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::FileId;
+
+    /// Renders `diagnostic` against a file named `test.tab` holding
+    /// `source`, with the labels given as byte ranges of it.
+    fn render(source: &str, diagnostic: impl FnOnce(FileId) -> Diagnostic) -> String {
+        let mut sources = SourceMap::new();
+        let file_id = sources.add_file("test.tab", source);
+        DiagnosticRenderer::new(&sources).render(&diagnostic(file_id))
+    }
+
+    #[test]
+    fn renders_labels_on_the_lines_they_point_at() {
+        let rendered = render("let x  1\nlet y  x + \"a\"\n", |file| {
+            Diagnostic::error(codes::TYPE_MISMATCH, "mismatched types")
+                .with_label(Span::new(file, 20, 23), "expected `int`, found `string`")
+                .with_secondary(Span::new(file, 7, 8), "`x` is an `int` here")
+        });
+        assert_eq!(
+            rendered,
+            "error[T0004]: mismatched types\n \
+             --> test.tab:2:12\n  \
+             |\n\
+             1 | let x  1\n  \
+             |        - `x` is an `int` here\n\
+             2 | let y  x + \"a\"\n  \
+             |            ^^^ expected `int`, found `string`\n"
+        );
+    }
+
+    #[test]
+    fn expands_tabs_before_underlining() {
+        let rendered = render("func f\n\tprint y\n", |file| {
+            Diagnostic::error(codes::UNDEFINED_VARIABLE, "cannot find `y`")
+                .with_label(Span::new(file, 14, 15), "not found in this scope")
+        });
+        assert_eq!(
+            rendered,
+            "error[T0001]: cannot find `y`\n \
+             --> test.tab:2:8\n  \
+             |\n\
+             2 |     print y\n  \
+             |           ^ not found in this scope\n"
+        );
+    }
+
+    #[test]
+    fn renders_notes_and_help_after_the_snippet() {
+        let rendered = render("let x  1\n", |file| {
+            Diagnostic::warning(codes::UNUSED_VARIABLE, "unused variable `x`")
+                .with_label(Span::new(file, 4, 5), "never read")
+                .with_note("`x` is declared here")
+                .with_help("name it `_x` if this is on purpose")
+        });
+        assert_eq!(
+            rendered,
+            "warning[T0020]: unused variable `x`\n \
+             --> test.tab:1:5\n  \
+             |\n\
+             1 | let x  1\n  \
+             |     ^ never read\n  \
+             |\n  \
+             = note: `x` is declared here\n  \
+             = help: name it `_x` if this is on purpose\n"
+        );
+        // Without a label there is no snippet to put them under.
+        let rendered = render("", |_| {
+            Diagnostic::error(codes::UNKNOWN_FUNCTION, "no function `main`")
+                .with_note("the program has no statements")
+                .with_help("add one")
+        });
+        assert_eq!(
+            rendered,
+            "error[R0003]: no function `main`\n  \
+             = note: the program has no statements\n  \
+             = help: add one\n"
+        );
+    }
+
+    #[test]
+    fn points_past_the_last_line_for_spans_beyond_the_end() {
+        let rendered = render("let x  (1", |file| {
+            Diagnostic::error(codes::EXPECTED_TOKEN, "expected `)`")
+                .with_label(Span::new(file, 40, 41), "unclosed here")
+        });
+        assert_eq!(
+            rendered,
+            "error[P0005]: expected `)`\n \
+             --> test.tab:1:10\n  \
+             |\n\
+             1 | let x  (1\n  \
+             |          ^ unclosed here\n"
+        );
+    }
+}
@@ -1,117 +1,446 @@
-use anyhow::{Context, Result};
+use crate::diagnostics::{codes, Diagnostic, DiagnosticResult};
+use crate::source::{FileId, Span};
 use std::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
-    Tab,
-    Space,
+    /// Start of a line indented one tab deeper than the line before it.
+    Indent,
+    /// End of a block: emitted once per level the indentation drops by.
+    Dedent,
+    /// A run of spaces. The length carries meaning: one space binds, two
+    /// separate items, and each extra space closes a nested call.
+    Space(usize),
     Newline,
     Word(String),
-    Number(i64),
-    Float(f64),
-    String(String),
+    Number {
+        value: i64,
+        suffix: Option<NumberSuffix>,
+        /// The literal as written, such as `0xff` or `1_000u8`.
+        raw: String,
+    },
+    Float {
+        value: f64,
+        suffix: Option<NumberSuffix>,
+        raw: String,
+    },
+    /// `true` or `false`.
+    Bool(bool),
+    /// The `none` literal.
+    None,
+    /// A character literal such as `'a'` or `'\n'`.
+    Char {
+        value: char,
+        raw: String,
+    },
+    /// A string literal without interpolations.
+    String {
+        value: String,
+        /// The literal as written, quotes and any `r` prefix included.
+        raw: String,
+    },
+    /// A string literal containing `{expr}` interpolations.
+    InterpolatedString {
+        parts: Vec<StringPart>,
+        raw: String,
+    },
+    Operator(Operator),
+    Punct(Punct),
     Eof,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+    Equal,
+    NotEqual,
+    Assign,
+    PlusAssign,
+    MinusAssign,
+    StarAssign,
+    SlashAssign,
+    And,
+    Or,
+    Not,
+}
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Plus => "+",
+            Operator::Minus => "-",
+            Operator::Star => "*",
+            Operator::Slash => "/",
+            Operator::Greater => ">",
+            Operator::Less => "<",
+            Operator::GreaterEqual => ">=",
+            Operator::LessEqual => "<=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Assign => "=",
+            Operator::PlusAssign => "+=",
+            Operator::MinusAssign => "-=",
+            Operator::StarAssign => "*=",
+            Operator::SlashAssign => "/=",
+            Operator::And => "&&",
+            Operator::Or => "||",
+            Operator::Not => "!",
+        }
+    }
+}
+
+/// A piece of an interpolated string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    /// Text between interpolations, with escapes already expanded.
+    Literal(String),
+    /// The tokens of an interpolated expression, ended by an `Eof` at the
+    /// closing `}`.
+    Code(Vec<TokenWithPos>),
+}
+
+/// Type suffix written after a numeric literal, such as the `u8` in `10u8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberSuffix {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl NumberSuffix {
+    pub const ALL: [NumberSuffix; 10] = [
+        NumberSuffix::I8,
+        NumberSuffix::I16,
+        NumberSuffix::I32,
+        NumberSuffix::I64,
+        NumberSuffix::U8,
+        NumberSuffix::U16,
+        NumberSuffix::U32,
+        NumberSuffix::U64,
+        NumberSuffix::F32,
+        NumberSuffix::F64,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NumberSuffix::I8 => "i8",
+            NumberSuffix::I16 => "i16",
+            NumberSuffix::I32 => "i32",
+            NumberSuffix::I64 => "i64",
+            NumberSuffix::U8 => "u8",
+            NumberSuffix::U16 => "u16",
+            NumberSuffix::U32 => "u32",
+            NumberSuffix::U64 => "u64",
+            NumberSuffix::F32 => "f32",
+            NumberSuffix::F64 => "f64",
+        }
+    }
+
+    pub fn from_name(text: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|suffix| suffix.as_str() == text)
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, NumberSuffix::F32 | NumberSuffix::F64)
+    }
+
+    /// Largest integer literal of this type. Integers are 64-bit signed at
+    /// runtime, so `u64` literals stop at `i64::MAX` too.
+    fn max_int(&self) -> i64 {
+        match self {
+            NumberSuffix::I8 => i8::MAX as i64,
+            NumberSuffix::I16 => i16::MAX as i64,
+            NumberSuffix::I32 => i32::MAX as i64,
+            NumberSuffix::U8 => u8::MAX as i64,
+            NumberSuffix::U16 => u16::MAX as i64,
+            NumberSuffix::U32 => u32::MAX as i64,
+            _ => i64::MAX,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Punct {
+    Arrow,
+    Colon,
+    Comma,
+    Dot,
+    /// `..`, an exclusive range.
+    DotDot,
+    /// `..=`, an inclusive range.
+    DotDotEq,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    /// `?`, after a type that may be `none`.
+    Question,
+}
+
+impl Punct {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Punct::Arrow => "->",
+            Punct::Colon => ":",
+            Punct::Comma => ",",
+            Punct::Dot => ".",
+            Punct::DotDot => "..",
+            Punct::DotDotEq => "..=",
+            Punct::LParen => "(",
+            Punct::RParen => ")",
+            Punct::LBracket => "[",
+            Punct::RBracket => "]",
+            Punct::LBrace => "{",
+            Punct::RBrace => "}",
+            Punct::Question => "?",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct TokenWithPos {
     pub token: Token,
     pub line: usize,
     pub column: usize,
+    pub span: Span,
+    /// Comments that appeared before this token, and a comment after it at
+    /// the end of its line.
+    pub trivia: Vec<Trivia>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// `# ...`
+    LineComment,
+    /// `## ...`
+    DocComment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    /// Comment text after the `#`/`##` marker, without the line break.
+    pub text: String,
+    /// True for a comment after code on the same line, which belongs to the
+    /// token before it rather than the one after it.
+    pub trailing: bool,
+    pub line: usize,
+    pub column: usize,
+    pub span: Span,
+}
+
+impl TokenWithPos {
+    /// Doc comment lines attached to this token, with the marker and one
+    /// leading space stripped.
+    pub fn doc_comments(&self) -> Vec<&str> {
+        self.trivia
+            .iter()
+            .filter(|t| t.kind == TriviaKind::DocComment && !t.trailing)
+            .map(|t| t.text.strip_prefix(' ').unwrap_or(&t.text))
+            .collect()
+    }
 }
 
 pub struct Lexer {
     source: Vec<char>,
+    file_id: FileId,
     position: usize,
+    /// Byte offset of `position` in the original source.
+    offset: usize,
     line: usize,
     column: usize,
 }
 
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Lexer {
     pub fn new() -> Self {
         Self {
             source: Vec::new(),
+            file_id: FileId::default(),
             position: 0,
+            offset: 0,
             line: 1,
             column: 1,
         }
     }
 
-    pub fn tokenize(&self, source: &str) -> Result<Vec<TokenWithPos>> {
+    pub fn tokenize(&self, source: &str) -> DiagnosticResult<Vec<TokenWithPos>> {
+        self.tokenize_file(source, FileId::default())
+    }
+
+    /// Tokenizes `source`, tagging every span with `file_id`.
+    pub fn tokenize_file(
+        &self,
+        source: &str,
+        file_id: FileId,
+    ) -> DiagnosticResult<Vec<TokenWithPos>> {
         let mut lexer = Self {
             source: source.chars().collect(),
+            file_id,
             position: 0,
+            offset: 0,
             line: 1,
             column: 1,
         };
 
-        let mut tokens = Vec::new();
+        let mut tokens: Vec<TokenWithPos> = Vec::new();
+        let mut trivia = Vec::new();
+        let mut line_has_code = false;
+        let mut at_line_start = true;
+        let mut depth = 0;
 
         while !lexer.is_at_end() {
             let start_line = lexer.line;
             let start_column = lexer.column;
+            let start_offset = lexer.offset;
+
+            if at_line_start {
+                at_line_start = false;
+                // Blank and comment-only lines leave the indentation alone.
+                let Some(line_depth) = lexer.scan_indentation()? else {
+                    continue;
+                };
+                // Each indent points at the tab that opens its level; dedents
+                // sit, empty, before the first token of the line.
+                for level in depth..line_depth {
+                    tokens.push(TokenWithPos {
+                        token: Token::Indent,
+                        line: start_line,
+                        column: start_column + level,
+                        span: Span::new(file_id, start_offset + level, start_offset + level + 1),
+                        trivia: Vec::new(),
+                    });
+                }
+                for _ in line_depth..depth {
+                    tokens.push(TokenWithPos {
+                        token: Token::Dedent,
+                        line: lexer.line,
+                        column: lexer.column,
+                        span: lexer.span_from(lexer.offset),
+                        trivia: Vec::new(),
+                    });
+                }
+                depth = line_depth;
+                continue;
+            }
 
-            if lexer.peek() == '\t' {
+            let token = if lexer.peek() == '#' {
+                // Whitespace before a comment is insignificant, and a comment
+                // on its own line also takes its line break with it. One
+                // after code stays with the last token of that code.
+                while matches!(tokens.last().map(|t| &t.token), Some(Token::Space(_))) {
+                    tokens.pop();
+                }
+                let mut comment = lexer.scan_comment();
+                match tokens.last_mut() {
+                    Some(last) if line_has_code => {
+                        comment.trailing = true;
+                        last.trivia.push(comment);
+                    }
+                    _ => trivia.push(comment),
+                }
+                if !line_has_code && lexer.peek() == '\n' {
+                    lexer.advance();
+                    at_line_start = true;
+                }
+                continue;
+            } else if lexer.peek() == '\t' {
+                // Tabs only indent. One after code on a line is accepted as
+                // trailing whitespace and dropped.
+                if !lexer.rest_of_line_is_blank() {
+                    return Err(Diagnostic::error(
+                        codes::MISPLACED_TAB,
+                        "tab inside a line",
+                    )
+                    .with_label(
+                        Span::new(file_id, start_offset, start_offset + 1),
+                        "tabs only open blocks at the start of a line",
+                    )
+                    .with_help("separate items on a line with spaces"));
+                }
                 lexer.advance();
-                tokens.push(TokenWithPos {
-                    token: Token::Tab,
-                    line: start_line,
-                    column: start_column,
-                });
+                continue;
             } else if lexer.peek() == ' ' {
-                lexer.advance();
-                tokens.push(TokenWithPos {
-                    token: Token::Space,
-                    line: start_line,
-                    column: start_column,
-                });
+                let mut count = 0;
+                while lexer.peek() == ' ' {
+                    lexer.advance();
+                    count += 1;
+                }
+                Token::Space(count)
             } else if lexer.peek() == '\n' {
                 lexer.advance();
-                tokens.push(TokenWithPos {
-                    token: Token::Newline,
-                    line: start_line,
-                    column: start_column,
-                });
-            } else if lexer.peek().is_alphabetic() || lexer.peek() == '_' {
-                let word = lexer.scan_word();
-                tokens.push(TokenWithPos {
-                    token: Token::Word(word),
-                    line: start_line,
-                    column: start_column,
-                });
-            } else if lexer.peek().is_ascii_digit() {
-                let (num, is_float) = lexer.scan_number();
-                tokens.push(TokenWithPos {
-                    token: if is_float {
-                        Token::Float(num as f64)
-                    } else {
-                        Token::Number(num)
-                    },
-                    line: start_line,
-                    column: start_column,
-                });
-            } else if lexer.peek() == '"' {
-                let string = lexer.scan_string()
-                    .with_context(|| format!("Unterminated string at line {}", start_line))?;
-                tokens.push(TokenWithPos {
-                    token: Token::String(string),
-                    line: start_line,
-                    column: start_column,
-                });
+                line_has_code = false;
+                at_line_start = true;
+                Token::Newline
             } else {
-                return Err(anyhow::anyhow!(
-                    "Unexpected character '{}' at line {}:{}",
-                    lexer.peek(),
-                    start_line,
-                    start_column
-                ));
-            }
+                lexer.scan_token(depth)?
+            };
+
+            // Whitespace never carries trivia; comments on their own line
+            // wait for the next significant token.
+            let leading_trivia = if matches!(token, Token::Space(_) | Token::Newline) {
+                Vec::new()
+            } else {
+                line_has_code = true;
+                std::mem::take(&mut trivia)
+            };
+            tokens.push(TokenWithPos {
+                token,
+                line: start_line,
+                column: start_column,
+                span: lexer.span_from(start_offset),
+                trivia: leading_trivia,
+            });
+        }
+
+        // End the last statement if the file has no trailing line break,
+        // then close any blocks still open.
+        if line_has_code {
+            tokens.push(TokenWithPos {
+                token: Token::Newline,
+                line: lexer.line,
+                column: lexer.column,
+                span: lexer.span_from(lexer.offset),
+                trivia: Vec::new(),
+            });
+        }
+        for _ in 0..depth {
+            tokens.push(TokenWithPos {
+                token: Token::Dedent,
+                line: lexer.line,
+                column: lexer.column,
+                span: lexer.span_from(lexer.offset),
+                trivia: Vec::new(),
+            });
         }
 
         tokens.push(TokenWithPos {
             token: Token::Eof,
             line: lexer.line,
             column: lexer.column,
+            span: lexer.span_from(lexer.offset),
+            trivia,
         });
 
         Ok(tokens)
@@ -129,12 +458,25 @@ impl Lexer {
         }
     }
 
+    fn peek_next(&self) -> char {
+        if self.position + 1 >= self.source.len() {
+            '\0'
+        } else {
+            self.source[self.position + 1]
+        }
+    }
+
+    fn peek_at(&self, ahead: usize) -> char {
+        self.source.get(self.position + ahead).copied().unwrap_or('\0')
+    }
+
     fn advance(&mut self) -> char {
         if self.is_at_end() {
             '\0'
         } else {
             let ch = self.source[self.position];
             self.position += 1;
+            self.offset += ch.len_utf8();
 
             if ch == '\n' {
                 self.line += 1;
@@ -147,6 +489,113 @@ impl Lexer {
         }
     }
 
+    /// Consumes the leading whitespace of a line and returns its depth in
+    /// tabs, or `None` for a blank or comment-only line, whose indentation
+    /// does not matter.
+    fn scan_indentation(&mut self) -> DiagnosticResult<Option<usize>> {
+        let mut depth = 0;
+        // First run of spaces in the indentation, reported if the line
+        // turns out to hold code.
+        let mut spaces: Option<Span> = None;
+        while matches!(self.peek(), '\t' | ' ') {
+            let offset = self.offset;
+            if self.advance() == '\t' {
+                depth += 1;
+            } else {
+                match &mut spaces {
+                    Some(span) if span.end == offset => span.end = self.offset,
+                    Some(_) => {}
+                    None => spaces = Some(self.span_from(offset)),
+                }
+            }
+        }
+
+        if matches!(self.peek(), '\n' | '#' | '\0') {
+            return Ok(None);
+        }
+        if let Some(span) = spaces {
+            return Err(Diagnostic::error(
+                codes::INCONSISTENT_INDENTATION,
+                "inconsistent indentation",
+            )
+            .with_label(span, "spaces cannot indent a block")
+            .with_help("indent blocks with tabs; spaces only separate items on a line"));
+        }
+        Ok(Some(depth))
+    }
+
+    /// True when only whitespace or a comment remains on the current line.
+    fn rest_of_line_is_blank(&self) -> bool {
+        self.source[self.position..]
+            .iter()
+            .find(|c| !matches!(c, '\t' | ' '))
+            .is_none_or(|c| matches!(c, '\n' | '#'))
+    }
+
+    fn span_from(&self, start: usize) -> Span {
+        Span::new(self.file_id, start, self.offset)
+    }
+
+    fn scan_comment(&mut self) -> Trivia {
+        let line = self.line;
+        let column = self.column;
+        let start = self.offset;
+        self.advance(); // consume '#'
+        let kind = if self.peek() == '#' {
+            self.advance();
+            TriviaKind::DocComment
+        } else {
+            TriviaKind::LineComment
+        };
+
+        let mut text = String::new();
+        while !self.is_at_end() && self.peek() != '\n' {
+            text.push(self.advance());
+        }
+
+        Trivia {
+            kind,
+            text: text.trim_end().to_string(),
+            trailing: false,
+            line,
+            column,
+            span: self.span_from(start),
+        }
+    }
+
+    /// Scans a word, literal or symbol. `depth` is the block depth of the
+    /// current line, which multiline strings need to strip their indentation.
+    fn scan_token(&mut self, depth: usize) -> DiagnosticResult<Token> {
+        let start = self.offset;
+        let ch = self.peek();
+        if ch == 'r' && self.peek_next() == '"' {
+            self.scan_string(depth)
+        } else if ch.is_alphabetic() || ch == '_' {
+            let word = self.scan_word();
+            Ok(match word.as_str() {
+                "true" => Token::Bool(true),
+                "false" => Token::Bool(false),
+                "none" => Token::None,
+                _ => Token::Word(word),
+            })
+        } else if ch.is_ascii_digit() {
+            self.scan_number()
+        } else if ch == '"' {
+            self.scan_string(depth)
+        } else if ch == '\'' {
+            self.scan_char()
+        } else if let Some(token) = self.scan_symbol() {
+            Ok(token)
+        } else {
+            let end = start + ch.len_utf8();
+            Err(Diagnostic::error(
+                codes::UNEXPECTED_CHARACTER,
+                format!("unexpected character `{}`", ch.escape_debug()),
+            )
+            .with_label(Span::new(self.file_id, start, end), "not valid here"))
+        }
+    }
+
     fn scan_word(&mut self) -> String {
         let mut word = String::new();
         while !self.is_at_end()
@@ -157,83 +606,861 @@ impl Lexer {
         word
     }
 
-    fn scan_number(&mut self) -> (i64, bool) {
-        let mut num_str = String::new();
-        let mut is_float = false;
+    /// Scans a numeric literal: `42`, `1_000`, `0xff`, `0b1010`, `0o17`,
+    /// `3.14` or `1e-9`, optionally followed by a type suffix (`10u8`,
+    /// `2.0f32`).
+    fn scan_number(&mut self) -> DiagnosticResult<Token> {
+        let start = self.offset;
+        let start_position = self.position;
+        let (radix, base) = match (self.peek(), self.peek_next()) {
+            ('0', 'x') => (16, "hexadecimal"),
+            ('0', 'o') => (8, "octal"),
+            ('0', 'b') => (2, "binary"),
+            _ => (10, "decimal"),
+        };
+        if radix != 10 {
+            self.advance();
+            self.advance();
+        }
 
-        while !self.is_at_end() && (self.peek().is_ascii_digit() || self.peek() == '.') {
-            if self.peek() == '.' {
-                if is_float {
-                    break;
-                }
+        // Digits go into `digits` without separators; decimal literals may
+        // continue with a fraction and an exponent. A number just after a
+        // single `.` is a tuple index, so `pair.0.1` keeps its second dot.
+        let mut digits = String::new();
+        let mut is_float = false;
+        let tuple_index = start_position > 0
+            && self.source[start_position - 1] == '.'
+            && (start_position < 2 || self.source[start_position - 2] != '.');
+        self.scan_digits(radix, &mut digits);
+        if radix == 10 && !tuple_index {
+            if self.peek() == '.' && self.peek_next().is_ascii_digit() {
                 is_float = true;
+                digits.push(self.advance());
+                self.scan_digits(10, &mut digits);
             }
-            num_str.push(self.advance());
+            let exponent_digit = match self.peek_next() {
+                '+' | '-' => self.peek_at(2).is_ascii_digit(),
+                c => c.is_ascii_digit(),
+            };
+            if matches!(self.peek(), 'e' | 'E') && exponent_digit {
+                is_float = true;
+                digits.push(self.advance());
+                if matches!(self.peek(), '+' | '-') {
+                    digits.push(self.advance());
+                }
+                self.scan_digits(10, &mut digits);
+            }
+        }
+
+        let suffix_start = self.offset;
+        let mut suffix_text = String::new();
+        while self.peek().is_alphanumeric() || self.peek() == '_' {
+            suffix_text.push(self.advance());
+        }
+        let span = self.span_from(start);
+        let raw: String = self.source[start_position..self.position].iter().collect();
+
+        if digits.is_empty() {
+            return Err(Diagnostic::error(
+                codes::INVALID_NUMBER,
+                format!("{} literal has no digits", base),
+            )
+            .with_label(span, format!("expected {} digits after `{}`", base, &raw[..2])));
+        }
+        if let Some(bad) = digits.chars().find(|c| radix != 10 && !c.is_digit(radix)) {
+            return Err(Diagnostic::error(
+                codes::INVALID_NUMBER,
+                format!("invalid digit `{}` in {} literal", bad, base),
+            )
+            .with_label(span, format!("`{}` is not a {} digit", bad, base)));
         }
 
-        if is_float {
-            let num = num_str.parse::<f64>().unwrap_or(0.0);
-            (num as i64, true)
+        let suffix = if suffix_text.is_empty() {
+            None
         } else {
-            (num_str.parse::<i64>().unwrap_or(0), false)
+            let suffix_span = Span::new(self.file_id, suffix_start, self.offset);
+            match NumberSuffix::from_name(&suffix_text) {
+                Some(suffix) if suffix.is_float() && radix != 10 => {
+                    return Err(Diagnostic::error(
+                        codes::INVALID_NUMBER,
+                        format!("{} literal cannot have a float suffix", base),
+                    )
+                    .with_label(suffix_span, "float suffix on an integer literal")
+                    .with_help("write the value in decimal"));
+                }
+                Some(suffix) if !suffix.is_float() && is_float => {
+                    return Err(Diagnostic::error(
+                        codes::INVALID_NUMBER,
+                        format!("integer suffix `{}` on a float literal", suffix_text),
+                    )
+                    .with_label(suffix_span, "integer suffix")
+                    .with_help("use `f32` or `f64`, or remove the fraction and exponent"));
+                }
+                Some(suffix) => Some(suffix),
+                None => {
+                    let valid: Vec<&str> = NumberSuffix::ALL.iter().map(|s| s.as_str()).collect();
+                    return Err(Diagnostic::error(
+                        codes::INVALID_NUMBER,
+                        format!("invalid suffix `{}` on number literal", suffix_text),
+                    )
+                    .with_label(suffix_span, "unknown suffix")
+                    .with_help(format!("valid suffixes are {}", valid.join(", "))));
+                }
+            }
+        };
+
+        if is_float || suffix.is_some_and(|s| s.is_float()) {
+            let value: f64 = digits.parse().unwrap_or(f64::INFINITY);
+            let in_range = match suffix {
+                Some(NumberSuffix::F32) => (value as f32).is_finite(),
+                _ => value.is_finite(),
+            };
+            if !in_range {
+                let ty = suffix.map_or("f64", |s| s.as_str());
+                return Err(Diagnostic::error(
+                    codes::NUMBER_OUT_OF_RANGE,
+                    "float literal is out of range",
+                )
+                .with_label(span, format!("does not fit in `{}`", ty)));
+            }
+            return Ok(Token::Float { value, suffix, raw });
+        }
+
+        let max = suffix.map_or(i64::MAX, |s| s.max_int());
+        match i64::from_str_radix(&digits, radix) {
+            Ok(value) if value <= max => Ok(Token::Number { value, suffix, raw }),
+            _ => {
+                let ty = suffix.map_or("int", |s| s.as_str());
+                let mut diagnostic = Diagnostic::error(
+                    codes::NUMBER_OUT_OF_RANGE,
+                    "integer literal is too large",
+                )
+                .with_label(span, format!("does not fit in `{}`", ty))
+                .with_note(format!("the largest `{}` is {}", ty, max));
+                if suffix == Some(NumberSuffix::U64) {
+                    diagnostic = diagnostic
+                        .with_note("integers are stored as 64-bit signed values at runtime");
+                }
+                Err(diagnostic)
+            }
         }
     }
 
-    fn scan_string(&mut self) -> Result<String> {
-        assert_eq!(self.advance(), '"');
-        let mut string = String::new();
+    /// Consumes the digits of a numeric literal in `radix` along with any
+    /// `_` separators, pushing the digits to `digits`. Binary and octal
+    /// literals also take stray decimal digits so they can be reported.
+    fn scan_digits(&mut self, radix: u32, digits: &mut String) {
+        loop {
+            let ch = self.peek();
+            if ch == '_' {
+                self.advance();
+            } else if ch.is_digit(radix.max(10)) {
+                digits.push(self.advance());
+            } else {
+                break;
+            }
+        }
+    }
 
-        while !self.is_at_end() && self.peek() != '"' {
-            if self.peek() == '\\' {
+    /// Scans an operator or punctuation token, preferring the longest match
+    /// so that `==` is not split into two `=` tokens.
+    fn scan_symbol(&mut self) -> Option<Token> {
+        if self.peek() == '.' && self.peek_next() == '.' && self.peek_at(2) == '=' {
+            for _ in 0..3 {
                 self.advance();
-                match self.peek() {
-                    'n' => {
-                        string.push('\n');
-                        self.advance();
-                    }
-                    't' => {
-                        string.push('\t');
+            }
+            return Some(Token::Punct(Punct::DotDotEq));
+        }
+        let double = match (self.peek(), self.peek_next()) {
+            ('-', '>') => Some(Token::Punct(Punct::Arrow)),
+            ('.', '.') => Some(Token::Punct(Punct::DotDot)),
+            ('=', '=') => Some(Token::Operator(Operator::Equal)),
+            ('!', '=') => Some(Token::Operator(Operator::NotEqual)),
+            ('>', '=') => Some(Token::Operator(Operator::GreaterEqual)),
+            ('<', '=') => Some(Token::Operator(Operator::LessEqual)),
+            ('&', '&') => Some(Token::Operator(Operator::And)),
+            ('|', '|') => Some(Token::Operator(Operator::Or)),
+            ('+', '=') => Some(Token::Operator(Operator::PlusAssign)),
+            ('-', '=') => Some(Token::Operator(Operator::MinusAssign)),
+            ('*', '=') => Some(Token::Operator(Operator::StarAssign)),
+            ('/', '=') => Some(Token::Operator(Operator::SlashAssign)),
+            _ => None,
+        };
+        if let Some(token) = double {
+            self.advance();
+            self.advance();
+            return Some(token);
+        }
+
+        let single = match self.peek() {
+            '+' => Token::Operator(Operator::Plus),
+            '-' => Token::Operator(Operator::Minus),
+            '*' => Token::Operator(Operator::Star),
+            '/' => Token::Operator(Operator::Slash),
+            '>' => Token::Operator(Operator::Greater),
+            '<' => Token::Operator(Operator::Less),
+            '=' => Token::Operator(Operator::Assign),
+            '!' => Token::Operator(Operator::Not),
+            ':' => Token::Punct(Punct::Colon),
+            ',' => Token::Punct(Punct::Comma),
+            '.' => Token::Punct(Punct::Dot),
+            '(' => Token::Punct(Punct::LParen),
+            ')' => Token::Punct(Punct::RParen),
+            '[' => Token::Punct(Punct::LBracket),
+            ']' => Token::Punct(Punct::RBracket),
+            '{' => Token::Punct(Punct::LBrace),
+            '}' => Token::Punct(Punct::RBrace),
+            '?' => Token::Punct(Punct::Question),
+            _ => return None,
+        };
+        self.advance();
+        Some(single)
+    }
+
+    /// Scans a string literal: `"..."`, raw `r"..."`, or a multiline
+    /// `"""` string. Strings other than raw ones expand escapes and split
+    /// around `{expr}` interpolations.
+    ///
+    /// The opening `"""` ends its line, and each following line is indented
+    /// one tab deeper than the enclosing block (`depth`). That indentation,
+    /// the first line break and the one before the closing `"""` are not part
+    /// of the string.
+    fn scan_string(&mut self, depth: usize) -> DiagnosticResult<Token> {
+        let start = self.offset;
+        let start_position = self.position;
+        let raw = self.peek() == 'r';
+        if raw {
+            self.advance();
+        }
+        let multiline = self.peek_next() == '"' && self.peek_at(2) == '"';
+        let quotes = if multiline { 3 } else { 1 };
+        for _ in 0..quotes {
+            self.advance();
+        }
+        let opening = self.span_from(start);
+
+        if multiline {
+            while matches!(self.peek(), ' ' | '\t') {
+                self.advance();
+            }
+            if !self.is_at_end() && self.peek() != '\n' {
+                let text_start = self.offset;
+                return Err(Diagnostic::error(
+                    codes::INVALID_MULTILINE_STRING,
+                    "text after the opening `\"\"\"`",
+                )
+                .with_label(
+                    Span::new(self.file_id, text_start, text_start + self.peek().len_utf8()),
+                    "the string's first line must be blank",
+                )
+                .with_secondary(opening, "multiline string starts here")
+                .with_help("start the text on the next line, or use `\"`"));
+            }
+            self.advance();
+        }
+
+        let unterminated = || {
+            Diagnostic::error(codes::UNTERMINATED_STRING, "unterminated string literal")
+                .with_label(opening, "string starts here")
+        };
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut line_start = multiline;
+        loop {
+            if line_start {
+                line_start = false;
+                if self.scan_string_indentation(depth)? {
+                    literal.pop();
+                    break;
+                }
+                continue;
+            }
+            if self.is_at_end() {
+                let help = if multiline { "add a closing `\"\"\"`" } else { "add a closing `\"`" };
+                return Err(unterminated().with_help(help));
+            }
+            match self.peek() {
+                '"' if !multiline => {
+                    self.advance();
+                    break;
+                }
+                '"' if self.peek_next() == '"' && self.peek_at(2) == '"' => {
+                    for _ in 0..3 {
                         self.advance();
                     }
-                    '\\' => {
-                        string.push('\\');
-                        self.advance();
+                    break;
+                }
+                '\n' if !multiline => {
+                    return Err(unterminated()
+                        .with_help("close the string on this line, or use `\"\"\"` for several lines"));
+                }
+                '\n' => {
+                    literal.push(self.advance());
+                    line_start = true;
+                }
+                '\\' if !raw => literal.push(self.scan_escape()?),
+                '{' if !raw => {
+                    if !literal.is_empty() {
+                        parts.push(StringPart::Literal(std::mem::take(&mut literal)));
                     }
-                    '"' => {
-                        string.push('"');
+                    parts.push(StringPart::Code(self.scan_interpolation(depth)?));
+                }
+                '}' if !raw => {
+                    let brace = self.offset;
+                    return Err(Diagnostic::error(
+                        codes::INVALID_INTERPOLATION,
+                        "unmatched `}` in string",
+                    )
+                    .with_label(Span::new(self.file_id, brace, brace + 1), "no `{` opens this")
+                    .with_help("write `\\}` for a literal brace"));
+                }
+                _ => literal.push(self.advance()),
+            }
+        }
+
+        let raw_text: String = self.source[start_position..self.position].iter().collect();
+        if parts.is_empty() {
+            return Ok(Token::String {
+                value: literal,
+                raw: raw_text,
+            });
+        }
+        if !literal.is_empty() {
+            parts.push(StringPart::Literal(literal));
+        }
+        Ok(Token::InterpolatedString {
+            parts,
+            raw: raw_text,
+        })
+    }
+
+    /// Consumes the indentation at the start of a line in a multiline
+    /// string. Returns true if the line holds the closing `"""`, which is
+    /// consumed too.
+    fn scan_string_indentation(&mut self, depth: usize) -> DiagnosticResult<bool> {
+        let line_start = self.offset;
+        let mut tabs = 0;
+        while self.peek_at(tabs) == '\t' {
+            tabs += 1;
+        }
+        if (0..3).all(|i| self.peek_at(tabs + i) == '"') {
+            for _ in 0..tabs + 3 {
+                self.advance();
+            }
+            return Ok(true);
+        }
+
+        let strip = depth + 1;
+        if tabs < strip {
+            if !self.rest_of_line_is_blank() {
+                let end = line_start + tabs.max(self.peek_at(tabs).len_utf8());
+                return Err(Diagnostic::error(
+                    codes::INVALID_MULTILINE_STRING,
+                    "line of a multiline string is not indented past its block",
+                )
+                .with_label(
+                    Span::new(self.file_id, line_start, end),
+                    format!("expected {} tab{}", strip, if strip == 1 { "" } else { "s" }),
+                )
+                .with_help("indent the string one tab deeper than the line that opens it"));
+            }
+            // Blank lines may be indented less; their whitespace is dropped.
+            while matches!(self.peek(), ' ' | '\t') {
+                self.advance();
+            }
+            return Ok(false);
+        }
+        for _ in 0..strip {
+            self.advance();
+        }
+        Ok(false)
+    }
+
+    /// Scans a character literal: one character or escape between single
+    /// quotes.
+    fn scan_char(&mut self) -> DiagnosticResult<Token> {
+        let start = self.offset;
+        let start_position = self.position;
+        self.advance();
+
+        let value = match self.peek() {
+            '\\' => Some(self.scan_escape()?),
+            '\'' | '\n' => None,
+            _ if self.is_at_end() => None,
+            _ => Some(self.advance()),
+        };
+        let Some(value) = value else {
+            let message = if self.peek() == '\'' {
+                self.advance();
+                "empty character literal"
+            } else {
+                "unterminated character literal"
+            };
+            return Err(Diagnostic::error(codes::INVALID_CHAR, message)
+                .with_label(self.span_from(start), "expected a character")
+                .with_help("write one character between single quotes, such as `'a'`"));
+        };
+        if self.peek() != '\'' {
+            // Tell a string in single quotes apart from a missing quote.
+            let rest = &self.source[self.position..];
+            let close = rest.iter().take_while(|c| **c != '\n').position(|c| *c == '\'');
+            return Err(match close {
+                Some(index) => {
+                    let len: usize = rest[..=index].iter().map(|c| c.len_utf8()).sum();
+                    Diagnostic::error(
+                        codes::INVALID_CHAR,
+                        "character literal may only contain one character",
+                    )
+                    .with_label(
+                        Span::new(self.file_id, start, self.offset + len),
+                        "more than one character",
+                    )
+                    .with_help("use double quotes for a string")
+                }
+                None => Diagnostic::error(codes::INVALID_CHAR, "unterminated character literal")
+                    .with_label(self.span_from(start), "expected a closing `'`"),
+            });
+        }
+        self.advance();
+
+        let raw: String = self.source[start_position..self.position].iter().collect();
+        Ok(Token::Char { value, raw })
+    }
+
+    /// Scans an escape sequence starting at a `\` and returns the character
+    /// it stands for.
+    fn scan_escape(&mut self) -> DiagnosticResult<char> {
+        let start = self.offset;
+        self.advance();
+        let invalid = |lexer: &Self, message: &str, label: &str| {
+            Diagnostic::error(codes::INVALID_ESCAPE, message.to_string())
+                .with_label(lexer.span_from(start), label.to_string())
+                .with_help(
+                    "valid escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\', \\{, \\}, \\x7f and \\u{1F600}",
+                )
+        };
+
+        let ch = self.peek();
+        if self.is_at_end() || ch == '\n' {
+            return Err(invalid(self, "incomplete escape sequence", "expected a character after `\\`"));
+        }
+        self.advance();
+        match ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' | '"' | '\'' | '{' | '}' => Ok(ch),
+            'x' => {
+                let mut digits = String::new();
+                while digits.len() < 2 && self.peek().is_ascii_hexdigit() {
+                    digits.push(self.advance());
+                }
+                match u8::from_str_radix(&digits, 16) {
+                    Ok(value) if digits.len() == 2 && value <= 0x7f => Ok(value as char),
+                    Ok(_) if digits.len() == 2 => Err(invalid(
+                        self,
+                        "byte escape out of range",
+                        "`\\x` escapes only go up to `\\x7f`",
+                    )
+                    .with_note("use `\\u{..}` for characters outside ASCII")),
+                    _ => Err(invalid(self, "invalid byte escape", "expected two hex digits")),
+                }
+            }
+            'u' => {
+                if self.peek() != '{' {
+                    return Err(invalid(self, "invalid unicode escape", "expected `{` after `\\u`"));
+                }
+                self.advance();
+                let mut digits = String::new();
+                while self.peek().is_ascii_hexdigit() {
+                    digits.push(self.advance());
+                }
+                if self.peek() != '}' {
+                    return Err(invalid(
+                        self,
+                        "unterminated unicode escape",
+                        "expected hex digits and a closing `}`",
+                    ));
+                }
+                self.advance();
+                if digits.is_empty() || digits.len() > 6 {
+                    return Err(invalid(
+                        self,
+                        "invalid unicode escape",
+                        "expected 1 to 6 hex digits",
+                    ));
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        invalid(self, "invalid unicode escape", "not a Unicode scalar value")
+                    })
+            }
+            _ => Err(invalid(
+                self,
+                &format!("unknown escape sequence `\\{}`", ch.escape_debug()),
+                "unknown escape",
+            )),
+        }
+    }
+
+    /// Scans a `{expr}` interpolation and returns the expression's tokens,
+    /// ended by an `Eof` at the closing brace. The expression stays on one
+    /// line and holds no string literals.
+    fn scan_interpolation(&mut self, depth: usize) -> DiagnosticResult<Vec<TokenWithPos>> {
+        let open = Span::new(self.file_id, self.offset, self.offset + 1);
+        self.advance();
+
+        let mut tokens = Vec::new();
+        let mut braces = 0;
+        loop {
+            let (line, column, start) = (self.line, self.column, self.offset);
+            let token = match self.peek() {
+                '}' if braces == 0 => break,
+                // Strings cannot nest, so a quote ends the enclosing string.
+                _ if self.is_at_end() || matches!(self.peek(), '\n' | '"') => {
+                    return Err(Diagnostic::error(
+                        codes::INVALID_INTERPOLATION,
+                        "unterminated interpolation",
+                    )
+                    .with_label(open, "interpolation starts here")
+                    .with_help("close it with `}` on the same line, or write `\\{` for a brace"));
+                }
+                ' ' => {
+                    let mut count = 0;
+                    while self.peek() == ' ' {
                         self.advance();
+                        count += 1;
                     }
-                    _ => {
-                        string.push('\\');
-                        string.push(self.advance());
-                    }
+                    Token::Space(count)
                 }
-            } else {
-                string.push(self.advance());
+                _ => self.scan_token(depth)?,
+            };
+            match token {
+                Token::Punct(Punct::LBrace) => braces += 1,
+                Token::Punct(Punct::RBrace) => braces -= 1,
+                _ => {}
             }
+            tokens.push(TokenWithPos {
+                token,
+                line,
+                column,
+                span: self.span_from(start),
+                trivia: Vec::new(),
+            });
         }
 
-        if self.is_at_end() {
-            return Err(anyhow::anyhow!("Unterminated string"));
+        let close = TokenWithPos {
+            token: Token::Eof,
+            line: self.line,
+            column: self.column,
+            span: self.span_from(self.offset),
+            trivia: Vec::new(),
+        };
+        self.advance();
+        if tokens.iter().all(|t| matches!(t.token, Token::Space(_))) {
+            return Err(Diagnostic::error(codes::INVALID_INTERPOLATION, "empty interpolation")
+                .with_label(
+                    Span::new(self.file_id, open.start, close.span.start + 1),
+                    "expected an expression",
+                )
+                .with_help("write `\\{` for a literal brace"));
         }
+        tokens.push(close);
+        Ok(tokens)
+    }
+}
 
-        self.advance(); // consume closing quote
-        Ok(string)
+impl Token {
+    /// Human-readable description used in diagnostics.
+    pub fn describe(&self) -> String {
+        match self {
+            Token::Indent => "indentation".to_string(),
+            Token::Dedent => "end of block".to_string(),
+            Token::Space(1) => "a space".to_string(),
+            Token::Space(n) => format!("{} spaces", n),
+            Token::Newline => "end of line".to_string(),
+            Token::Word(w) => format!("`{}`", w),
+            Token::Number { raw, .. } | Token::Float { raw, .. } => format!("number `{}`", raw),
+            Token::Bool(value) => format!("`{}`", value),
+            Token::None => "`none`".to_string(),
+            Token::Char { raw, .. } => format!("character {}", raw),
+            Token::String { raw, .. } | Token::InterpolatedString { raw, .. } => {
+                format!("string {}", raw)
+            }
+            Token::Operator(op) => format!("`{}`", op.as_str()),
+            Token::Punct(p) => format!("`{}`", p.as_str()),
+            Token::Eof => "end of file".to_string(),
+        }
     }
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Token::Tab => write!(f, "TAB"),
-            Token::Space => write!(f, "SPACE"),
+            Token::Indent => write!(f, "INDENT"),
+            Token::Dedent => write!(f, "DEDENT"),
+            Token::Space(n) => write!(f, "SPACE({})", n),
             Token::Newline => write!(f, "NEWLINE"),
             Token::Word(w) => write!(f, "WORD({})", w),
-            Token::Number(n) => write!(f, "NUMBER({})", n),
-            Token::Float(n) => write!(f, "FLOAT({})", n),
-            Token::String(s) => write!(f, "STRING({:?})", s),
+            Token::Number { raw, .. } => write!(f, "NUMBER({})", raw),
+            Token::Float { raw, .. } => write!(f, "FLOAT({})", raw),
+            Token::Bool(value) => write!(f, "BOOL({})", value),
+            Token::None => write!(f, "NONE"),
+            Token::Char { value, .. } => write!(f, "CHAR({:?})", value),
+            Token::String { value, .. } => write!(f, "STRING({:?})", value),
+            Token::InterpolatedString { raw, .. } => write!(f, "INTERPOLATED({})", raw),
+            Token::Operator(op) => write!(f, "OPERATOR({})", op.as_str()),
+            Token::Punct(p) => write!(f, "PUNCT({})", p.as_str()),
             Token::Eof => write!(f, "EOF"),
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tokens of `source` without the `NEWLINE` and `EOF` at the end.
+    fn tokens(source: &str) -> Vec<Token> {
+        let mut tokens: Vec<Token> = Lexer::new()
+            .tokenize(source)
+            .expect("source should lex")
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        assert_eq!(tokens.pop(), Some(Token::Eof));
+        assert_eq!(tokens.pop(), Some(Token::Newline));
+        tokens
+    }
+
+    fn token(source: &str) -> Token {
+        let tokens = tokens(source);
+        assert_eq!(tokens.len(), 1, "{:?} lexed as {:?}", source, tokens);
+        tokens.into_iter().next().unwrap()
+    }
+
+    fn error(source: &str) -> &'static str {
+        match Lexer::new().tokenize(source) {
+            Ok(tokens) => panic!("{:?} lexed as {:?}", source, tokens),
+            Err(diagnostic) => diagnostic.code,
+        }
+    }
+
+    fn int(source: &str) -> i64 {
+        match token(source) {
+            Token::Number { value, suffix: None, raw } => {
+                assert_eq!(raw, source);
+                value
+            }
+            token => panic!("{:?} lexed as {:?}", source, token),
+        }
+    }
+
+    fn float(source: &str) -> f64 {
+        match token(source) {
+            Token::Float { value, suffix: None, raw } => {
+                assert_eq!(raw, source);
+                value
+            }
+            token => panic!("{:?} lexed as {:?}", source, token),
+        }
+    }
+
+    /// The value of the string literal `source`.
+    fn string(source: &str) -> String {
+        match token(source) {
+            Token::String { value, raw } => {
+                assert_eq!(raw, source);
+                value
+            }
+            token => panic!("{:?} lexed as {:?}", source, token),
+        }
+    }
+
+    #[test]
+    fn lexes_integers_in_every_radix() {
+        assert_eq!(int("42"), 42);
+        assert_eq!(int("1_000_000"), 1_000_000);
+        assert_eq!(int("0xff"), 255);
+        assert_eq!(int("0xFF_FF"), 0xffff);
+        assert_eq!(int("0o17"), 15);
+        assert_eq!(int("0b1010_0101"), 0b1010_0101);
+        assert_eq!(int("0x1f64"), 0x1f64);
+        assert_eq!(int("9223372036854775807"), i64::MAX);
+    }
+
+    #[test]
+    fn lexes_floats_with_fractions_and_exponents() {
+        assert_eq!(float("2.75"), 2.75);
+        assert_eq!(float("1e-9"), 1e-9);
+        assert_eq!(float("2.5E+3"), 2500.0);
+        assert_eq!(float("1_000.5"), 1000.5);
+    }
+
+    #[test]
+    fn keeps_ranges_and_tuple_indexes_apart_from_floats() {
+        assert_eq!(
+            tokens("1..10"),
+            vec![
+                Token::Number {
+                    value: 1,
+                    suffix: None,
+                    raw: "1".to_string()
+                },
+                Token::Punct(Punct::DotDot),
+                Token::Number {
+                    value: 10,
+                    suffix: None,
+                    raw: "10".to_string()
+                },
+            ]
+        );
+        let pair = tokens("pair.0.1");
+        assert_eq!(pair.len(), 5);
+        assert!(matches!(pair[2], Token::Number { value: 0, .. }));
+        assert!(matches!(pair[4], Token::Number { value: 1, .. }));
+    }
+
+    #[test]
+    fn reports_numbers_out_of_range() {
+        assert_eq!(error("9223372036854775808"), codes::NUMBER_OUT_OF_RANGE);
+        assert_eq!(error("0x1_0000_0000_0000_0000"), codes::NUMBER_OUT_OF_RANGE);
+        assert_eq!(error("1e999"), codes::NUMBER_OUT_OF_RANGE);
+    }
+
+    #[test]
+    fn reports_malformed_numbers() {
+        assert_eq!(error("0x"), codes::INVALID_NUMBER);
+        assert_eq!(error("0b102"), codes::INVALID_NUMBER);
+        assert_eq!(error("0o8"), codes::INVALID_NUMBER);
+        assert_eq!(error("10abc"), codes::INVALID_NUMBER);
+    }
+
+    #[test]
+    fn lexes_type_suffixes() {
+        assert_eq!(
+            token("10u8"),
+            Token::Number {
+                value: 10,
+                suffix: Some(NumberSuffix::U8),
+                raw: "10u8".to_string()
+            }
+        );
+        assert_eq!(
+            token("2.0f32"),
+            Token::Float {
+                value: 2.0,
+                suffix: Some(NumberSuffix::F32),
+                raw: "2.0f32".to_string()
+            }
+        );
+        assert!(matches!(
+            token("0xffi64"),
+            Token::Number {
+                value: 255,
+                suffix: Some(NumberSuffix::I64),
+                ..
+            }
+        ));
+        // A float suffix makes an integer literal a float.
+        assert!(matches!(
+            token("1f64"),
+            Token::Float {
+                suffix: Some(NumberSuffix::F64),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn checks_suffixed_numbers_against_their_type() {
+        assert!(matches!(token("255u8"), Token::Number { value: 255, .. }));
+        assert_eq!(error("300u8"), codes::NUMBER_OUT_OF_RANGE);
+        assert_eq!(error("128i8"), codes::NUMBER_OUT_OF_RANGE);
+        assert_eq!(error("9223372036854775808u64"), codes::NUMBER_OUT_OF_RANGE);
+        assert_eq!(error("1e39f32"), codes::NUMBER_OUT_OF_RANGE);
+        assert_eq!(error("1.5u8"), codes::INVALID_NUMBER);
+        assert_eq!(error("0b1f32"), codes::INVALID_NUMBER);
+        assert_eq!(error("10u7"), codes::INVALID_NUMBER);
+    }
+
+    #[test]
+    fn expands_string_escapes() {
+        assert_eq!(string(r#""a\nb\tc""#), "a\nb\tc");
+        assert_eq!(string(r#""\\ \" \{ \} \0""#), "\\ \" { } \0");
+        assert_eq!(string(r#""\x41\u{1F600}""#), "A\u{1F600}");
+    }
+
+    #[test]
+    fn keeps_raw_strings_as_written() {
+        assert_eq!(string(r#"r"C:\path\{x}""#), r"C:\path\{x}");
+    }
+
+    #[test]
+    fn splits_interpolated_strings_into_parts() {
+        let Token::InterpolatedString { parts, raw } = token(r#""Hi {name}!\n""#) else {
+            panic!("expected an interpolated string");
+        };
+        assert_eq!(raw, r#""Hi {name}!\n""#);
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[0], StringPart::Literal("Hi ".to_string()));
+        let StringPart::Code(code) = &parts[1] else {
+            panic!("expected code, found {:?}", parts[1]);
+        };
+        let code: Vec<&Token> = code.iter().map(|token| &token.token).collect();
+        assert_eq!(code, [&Token::Word("name".to_string()), &Token::Eof]);
+        assert_eq!(parts[2], StringPart::Literal("!\n".to_string()));
+    }
+
+    #[test]
+    fn strips_the_indentation_of_multiline_strings() {
+        let source = "let poem  \"\"\"\n\tRoses are red,\n\t\tviolets are blue.\n\t\"\"\"\n";
+        let poem = Lexer::new()
+            .tokenize(source)
+            .expect("source should lex")
+            .into_iter()
+            .find_map(|token| match token.token {
+                Token::String { value, .. } => Some(value),
+                _ => None,
+            });
+        assert_eq!(poem.as_deref(), Some("Roses are red,\n\tviolets are blue."));
+    }
+
+    #[test]
+    fn lexes_character_literals() {
+        assert_eq!(
+            token("'a'"),
+            Token::Char {
+                value: 'a',
+                raw: "'a'".to_string()
+            }
+        );
+        assert_eq!(
+            token(r"'\''"),
+            Token::Char {
+                value: '\'',
+                raw: r"'\''".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn reports_malformed_strings_and_characters() {
+        assert_eq!(error("\"abc"), codes::UNTERMINATED_STRING);
+        assert_eq!(error(r#""\q""#), codes::INVALID_ESCAPE);
+        assert_eq!(error(r#""\x80""#), codes::INVALID_ESCAPE);
+        assert_eq!(error(r#""{}""#), codes::INVALID_INTERPOLATION);
+        assert_eq!(error(r#""a}""#), codes::INVALID_INTERPOLATION);
+        assert_eq!(
+            error("let s  \"\"\"text\n"),
+            codes::INVALID_MULTILINE_STRING
+        );
+        assert_eq!(error("''"), codes::INVALID_CHAR);
+        assert_eq!(error("'ab'"), codes::INVALID_CHAR);
+    }
+}
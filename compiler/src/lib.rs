@@ -1,16 +1,38 @@
 pub mod ast;
 pub mod codegen;
+pub mod diagnostics;
 pub mod lexer;
 pub mod parser;
+pub mod source;
 pub mod typechecker;
 pub mod wasm;
 
 use anyhow::Result;
+use diagnostics::{Diagnostic, DiagnosticRenderer, Diagnostics};
+use source::SourceMap;
 use std::path::Path;
 
+/// Runs a program through the stages of compilation: lexing and parsing
+/// (`load`), checking types (`check`), lowering the checked program to the
+/// form the backends take (`lower`), and a backend, which uses the types the
+/// check found. Names are resolved by the check, which follows the same
+/// scopes to find the types of the variables.
 pub struct Compiler {
     pub lexer: lexer::Lexer,
     pub parser: parser::Parser,
+    pub sources: SourceMap,
+    /// Whether programs are typechecked before they are compiled or run.
+    /// Without the check, backends take every value without an annotation
+    /// to be an `int`.
+    pub typecheck: bool,
+    /// Warnings about the last program checked.
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Compiler {
@@ -18,31 +40,33 @@ impl Compiler {
         Self {
             lexer: lexer::Lexer::new(),
             parser: parser::Parser::new(),
+            sources: SourceMap::new(),
+            typecheck: true,
+            warnings: Vec::new(),
         }
     }
 
     pub fn compile(
-        &self,
+        &mut self,
         input: &Path,
         output: Option<&Path>,
         target: &str,
     ) -> Result<()> {
-        let source = std::fs::read_to_string(input)?;
-        let tokens = self.lexer.tokenize(&source)?;
-        let ast = self.parser.parse(tokens)?;
+        let (ast, types) = self.analyze(input)?;
+        let ast = self.lower(&ast);
 
         match target {
             "native" => {
                 let output_path = output
                     .map(|p| p.to_path_buf())
                     .unwrap_or_else(|| input.with_extension(""));
-                codegen::Codegen::new().generate_native(&ast, &output_path)?;
+                codegen::Codegen::new().generate_native(&ast, &types, &output_path)?;
             }
             "wasm" => {
                 let output_path = output
                     .map(|p| p.to_path_buf())
                     .unwrap_or_else(|| input.with_extension("wasm"));
-                wasm::WasmGenerator::new().generate(&ast, &output_path)?;
+                wasm::WasmGenerator::new().generate(&ast, &types, &output_path)?;
             }
             _ => anyhow::bail!("Unknown target: {}", target),
         }
@@ -50,19 +74,84 @@ impl Compiler {
         Ok(())
     }
 
-    pub fn format(&self, input: &Path) -> Result<String> {
-        let source = std::fs::read_to_string(input)?;
-        let tokens = self.lexer.tokenize(&source)?;
-        let ast = self.parser.parse(tokens)?;
+    /// Formatting only needs the program to parse, so it is not
+    /// typechecked.
+    pub fn format(&mut self, input: &Path) -> Result<String> {
+        let ast = self.load(input)?;
         Ok(ast.format())
     }
 
-    pub fn run(&self, input: &Path) -> Result<()> {
-        let source = std::fs::read_to_string(input)?;
-        let tokens = self.lexer.tokenize(&source)?;
-        let ast = self.parser.parse(tokens)?;
-        codegen::Interpreter::new().interpret(&ast)?;
+    pub fn run(&mut self, input: &Path) -> Result<()> {
+        let (ast, _) = self.analyze(input)?;
+        self.interpret(&ast)
+    }
+
+    /// Runs every front-end stage over `input`: the program, and the type of
+    /// each of its expressions for the backends.
+    pub fn analyze(&mut self, input: &Path) -> Result<(ast::Program, typechecker::TypeTable)> {
+        let ast = self.load(input)?;
+        let types = self.check(&ast)?;
+        Ok((ast, types))
+    }
+
+    /// Rewrites a checked `program` into the form the backends take. A
+    /// declaration that shadows a name in scope gets a name of its own, as
+    /// C cannot declare a name twice in one block and WASM has one set of
+    /// locals for all of `main`. Spans are kept, so the types found by the
+    /// check still apply.
+    pub fn lower(&self, program: &ast::Program) -> ast::Program {
+        program.without_shadowing()
+    }
+
+    pub fn interpret(&self, program: &ast::Program) -> Result<()> {
+        codegen::Interpreter::new().interpret(program)?;
         Ok(())
     }
+
+    /// Renders the warnings about the last program checked, and forgets
+    /// them so that each is reported once.
+    pub fn render_warnings(&mut self) -> String {
+        let warnings = std::mem::take(&mut self.warnings);
+        DiagnosticRenderer::new(&self.sources).render_all(&warnings)
+    }
+
+    /// Renders an error returned by this compiler. Diagnostics are shown
+    /// with the offending source lines; other errors fall back to their
+    /// plain message.
+    pub fn render_error(&self, error: &anyhow::Error) -> String {
+        let renderer = DiagnosticRenderer::new(&self.sources);
+        if let Some(diagnostic) = error.downcast_ref::<Diagnostic>() {
+            renderer.render(diagnostic)
+        } else if let Some(Diagnostics(diagnostics)) = error.downcast_ref::<Diagnostics>() {
+            renderer.render_all(diagnostics)
+        } else {
+            format!("error: {:#}\n", error)
+        }
+    }
+
+    fn load(&mut self, input: &Path) -> Result<ast::Program> {
+        let source = std::fs::read_to_string(input)?;
+        let file_id = self.sources.add_file(input.display().to_string(), source.as_str());
+        let tokens = self.lexer.tokenize_file(&source, file_id)?;
+        let (program, diagnostics) = self.parser.parse_with_recovery(tokens);
+        if diagnostics.is_empty() {
+            Ok(program)
+        } else {
+            Err(Diagnostics(diagnostics).into())
+        }
+    }
+
+    /// Resolves the names in `program` and checks its types, keeping the
+    /// warnings. The table is empty when typechecking is turned off.
+    fn check(&mut self, program: &ast::Program) -> Result<typechecker::TypeTable> {
+        if !self.typecheck {
+            return Ok(typechecker::TypeTable::default());
+        }
+        let mut checker = typechecker::TypeChecker::new();
+        let result = checker.check(program);
+        self.warnings = checker.warnings().to_vec();
+        result?;
+        Ok(checker.types().clone())
+    }
 }
 
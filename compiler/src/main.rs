@@ -24,6 +24,9 @@ enum Commands {
         /// Target: native or wasm
         #[arg(short, long, default_value = "native")]
         target: String,
+        /// Skip typechecking; values without annotations compile as `int`
+        #[arg(long)]
+        no_typecheck: bool,
     },
     /// Format Tabula source code
     Fmt {
@@ -39,20 +42,38 @@ enum Commands {
         /// Input source file
         #[arg(short, long)]
         input: PathBuf,
+        /// Skip typechecking
+        #[arg(long)]
+        no_typecheck: bool,
     },
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() {
     let cli = Cli::parse();
+    let mut compiler = Compiler::new();
 
-    match cli.command {
-        Commands::Build { input, output, target } => {
-            let compiler = Compiler::new();
-            compiler.compile(&input, output.as_ref(), &target)?;
+    let result = execute(&mut compiler, cli.command);
+    eprint!("{}", compiler.render_warnings());
+    if let Err(error) = result {
+        eprint!("{}", compiler.render_error(&error));
+        std::process::exit(1);
+    }
+}
+
+fn execute(compiler: &mut Compiler, command: Commands) -> anyhow::Result<()> {
+    match command {
+        Commands::Build {
+            input,
+            output,
+            target,
+            no_typecheck,
+        } => {
+            compiler.typecheck = !no_typecheck;
+            compiler.compile(&input, output.as_deref(), &target)?;
+            eprint!("{}", compiler.render_warnings());
             println!("Compilation successful!");
         }
         Commands::Fmt { input, write } => {
-            let compiler = Compiler::new();
             let formatted = compiler.format(&input)?;
             if write {
                 std::fs::write(&input, formatted)?;
@@ -61,9 +82,12 @@ fn main() -> anyhow::Result<()> {
                 print!("{}", formatted);
             }
         }
-        Commands::Run { input } => {
-            let compiler = Compiler::new();
-            compiler.run(&input)?;
+        Commands::Run { input, no_typecheck } => {
+            compiler.typecheck = !no_typecheck;
+            // Warnings come before anything the program prints.
+            let (program, _) = compiler.analyze(&input)?;
+            eprint!("{}", compiler.render_warnings());
+            compiler.interpret(&program)?;
         }
     }
 
@@ -0,0 +1,233 @@
+use std::fmt;
+
+/// Identifies a file registered in a `SourceMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct FileId(pub u32);
+
+/// A half-open byte range `start..end` into one source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub file_id: FileId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(file_id: FileId, start: usize, end: usize) -> Self {
+        Self { file_id, start, end }
+    }
+
+    /// Returns the smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            file_id: self.file_id,
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// A zero-based line/column pair. The unit of `column` depends on the method
+/// that produced it: UTF-8 bytes or UTF-16 code units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+pub struct SourceFile {
+    name: String,
+    source: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        let source = source.into();
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self {
+            name: name.into(),
+            source,
+            line_starts,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Zero-based line containing `offset`.
+    pub fn line_index(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /// Text of a zero-based line, without its line break.
+    pub fn line_text(&self, line: usize) -> &str {
+        let start = self.line_starts[line];
+        let end = self
+            .line_starts
+            .get(line + 1)
+            .map(|next| next - 1)
+            .unwrap_or(self.source.len());
+        &self.source[start..end]
+    }
+
+    /// Position of `offset` with the column counted in UTF-8 bytes.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.source.len());
+        let line = self.line_index(offset);
+        LineCol {
+            line,
+            column: offset - self.line_starts[line],
+        }
+    }
+
+    /// Position of `offset` with the column counted in UTF-16 code units, as
+    /// expected by the Language Server Protocol.
+    pub fn line_col_utf16(&self, offset: usize) -> LineCol {
+        let offset = offset.min(self.source.len());
+        let line = self.line_index(offset);
+        let prefix = &self.source[self.line_starts[line]..offset];
+        LineCol {
+            line,
+            column: prefix.chars().map(char::len_utf16).sum(),
+        }
+    }
+
+    /// Converts a UTF-16 position back into a byte offset, clamping to the
+    /// end of the line.
+    pub fn offset_utf16(&self, position: LineCol) -> usize {
+        if position.line >= self.line_starts.len() {
+            return self.source.len();
+        }
+        let start = self.line_starts[position.line];
+        let mut units = 0;
+        for (i, ch) in self.line_text(position.line).char_indices() {
+            if units >= position.column {
+                return start + i;
+            }
+            units += ch.len_utf16();
+        }
+        start + self.line_text(position.line).len()
+    }
+}
+
+/// Owns every source file of a compilation and resolves spans against them.
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, name: impl Into<String>, source: impl Into<String>) -> FileId {
+        self.files.push(SourceFile::new(name, source));
+        FileId(self.files.len() as u32 - 1)
+    }
+
+    pub fn get(&self, file_id: FileId) -> Option<&SourceFile> {
+        self.files.get(file_id.0 as usize)
+    }
+
+    /// Start position of `span`, columns in UTF-8 bytes.
+    pub fn line_col(&self, span: Span) -> Option<LineCol> {
+        self.get(span.file_id).map(|file| file.line_col(span.start))
+    }
+
+    /// Start position of `span`, columns in UTF-16 code units.
+    pub fn line_col_utf16(&self, span: Span) -> Option<LineCol> {
+        self.get(span.file_id).map(|file| file.line_col_utf16(span.start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_lines_and_byte_columns() {
+        let file = SourceFile::new("test.tab", "let x  1\n\tprint x\n");
+        assert_eq!(file.line_count(), 3);
+        assert_eq!(file.line_col(0), LineCol { line: 0, column: 0 });
+        assert_eq!(file.line_col(8), LineCol { line: 0, column: 8 });
+        assert_eq!(file.line_col(9), LineCol { line: 1, column: 0 });
+        assert_eq!(file.line_col(16), LineCol { line: 1, column: 7 });
+        assert_eq!(file.line_text(1), "\tprint x");
+        assert_eq!(file.line_text(2), "");
+    }
+
+    #[test]
+    fn clamps_offsets_past_the_end() {
+        let file = SourceFile::new("test.tab", "ab");
+        assert_eq!(file.line_col(10), LineCol { line: 0, column: 2 });
+    }
+
+    #[test]
+    fn counts_utf16_code_units() {
+        // `é` is two bytes and one unit; `😀` is four bytes and two units.
+        let file = SourceFile::new("test.tab", "let s  \"é😀\"  x\nok");
+        let x = file.source().find("  x").unwrap() + 2;
+        assert_eq!(file.line_col(x), LineCol { line: 0, column: 17 });
+        assert_eq!(file.line_col_utf16(x), LineCol { line: 0, column: 14 });
+        assert_eq!(file.line_col_utf16(x + 2), LineCol { line: 1, column: 0 });
+    }
+
+    #[test]
+    fn converts_utf16_positions_back_to_offsets() {
+        let file = SourceFile::new("test.tab", "\"é😀\"  x\nok");
+        let x = file.source().find("  x").unwrap() + 2;
+        assert_eq!(file.offset_utf16(file.line_col_utf16(x)), x);
+        assert_eq!(file.offset_utf16(LineCol { line: 0, column: 2 }), 3);
+        // Past the end of a line clamps to the line break; past the last
+        // line, to the end of the file.
+        assert_eq!(file.offset_utf16(LineCol { line: 0, column: 99 }), x + 1);
+        assert_eq!(file.offset_utf16(LineCol { line: 1, column: 1 }), x + 3);
+        assert_eq!(file.offset_utf16(LineCol { line: 5, column: 0 }), x + 4);
+    }
+
+    #[test]
+    fn resolves_spans_in_their_own_file() {
+        let mut map = SourceMap::new();
+        let first = map.add_file("a.tab", "a\nb");
+        let second = map.add_file("b.tab", "\u{1F600}c");
+        assert_eq!(map.get(first).unwrap().name(), "a.tab");
+        let span = Span::new(second, 4, 5);
+        assert_eq!(map.line_col(span), Some(LineCol { line: 0, column: 4 }));
+        assert_eq!(map.line_col_utf16(span), Some(LineCol { line: 0, column: 2 }));
+        assert_eq!(map.line_col(Span::new(first, 2, 3)), Some(LineCol { line: 1, column: 0 }));
+        assert_eq!(map.line_col(Span::new(FileId(7), 0, 0)), None);
+    }
+}
@@ -0,0 +1,562 @@
+//! Exhaustiveness and reachability of `match` arms.
+//!
+//! Both questions reduce to the usefulness check from Maranget's "Warnings
+//! for pattern matching": a row of patterns is useful against a matrix of
+//! earlier rows when some value matches the row but no earlier one. An arm
+//! that is not useful is unreachable, and a match is exhaustive when a `_`
+//! after its last arm would not be useful.
+
+use super::inference::substitute;
+use super::{Enums, Type};
+use crate::ast::{ExpressionKind, Pattern, PatternKind, UnaryOp};
+
+/// The part of a pattern that decides which values it can match, ignoring
+/// bindings.
+#[derive(Debug, Clone, PartialEq)]
+enum Constructor {
+    Bool(bool),
+    /// Inclusive range of integers. Bounds are `i128` so that one past
+    /// `i64::MAX` still fits.
+    Int(i128, i128),
+    /// Inclusive range of character code points.
+    Char(i128, i128),
+    /// A string or float literal, spelled out. These types have too many
+    /// values to list, so only `_` or a binding covers them.
+    Literal(String),
+    None,
+    Tuple(usize),
+    Variant(String, usize),
+}
+
+impl Constructor {
+    fn arity(&self) -> usize {
+        match self {
+            Constructor::Tuple(arity) | Constructor::Variant(_, arity) => *arity,
+            _ => 0,
+        }
+    }
+
+    fn range(&self) -> Option<(i128, i128)> {
+        match self {
+            Constructor::Int(lo, hi) | Constructor::Char(lo, hi) => Some((*lo, *hi)),
+            _ => None,
+        }
+    }
+
+    /// Builds a range of the same kind as `self`.
+    fn with_range(&self, lo: i128, hi: i128) -> Constructor {
+        match self {
+            Constructor::Char(..) => Constructor::Char(lo, hi),
+            _ => Constructor::Int(lo, hi),
+        }
+    }
+
+    /// True when every value built by `other` is also built by `self`.
+    /// Ranges are split before they are compared, so a range either covers
+    /// another completely or not at all.
+    fn covers(&self, other: &Constructor) -> bool {
+        match (self, other) {
+            (Constructor::Int(lo, hi), Constructor::Int(other_lo, other_hi))
+            | (Constructor::Char(lo, hi), Constructor::Char(other_lo, other_hi)) => {
+                lo <= other_lo && other_hi <= hi
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Splits `self` into pieces that each lie entirely inside or entirely
+    /// outside every range in `heads`. Other constructors are left whole.
+    /// An empty range splits into no pieces.
+    fn split(&self, heads: &[&Constructor]) -> Vec<Constructor> {
+        let Some((lo, hi)) = self.range() else {
+            return vec![self.clone()];
+        };
+        let mut starts = vec![lo];
+        for head in heads {
+            if let Some((head_lo, head_hi)) = head.range() {
+                for start in [head_lo, head_hi + 1] {
+                    if lo < start && start <= hi {
+                        starts.push(start);
+                    }
+                }
+            }
+        }
+        starts.sort_unstable();
+        starts.dedup();
+        let mut pieces = Vec::new();
+        for (i, start) in starts.iter().enumerate() {
+            let end = starts.get(i + 1).map_or(hi, |next| next - 1);
+            if *start <= end {
+                pieces.push(self.with_range(*start, end));
+            }
+        }
+        pieces
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Constructor, Vec<Pat>),
+}
+
+impl Pat {
+    fn lower(pattern: &Pattern) -> Pat {
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding(_) => Pat::Wild,
+            PatternKind::Literal(expr) => match literal(&expr.kind) {
+                Some(ctor) => Pat::Ctor(ctor, Vec::new()),
+                None => Pat::Wild,
+            },
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => match (literal(&start.kind), literal(&end.kind)) {
+                (Some(start), Some(end)) => match (start.range(), end.range()) {
+                    (Some((lo, _)), Some((hi, _))) => {
+                        let hi = if *inclusive { hi } else { hi - 1 };
+                        Pat::Ctor(start.with_range(lo, hi), Vec::new())
+                    }
+                    _ => Pat::Wild,
+                },
+                _ => Pat::Wild,
+            },
+            PatternKind::Tuple(items) => Pat::Ctor(
+                Constructor::Tuple(items.len()),
+                items.iter().map(Pat::lower).collect(),
+            ),
+            PatternKind::Variant { name, fields } => Pat::Ctor(
+                Constructor::Variant(name.name.clone(), fields.len()),
+                fields.iter().map(Pat::lower).collect(),
+            ),
+        }
+    }
+
+    /// Formats a value this pattern stands for, in Tabula pattern syntax,
+    /// as the whole pattern of an arm.
+    fn format(&self) -> String {
+        match self {
+            Pat::Ctor(Constructor::Variant(name, _), args) if !args.is_empty() => {
+                let fields: Vec<String> = args.iter().map(Pat::format_nested).collect();
+                format!("{} {}", name, fields.join("  "))
+            }
+            _ => self.format_nested(),
+        }
+    }
+
+    /// Formats a value this pattern stands for inside another pattern,
+    /// where a variant with fields needs parentheses.
+    fn format_nested(&self) -> String {
+        match self {
+            Pat::Wild => "_".to_string(),
+            Pat::Ctor(ctor, args) => match ctor {
+                Constructor::Bool(value) => value.to_string(),
+                Constructor::Int(lo, hi) => representative(*lo, *hi).to_string(),
+                Constructor::Char(lo, hi) => char::from_u32(representative(*lo, *hi) as u32)
+                    .map_or_else(|| "_".to_string(), |c| format!("{:?}", c)),
+                Constructor::Literal(text) => text.clone(),
+                Constructor::None => "none".to_string(),
+                Constructor::Tuple(_) => {
+                    let items: Vec<String> = args.iter().map(Pat::format_nested).collect();
+                    format!("({})", items.join("  "))
+                }
+                Constructor::Variant(name, _) if args.is_empty() => name.clone(),
+                Constructor::Variant(name, _) => {
+                    let fields: Vec<String> = args.iter().map(Pat::format_nested).collect();
+                    format!("({} {})", name, fields.join("  "))
+                }
+            },
+        }
+    }
+}
+
+/// Picks the value of a range to show in a diagnostic: its start, unless
+/// that is the smallest integer.
+fn representative(lo: i128, hi: i128) -> i128 {
+    if lo == i64::MIN as i128 {
+        hi
+    } else {
+        lo
+    }
+}
+
+fn literal(kind: &ExpressionKind) -> Option<Constructor> {
+    match kind {
+        ExpressionKind::Number { value, .. } => {
+            Some(Constructor::Int(*value as i128, *value as i128))
+        }
+        ExpressionKind::Float { value, .. } => Some(Constructor::Literal(value.to_string())),
+        ExpressionKind::Unary {
+            op: UnaryOp::Negate,
+            expr,
+        } => match &expr.kind {
+            ExpressionKind::Number { value, .. } => {
+                Some(Constructor::Int(-(*value as i128), -(*value as i128)))
+            }
+            ExpressionKind::Float { value, .. } => {
+                Some(Constructor::Literal((-value).to_string()))
+            }
+            _ => None,
+        },
+        ExpressionKind::Bool(value) => Some(Constructor::Bool(*value)),
+        ExpressionKind::None => Some(Constructor::None),
+        ExpressionKind::Char { value, .. } => {
+            Some(Constructor::Char(*value as i128, *value as i128))
+        }
+        ExpressionKind::String { value, .. } => {
+            Some(Constructor::Literal(format!("{:?}", value)))
+        }
+        _ => None,
+    }
+}
+
+/// Lists every constructor of a column's type, with ranges covering the
+/// whole type. Returns `None` for types with too many values to list. When
+/// the type is unknown it is taken from the patterns in the column.
+fn all_constructors(
+    column: &Type,
+    heads: &[&Constructor],
+    enums: &Enums,
+) -> Option<Vec<Constructor>> {
+    let int = || vec![Constructor::Int(i64::MIN as i128, i64::MAX as i128)];
+    // Surrogates are not characters.
+    let char = || vec![Constructor::Char(0, 0xD7FF), Constructor::Char(0xE000, 0x10FFFF)];
+    let bool = || vec![Constructor::Bool(false), Constructor::Bool(true)];
+    let variants = |name: &str| {
+        Some(
+            enums
+                .get(name)?
+                .variants
+                .iter()
+                .map(|(variant, fields)| Constructor::Variant(variant.clone(), fields.len()))
+                .collect(),
+        )
+    };
+    match column {
+        Type::Boolean => Some(bool()),
+        Type::Number => Some(int()),
+        Type::Char => Some(char()),
+        Type::None => Some(vec![Constructor::None]),
+        // `none` and every value of the inner type.
+        Type::Option(inner) => {
+            let heads: Vec<&Constructor> = heads
+                .iter()
+                .copied()
+                .filter(|head| **head != Constructor::None)
+                .collect();
+            let mut all = vec![Constructor::None];
+            all.extend(all_constructors(inner, &heads, enums)?);
+            Some(all)
+        }
+        Type::Tuple(items) => Some(vec![Constructor::Tuple(items.len())]),
+        Type::Enum(name, _) => variants(name),
+        Type::Unknown | Type::Var { .. } => match heads.first()? {
+            Constructor::Bool(_) => Some(bool()),
+            Constructor::Int(..) => Some(int()),
+            Constructor::Char(..) => Some(char()),
+            Constructor::Tuple(arity) => Some(vec![Constructor::Tuple(*arity)]),
+            Constructor::Variant(variant, _) => variants(enum_of(variant, enums)?),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The name of the enum that declares `variant`.
+fn enum_of<'a>(variant: &str, enums: &'a Enums) -> Option<&'a str> {
+    enums
+        .iter()
+        .find(|(_, def)| def.variants.iter().any(|(name, _)| name == variant))
+        .map(|(name, _)| name.as_str())
+}
+
+/// Types of the fields a constructor takes apart.
+fn field_types(ctor: &Constructor, column: &Type, enums: &Enums) -> Vec<Type> {
+    let variant_fields = |variant: &str| {
+        let def = enums.get(enum_of(variant, enums)?)?;
+        let (_, fields) = def.variants.iter().find(|(name, _)| name == variant)?;
+        // The fields of a generic enum depend on the instance being matched.
+        let args = match column {
+            Type::Enum(_, args) if args.len() == def.params.len() => args.clone(),
+            _ => vec![Type::Unknown; def.params.len()],
+        };
+        Some(fields.iter().map(|field| substitute(field, &def.params, &args)).collect::<Vec<_>>())
+    };
+    match (ctor, column) {
+        (_, Type::Option(inner)) => field_types(ctor, inner, enums),
+        (Constructor::Tuple(arity), Type::Tuple(items)) if items.len() == *arity => items.clone(),
+        (Constructor::Variant(variant, arity), _) => variant_fields(variant)
+            .filter(|fields| fields.len() == *arity)
+            .unwrap_or_else(|| vec![Type::Unknown; *arity]),
+        _ => vec![Type::Unknown; ctor.arity()],
+    }
+}
+
+/// Keeps the rows whose first pattern matches values built by `ctor`,
+/// replacing that pattern with its fields.
+fn specialize(matrix: &[Vec<Pat>], ctor: &Constructor) -> Vec<Vec<Pat>> {
+    matrix
+        .iter()
+        .filter_map(|row| {
+            let mut fields = match &row[0] {
+                Pat::Wild => vec![Pat::Wild; ctor.arity()],
+                Pat::Ctor(head, args) if head.covers(ctor) => args.clone(),
+                Pat::Ctor(..) => return None,
+            };
+            fields.extend(row[1..].iter().cloned());
+            Some(fields)
+        })
+        .collect()
+}
+
+/// Keeps the rows whose first pattern matches anything, dropping it.
+fn default_matrix(matrix: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    matrix
+        .iter()
+        .filter(|row| matches!(row[0], Pat::Wild))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// Puts the first `ctor.arity()` patterns of a witness back together under
+/// `ctor`.
+fn rebuild(ctor: Constructor, mut witness: Vec<Pat>) -> Vec<Pat> {
+    let rest = witness.split_off(ctor.arity());
+    let mut rebuilt = vec![Pat::Ctor(ctor, witness)];
+    rebuilt.extend(rest);
+    rebuilt
+}
+
+/// Returns a witness, one pattern per column, for a value that `row`
+/// matches and no row of `matrix` does, or `None` when there is none.
+fn useful(
+    matrix: &[Vec<Pat>],
+    row: &[Pat],
+    columns: &[Type],
+    enums: &Enums,
+) -> Option<Vec<Pat>> {
+    let Some((head, rest)) = row.split_first() else {
+        return matrix.is_empty().then(Vec::new);
+    };
+    let heads: Vec<&Constructor> = matrix
+        .iter()
+        .filter_map(|row| match &row[0] {
+            Pat::Ctor(ctor, _) => Some(ctor),
+            Pat::Wild => None,
+        })
+        .collect();
+
+    let try_constructor = |ctor: Constructor, fields: Vec<Pat>| {
+        let mut row = fields;
+        row.extend(rest.iter().cloned());
+        let mut types = field_types(&ctor, &columns[0], enums);
+        types.extend(columns[1..].iter().cloned());
+        useful(&specialize(matrix, &ctor), &row, &types, enums)
+            .map(|witness| rebuild(ctor, witness))
+    };
+
+    match head {
+        Pat::Ctor(ctor, fields) => ctor
+            .split(&heads)
+            .into_iter()
+            .find_map(|piece| try_constructor(piece, fields.clone())),
+        Pat::Wild => {
+            let pieces = all_constructors(&columns[0], &heads, enums).map(|all| {
+                all.iter()
+                    .flat_map(|ctor| ctor.split(&heads))
+                    .collect::<Vec<_>>()
+            });
+            let covered = |piece: &Constructor| heads.iter().any(|head| head.covers(piece));
+            if let Some(pieces) = &pieces {
+                if pieces.iter().all(covered) {
+                    return pieces.iter().find_map(|piece| {
+                        try_constructor(piece.clone(), vec![Pat::Wild; piece.arity()])
+                    });
+                }
+            }
+
+            // Some values of this column appear in no row, so only the rows
+            // that match anything here can cover them.
+            let witness = useful(&default_matrix(matrix), rest, &columns[1..], enums)?;
+            let head = pieces
+                .filter(|_| !heads.is_empty())
+                .and_then(|pieces| pieces.into_iter().find(|piece| !covered(piece)))
+                .map_or(Pat::Wild, |ctor| {
+                    let fields = vec![Pat::Wild; ctor.arity()];
+                    Pat::Ctor(ctor, fields)
+                });
+            let mut rebuilt = vec![head];
+            rebuilt.extend(witness);
+            Some(rebuilt)
+        }
+    }
+}
+
+/// Returns a pattern for a value of type `subject` that none of `arms`
+/// matches, or `None` when the arms cover every value.
+pub fn missing_pattern(arms: &[&Pattern], subject: &Type, enums: &Enums) -> Option<String> {
+    let matrix: Vec<Vec<Pat>> = arms.iter().map(|arm| vec![Pat::lower(arm)]).collect();
+    useful(&matrix, &[Pat::Wild], std::slice::from_ref(subject), enums)
+        .map(|witness| witness[0].format())
+}
+
+/// True when `pattern` matches some value of type `subject` that none of the
+/// `earlier` arms matches.
+pub fn is_reachable(
+    earlier: &[&Pattern],
+    pattern: &Pattern,
+    subject: &Type,
+    enums: &Enums,
+) -> bool {
+    let matrix: Vec<Vec<Pat>> = earlier.iter().map(|arm| vec![Pat::lower(arm)]).collect();
+    let row = [Pat::lower(pattern)];
+    useful(&matrix, &row, std::slice::from_ref(subject), enums).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::StatementKind;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::source::Span;
+    use crate::typechecker::EnumDef;
+
+    /// The patterns of a `match` with one arm per item of `patterns`.
+    fn arms(patterns: &[&str]) -> Vec<Pattern> {
+        let mut source = String::from("match x\n");
+        for pattern in patterns {
+            source.push_str(&format!("\t{}\n\t\tprint 1\n", pattern));
+        }
+        let tokens = Lexer::new().tokenize(&source).expect("source should lex");
+        let mut program = Parser::new().parse(tokens).expect("source should parse");
+        match program.statements.remove(0).kind {
+            StatementKind::Match { arms, .. } => arms.into_iter().map(|arm| arm.pattern).collect(),
+            kind => panic!("expected a match, found {:?}", kind),
+        }
+    }
+
+    /// `enum Shape  Circle float  Rect float  float  Empty`, and the generic
+    /// `enum Maybe[T]  Just T  Nothing`.
+    fn enums() -> Enums {
+        let param = Type::Var {
+            id: 0,
+            numeric: false,
+        };
+        let shape = EnumDef {
+            span: Span::default(),
+            params: Vec::new(),
+            variants: vec![
+                ("Circle".to_string(), vec![Type::Float]),
+                ("Rect".to_string(), vec![Type::Float, Type::Float]),
+                ("Empty".to_string(), Vec::new()),
+            ],
+        };
+        let maybe = EnumDef {
+            span: Span::default(),
+            params: vec![0],
+            variants: vec![
+                ("Just".to_string(), vec![param]),
+                ("Nothing".to_string(), Vec::new()),
+            ],
+        };
+        Enums::from([("Shape".to_string(), shape), ("Maybe".to_string(), maybe)])
+    }
+
+    fn missing(patterns: &[&str], subject: Type) -> Option<String> {
+        let arms = arms(patterns);
+        let arms: Vec<&Pattern> = arms.iter().collect();
+        missing_pattern(&arms, &subject, &enums())
+    }
+
+    /// Whether the last of `patterns` matches a value the others do not.
+    fn reachable(patterns: &[&str], subject: Type) -> bool {
+        let arms = arms(patterns);
+        let (last, earlier) = arms.split_last().unwrap();
+        let earlier: Vec<&Pattern> = earlier.iter().collect();
+        is_reachable(&earlier, last, &subject, &enums())
+    }
+
+    fn shape() -> Type {
+        Type::Enum("Shape".to_string(), Vec::new())
+    }
+
+    #[test]
+    fn booleans_need_both_values() {
+        assert_eq!(missing(&["true"], Type::Boolean).as_deref(), Some("false"));
+        assert_eq!(missing(&["true", "false"], Type::Boolean), None);
+    }
+
+    #[test]
+    fn integers_need_a_catch_all() {
+        assert_eq!(
+            missing(&["0", "1..=5"], Type::Number).as_deref(),
+            Some("-1")
+        );
+        assert_eq!(missing(&["0", "n"], Type::Number), None);
+    }
+
+    #[test]
+    fn strings_are_only_covered_by_a_catch_all() {
+        assert_eq!(
+            missing(&["\"a\"", "\"b\""], Type::String).as_deref(),
+            Some("_")
+        );
+        assert_eq!(missing(&["\"a\"", "_"], Type::String), None);
+    }
+
+    #[test]
+    fn enums_need_every_variant() {
+        assert_eq!(
+            missing(&["Circle r", "Empty"], shape()).as_deref(),
+            Some("Rect _  _")
+        );
+        assert_eq!(missing(&["Circle r", "Rect w  h", "Empty"], shape()), None);
+    }
+
+    #[test]
+    fn generic_enums_take_their_fields_from_the_type_arguments() {
+        let maybe = Type::Enum("Maybe".to_string(), vec![Type::Boolean]);
+        assert_eq!(
+            missing(&["Just true", "Nothing"], maybe.clone()).as_deref(),
+            Some("Just false")
+        );
+        assert_eq!(
+            missing(&["Just true", "Just false", "Nothing"], maybe),
+            None
+        );
+    }
+
+    #[test]
+    fn optionals_need_none_and_a_value() {
+        let optional = Type::Option(Box::new(Type::Boolean));
+        assert_eq!(
+            missing(&["true", "false"], optional.clone()).as_deref(),
+            Some("none")
+        );
+        assert_eq!(missing(&["none", "b"], optional), None);
+    }
+
+    #[test]
+    fn tuples_are_checked_item_by_item() {
+        let pair = Type::Tuple(vec![Type::Boolean, Type::Boolean]);
+        assert_eq!(
+            missing(&["true  _", "_  true"], pair.clone()).as_deref(),
+            Some("(false  false)")
+        );
+        assert_eq!(missing(&["true  _", "false  _"], pair), None);
+    }
+
+    #[test]
+    fn arms_covered_by_earlier_ones_are_unreachable() {
+        assert!(!reachable(&["_", "1"], Type::Number));
+        assert!(!reachable(&["1..=5", "3"], Type::Number));
+        assert!(reachable(&["1..=5", "6"], Type::Number));
+        assert!(!reachable(
+            &["Circle _", "Rect _  _", "Empty", "_"],
+            shape()
+        ));
+        assert!(!reachable(&["'a'..='z'", "'q'"], Type::Char));
+    }
+}
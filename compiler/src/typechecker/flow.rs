@@ -0,0 +1,228 @@
+//! Which statements can run, and which can reach the statement after them.
+//!
+//! A block completes when running it can reach its end and go on to the
+//! statement after it. `return`, `break` and `continue` never complete, and
+//! neither does a statement all of whose branches end in one of them. Code
+//! after a statement that does not complete is unreachable, and a function
+//! that returns a value must not let its body complete.
+//!
+//! Conditions made only of literals are evaluated here, so that `while true`
+//! counts as a loop that only a `break` can leave.
+
+use crate::ast::{BinaryOp, Expression, ExpressionKind, Ident, Statement, StatementKind, UnaryOp};
+use std::cmp::Ordering;
+
+/// Whether running `statements` can reach their end.
+pub fn completes(statements: &[Statement]) -> bool {
+    statements.iter().all(statement_completes)
+}
+
+/// Whether running `stmt` can go on to the statement after it.
+pub fn statement_completes(stmt: &Statement) -> bool {
+    match &stmt.kind {
+        StatementKind::Return { .. }
+        | StatementKind::Break { .. }
+        | StatementKind::Continue { .. } => false,
+        StatementKind::If {
+            condition,
+            then_body,
+            else_ifs,
+            else_body,
+        } => {
+            let branches = std::iter::once((condition, then_body)).chain(
+                else_ifs
+                    .iter()
+                    .map(|else_if| (&else_if.condition, &else_if.body)),
+            );
+            for (condition, body) in branches {
+                match constant(condition) {
+                    // The branches after one that always runs never do.
+                    Some(true) => return completes(body),
+                    Some(false) => {}
+                    None if completes(body) => return true,
+                    None => {}
+                }
+            }
+            else_body.as_deref().is_none_or(completes)
+        }
+        StatementKind::While {
+            label,
+            condition,
+            body,
+        } => constant(condition) != Some(true) || breaks(body, label.as_ref(), true),
+        // The typechecker makes sure that some arm matches.
+        StatementKind::Match { arms, .. } => {
+            arms.is_empty() || arms.iter().any(|arm| completes(&arm.body))
+        }
+        _ => true,
+    }
+}
+
+/// Whether `statements` contain a `break` that leaves the loop named
+/// `label`, or the loop they are the body of when `innermost`.
+fn breaks(statements: &[Statement], label: Option<&Ident>, innermost: bool) -> bool {
+    statements.iter().any(|stmt| match &stmt.kind {
+        StatementKind::Break { label: None } => innermost,
+        StatementKind::Break {
+            label: Some(target),
+        } => label.is_some_and(|label| label.name == target.name),
+        StatementKind::If {
+            then_body,
+            else_ifs,
+            else_body,
+            ..
+        } => {
+            breaks(then_body, label, innermost)
+                || else_ifs
+                    .iter()
+                    .any(|else_if| breaks(&else_if.body, label, innermost))
+                || else_body
+                    .as_ref()
+                    .is_some_and(|body| breaks(body, label, innermost))
+        }
+        StatementKind::Match { arms, .. } => {
+            arms.iter().any(|arm| breaks(&arm.body, label, innermost))
+        }
+        // An unlabeled `break` inside a nested loop leaves that loop.
+        StatementKind::For { body, .. } | StatementKind::While { body, .. } => {
+            breaks(body, label, false)
+        }
+        _ => false,
+    })
+}
+
+/// The value of a condition made only of literals, such as `true` or
+/// `1 < 2`.
+pub fn constant(expr: &Expression) -> Option<bool> {
+    match &expr.kind {
+        ExpressionKind::Bool(value) => Some(*value),
+        ExpressionKind::Unary {
+            op: UnaryOp::Not,
+            expr,
+        } => constant(expr).map(|value| !value),
+        ExpressionKind::Binary { left, op, right } => {
+            let (a, b) = (constant(left), constant(right));
+            match op {
+                BinaryOp::And if a == Some(false) || b == Some(false) => Some(false),
+                BinaryOp::And => Some(a? && b?),
+                BinaryOp::Or if a == Some(true) || b == Some(true) => Some(true),
+                BinaryOp::Or => Some(a? || b?),
+                BinaryOp::Equal => Some(compare(left, right)? == Ordering::Equal),
+                BinaryOp::NotEqual => Some(compare(left, right)? != Ordering::Equal),
+                BinaryOp::Less => Some(compare(left, right)? == Ordering::Less),
+                BinaryOp::Greater => Some(compare(left, right)? == Ordering::Greater),
+                BinaryOp::LessEqual => Some(compare(left, right)? != Ordering::Greater),
+                BinaryOp::GreaterEqual => Some(compare(left, right)? != Ordering::Less),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// How two literals of the same type compare.
+fn compare(left: &Expression, right: &Expression) -> Option<Ordering> {
+    match (&left.kind, &right.kind) {
+        (ExpressionKind::Number { value: a, .. }, ExpressionKind::Number { value: b, .. }) => {
+            Some(a.cmp(b))
+        }
+        (ExpressionKind::Number { value: a, .. }, ExpressionKind::Float { value: b, .. }) => {
+            (*a as f64).partial_cmp(b)
+        }
+        (ExpressionKind::Float { value: a, .. }, ExpressionKind::Number { value: b, .. }) => {
+            a.partial_cmp(&(*b as f64))
+        }
+        (ExpressionKind::Float { value: a, .. }, ExpressionKind::Float { value: b, .. }) => {
+            a.partial_cmp(b)
+        }
+        (ExpressionKind::Char { value: a, .. }, ExpressionKind::Char { value: b, .. }) => {
+            Some(a.cmp(b))
+        }
+        (ExpressionKind::String { value: a, .. }, ExpressionKind::String { value: b, .. }) => {
+            Some(a.cmp(b))
+        }
+        _ => Some(constant(left)?.cmp(&constant(right)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// The statements of the program `source`.
+    fn parse(source: &str) -> Vec<Statement> {
+        let tokens = Lexer::new().tokenize(source).expect("source should lex");
+        Parser::new().parse(tokens).expect("source should parse").statements
+    }
+
+    /// Whether the body of the function that `source` declares completes.
+    fn body_completes(source: &str) -> bool {
+        match parse(source).remove(0).kind {
+            StatementKind::Function { body, .. } => completes(&body),
+            kind => panic!("expected a function, found {:?}", kind),
+        }
+    }
+
+    /// The value `constant` gives the expression `source`.
+    fn constant_of(source: &str) -> Option<bool> {
+        match parse(&format!("let c  {}\n", source)).remove(0).kind {
+            StatementKind::Let { value, .. } => constant(&value),
+            kind => panic!("expected a let, found {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn a_body_completes_unless_every_branch_returns() {
+        let cases = [
+            ("func f x\n\treturn x\n", false),
+            ("func f x\n\tif x > 0\n\t\treturn 1\n\telse\n\t\treturn 2\n", false),
+            ("func f x\n\tif x > 0\n\t\treturn 1\n\telse if x < 0\n\t\treturn 2\n", true),
+            ("func f x\n\tif x > 0\n\t\treturn 1\n", true),
+            ("func f x\n\tif true\n\t\treturn 1\n", false),
+            ("func f x\n\tif false\n\t\treturn 1\n\telse\n\t\treturn 2\n", false),
+            ("func f x\n\tfor i in 0..x\n\t\treturn i\n", true),
+            ("func f x\n\tmatch x\n\t\t0\n\t\t\treturn 1\n\t\t_\n\t\t\treturn 2\n", false),
+            ("func f x\n\tmatch x\n\t\t0\n\t\t\treturn 1\n\t\t_\n\t\t\tprint x\n", true),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(body_completes(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn a_loop_that_always_runs_completes_only_through_a_break() {
+        let cases = [
+            ("func f\n\twhile true\n\t\tprint 1\n", false),
+            ("func f\n\twhile true\n\t\tbreak\n", true),
+            ("func f x\n\twhile x > 0\n\t\tprint 1\n", true),
+            // A `break` in a nested loop leaves that loop, unless it names
+            // the outer one.
+            ("func f\n\twhile true\n\t\tfor i in 0..3\n\t\t\tbreak\n", false),
+            ("func f\n\touter: while true\n\t\tfor i in 0..3\n\t\t\tbreak outer\n", true),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(body_completes(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn conditions_of_literals_have_a_value() {
+        let cases = [
+            ("true", Some(true)),
+            ("!true", Some(false)),
+            ("1 < 2", Some(true)),
+            ("2.5 >= 3", Some(false)),
+            ("\"a\" == \"a\"", Some(true)),
+            ("'a' != 'b'", Some(true)),
+            ("false && x", Some(false)),
+            ("x || true", Some(true)),
+            ("x && true", None),
+            ("x < 2", None),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(constant_of(source), expected, "{}", source);
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! Type variables and unification.
+//!
+//! A type that is not known where it first appears, such as the type of an
+//! unannotated parameter or of an empty list, starts as a fresh type
+//! variable. Each use of a value unifies its type with the type the use
+//! requires, and the `Substitution` records what every variable has been
+//! found to stand for. Once a function's body is checked, the variables in
+//! its signature that nothing outside the function constrains are
+//! generalised: each call instantiates them afresh, so `func first xs` can
+//! be called with a list of any type.
+
+use super::Type;
+
+/// A function's signature, generic over the type variables in `vars`.
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub params: Vec<Type>,
+    pub ret: Type,
+}
+
+impl Scheme {
+    /// A signature with no generic variables.
+    pub fn mono(params: Vec<Type>, ret: Type) -> Self {
+        Self {
+            vars: Vec::new(),
+            params,
+            ret,
+        }
+    }
+}
+
+/// What each type variable has been found to stand for.
+pub struct Substitution {
+    /// The type bound to each variable, by number, once it is known.
+    bindings: Vec<Option<Type>>,
+    /// Whether each variable must be `int` or `float` because arithmetic is
+    /// applied to it.
+    numeric: Vec<bool>,
+    /// Whether each variable is bound to `int` only because an `int` was
+    /// given where it was expected, so that a `float` given there as well
+    /// makes it a `float`.
+    widenable: Vec<bool>,
+}
+
+impl Substitution {
+    pub fn new() -> Self {
+        Self {
+            bindings: Vec::new(),
+            numeric: Vec::new(),
+            widenable: Vec::new(),
+        }
+    }
+
+    pub fn fresh(&mut self) -> Type {
+        self.fresh_var(false)
+    }
+
+    /// The number of a fresh variable, for a parameter of a generic type.
+    pub fn fresh_id(&mut self) -> usize {
+        self.bindings.push(None);
+        self.numeric.push(false);
+        self.widenable.push(false);
+        self.bindings.len() - 1
+    }
+
+    fn fresh_var(&mut self, numeric: bool) -> Type {
+        let id = self.fresh_id();
+        self.numeric[id] = numeric;
+        Type::Var { id, numeric }
+    }
+
+    /// `ty` with every bound variable replaced by what it stands for.
+    pub fn resolve(&self, ty: &Type) -> Type {
+        map_vars(ty, &mut |id, _| match &self.bindings[id] {
+            Some(bound) => self.resolve(bound),
+            None => Type::Var {
+                id,
+                numeric: self.numeric[id],
+            },
+        })
+    }
+
+    /// Makes `a` and `b` the same type by binding variables in either, and
+    /// returns false when they cannot be.
+    pub fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        let (a, b) = (self.resolve(a), self.resolve(b));
+        match (&a, &b) {
+            (Type::Var { id: x, .. }, Type::Var { id: y, .. }) if x == y => true,
+            (Type::Var { id, .. }, other) | (other, Type::Var { id, .. }) => self.bind(*id, other),
+            (Type::List(x), Type::List(y)) | (Type::Option(x), Type::Option(y)) => self.unify(x, y),
+            (Type::Record(a, xs), Type::Record(b, ys)) | (Type::Enum(a, xs), Type::Enum(b, ys)) => {
+                a == b && xs.iter().zip(ys).all(|(x, y)| self.unify(x, y))
+            }
+            (Type::Map(k1, v1), Type::Map(k2, v2)) => self.unify(k1, k2) && self.unify(v1, v2),
+            (Type::Tuple(xs), Type::Tuple(ys)) => {
+                xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| self.unify(x, y))
+            }
+            (Type::Function(p1, r1), Type::Function(p2, r2)) => {
+                p1.len() == p2.len()
+                    && p1.iter().zip(p2).all(|(x, y)| self.unify(x, y))
+                    && self.unify(r1, r2)
+            }
+            _ => a == b,
+        }
+    }
+
+    /// Like `unify`, but an `int` may be used where a `float` is expected,
+    /// and `none` or a value of type `T` where a `T?` is expected, also as
+    /// the items of a list, optional or tuple.
+    pub fn coerce(&mut self, found: &Type, expected: &Type) -> bool {
+        match (self.resolve(found), self.resolve(expected)) {
+            (Type::Number, Type::Float) | (Type::None, Type::Option(_)) => true,
+            // A number that is an `int` so far may still be a `float`.
+            (Type::Number, Type::Var { id, numeric: true }) => {
+                self.widenable[id] = true;
+                self.bind(id, &Type::Number)
+            }
+            (Type::Float, Type::Number) => match self.widenable_var(expected) {
+                Some(id) => {
+                    self.bindings[id] = Some(Type::Float);
+                    true
+                }
+                None => false,
+            },
+            (Type::List(found), Type::List(expected))
+            | (Type::Option(found), Type::Option(expected)) => self.coerce(&found, &expected),
+            (Type::Tuple(found), Type::Tuple(expected)) => {
+                found.len() == expected.len()
+                    && found.iter().zip(&expected).all(|(x, y)| self.coerce(x, y))
+            }
+            (found @ (Type::Option(_) | Type::Var { .. }), expected) => {
+                self.unify(&found, &expected)
+            }
+            (found, Type::Option(inner)) => self.coerce(&found, &inner),
+            (found, expected) => self.unify(&found, &expected),
+        }
+    }
+
+    /// The type that values of types `a` and `b` both coerce to, such as
+    /// `float` for an `int` and a `float`, or `int?` for an `int` and `none`.
+    pub fn join(&mut self, a: &Type, b: &Type) -> Option<Type> {
+        let (a, b) = (self.resolve(a), self.resolve(b));
+        if self.coerce(&a, &b) {
+            Some(self.resolve(&b))
+        } else if self.coerce(&b, &a) {
+            Some(self.resolve(&a))
+        } else {
+            match (a, b) {
+                (Type::None, other) | (other, Type::None) => Some(Type::Option(Box::new(other))),
+                _ => None,
+            }
+        }
+    }
+
+    /// The variable that `ty` is bound through, if it is bound to `int` in
+    /// a way that a `float` may widen.
+    fn widenable_var(&self, ty: &Type) -> Option<usize> {
+        let Type::Var { id, .. } = ty else {
+            return None;
+        };
+        match &self.bindings[*id] {
+            Some(Type::Number) if self.widenable[*id] => Some(*id),
+            Some(bound) => self.widenable_var(bound),
+            None => None,
+        }
+    }
+
+    /// Requires `ty` to be `int` or `float`, and returns false when it is
+    /// known to be neither.
+    pub fn require_number(&mut self, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Number | Type::Float => true,
+            Type::Var { id, .. } => {
+                self.numeric[id] = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn bind(&mut self, id: usize, ty: &Type) -> bool {
+        let mut occurs = false;
+        map_vars(ty, &mut |var, _| {
+            occurs |= var == id;
+            Type::Unknown
+        });
+        if occurs {
+            return false;
+        }
+        if self.numeric[id] {
+            match ty {
+                Type::Var { id: other, .. } => self.numeric[*other] = true,
+                Type::Number | Type::Float => {}
+                _ => return false,
+            }
+        }
+        self.bindings[id] = Some(ty.clone());
+        true
+    }
+
+    /// Adds the unbound variables in `ty` to `vars`.
+    pub fn free_vars(&self, ty: &Type, vars: &mut Vec<usize>) {
+        map_vars(&self.resolve(ty), &mut |id, _| {
+            if !vars.contains(&id) {
+                vars.push(id);
+            }
+            Type::Unknown
+        });
+    }
+
+    /// The signature `params -> ret`, generic over its variables that are
+    /// not in `environment`.
+    pub fn generalize(&self, params: &[Type], ret: &Type, environment: &[usize]) -> Scheme {
+        let params: Vec<Type> = params.iter().map(|param| self.resolve(param)).collect();
+        let ret = self.resolve(ret);
+        let mut vars = Vec::new();
+        for ty in params.iter().chain([&ret]) {
+            self.free_vars(ty, &mut vars);
+        }
+        vars.retain(|var| !environment.contains(var));
+        Scheme { vars, params, ret }
+    }
+
+    /// The parameter and return types of `scheme`, with fresh variables for
+    /// the ones it is generic over.
+    pub fn instantiate(&mut self, scheme: &Scheme) -> (Vec<Type>, Type) {
+        let fresh: Vec<Type> = scheme
+            .vars
+            .iter()
+            .map(|&var| self.fresh_var(self.numeric[var]))
+            .collect();
+        let replace = |ty: &Type| substitute(&self.resolve(ty), &scheme.vars, &fresh);
+        let params = scheme.params.iter().map(replace).collect();
+        (params, replace(&scheme.ret))
+    }
+}
+
+/// `ty` with each of the variables `vars` replaced by the matching type in
+/// `types`.
+pub fn substitute(ty: &Type, vars: &[usize], types: &[Type]) -> Type {
+    map_vars(ty, &mut |id, numeric| match vars.iter().position(|&var| var == id) {
+        Some(i) => types[i].clone(),
+        None => Type::Var { id, numeric },
+    })
+}
+
+/// `ty` with each type variable replaced by `f` of its number and whether it
+/// is numeric.
+fn map_vars(ty: &Type, f: &mut impl FnMut(usize, bool) -> Type) -> Type {
+    match ty {
+        Type::Var { id, numeric } => f(*id, *numeric),
+        Type::List(item) => Type::List(Box::new(map_vars(item, f))),
+        Type::Option(inner) => Type::Option(Box::new(map_vars(inner, f))),
+        Type::Map(key, value) => {
+            Type::Map(Box::new(map_vars(key, f)), Box::new(map_vars(value, f)))
+        }
+        Type::Tuple(items) => Type::Tuple(items.iter().map(|item| map_vars(item, f)).collect()),
+        Type::Record(name, args) => {
+            Type::Record(name.clone(), args.iter().map(|arg| map_vars(arg, f)).collect())
+        }
+        Type::Enum(name, args) => {
+            Type::Enum(name.clone(), args.iter().map(|arg| map_vars(arg, f)).collect())
+        }
+        Type::Function(params, ret) => Type::Function(
+            params.iter().map(|param| map_vars(param, f)).collect(),
+            Box::new(map_vars(ret, f)),
+        ),
+        other => other.clone(),
+    }
+}
@@ -1,6 +1,15 @@
 use crate::ast::*;
-use anyhow::Result;
-use std::collections::HashMap;
+use crate::diagnostics::{codes, Diagnostic, DiagnosticResult};
+use crate::source::Span;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+mod exhaustiveness;
+mod flow;
+mod inference;
+mod order;
+
+use inference::{substitute, Scheme, Substitution};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
@@ -8,21 +17,302 @@ pub enum Type {
     Float,
     String,
     Boolean,
+    Char,
+    /// Type of the `none` literal.
+    None,
+    /// `T?`: a value of the inner type, or `none`.
+    Option(Box<Type>),
     List(Box<Type>),
+    /// Keys and values.
+    Map(Box<Type>, Box<Type>),
+    /// An integer range such as `0..n`.
+    Range,
+    Tuple(Vec<Type>),
+    /// A record type declared with `type`, by name, with its type
+    /// arguments.
+    Record(String, Vec<Type>),
+    /// An enum declared with `enum`, by name, with its type arguments.
+    Enum(String, Vec<Type>),
     Function(Vec<Type>, Box<Type>),
+    /// A type still being inferred, numbered within the checker. It is
+    /// `numeric` when it must turn out to be `int` or `float`.
+    Var { id: usize, numeric: bool },
+    /// A type the exhaustiveness check has no information about.
     Unknown,
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Number => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Boolean => write!(f, "bool"),
+            Type::Char => write!(f, "char"),
+            Type::None => write!(f, "none"),
+            Type::Option(inner) => write!(f, "{}?", inner),
+            Type::List(elem) => write!(f, "List[{}]", elem),
+            Type::Map(key, value) => write!(f, "Map[{}, {}]", key, value),
+            Type::Range => write!(f, "range"),
+            Type::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", items.join(", "))
+            }
+            Type::Record(name, args) | Type::Enum(name, args) if args.is_empty() => {
+                write!(f, "{}", name)
+            }
+            Type::Record(name, args) | Type::Enum(name, args) => {
+                let args: Vec<String> = args.iter().map(|t| t.to_string()).collect();
+                write!(f, "{}[{}]", name, args.join(", "))
+            }
+            Type::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "func({}) -> {}", params.join(", "), ret)
+            }
+            Type::Var { numeric: true, .. } => write!(f, "{{number}}"),
+            Type::Var { .. } => write!(f, "_"),
+            Type::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// The type of each expression, declared variable and function of a checked
+/// program, by span, for the backends to pick representations from.
+#[derive(Debug, Clone, Default)]
+pub struct TypeTable {
+    types: HashMap<Span, Type>,
+    /// Values given where an optional type is expected, with that type.
+    conversions: HashMap<Span, Type>,
+    /// The type of the function that each call calls, by the span of the
+    /// called name, with the types that the call gives its generic ones.
+    calls: HashMap<Span, Type>,
+    /// Reads of optional variables where a check has ruled out `none`.
+    narrowed: HashSet<Span>,
+}
+
+impl TypeTable {
+    /// The type of the expression or the variable declared at `span`. A type
+    /// that nothing settled, as in a generic function, is a `Type::Var`.
+    pub fn get(&self, span: Span) -> Option<&Type> {
+        self.types.get(&span)
+    }
+
+    /// Every type in the table, in the order of where they were found in
+    /// the source.
+    pub fn all(&self) -> Vec<&Type> {
+        let mut types: Vec<(&Span, &Type)> = self.types.iter().collect();
+        types.sort_by_key(|(span, _)| (span.file_id.0, span.start, span.end));
+        types.into_iter().map(|(_, ty)| ty).collect()
+    }
+
+    /// The optional type that the expression at `span`, a plain value or
+    /// `none`, becomes where it is used, if it is given where a `T?` is
+    /// expected.
+    pub fn conversion(&self, span: Span) -> Option<&Type> {
+        self.conversions.get(&span)
+    }
+
+    /// The type of the function called by the name at `span`, as the call
+    /// uses it: `first [1]` calls `func first xs` as `(List[int]) -> int`.
+    pub fn call(&self, span: Span) -> Option<&Type> {
+        self.calls.get(&span)
+    }
+
+    /// The table with each of the type variables `vars` replaced by the
+    /// matching type in `types`, as for one use of a generic function.
+    pub fn substitute(&self, vars: &[usize], types: &[Type]) -> TypeTable {
+        let substitute_all = |table: &HashMap<Span, Type>| {
+            table
+                .iter()
+                .map(|(span, ty)| (*span, substitute(ty, vars, types)))
+                .collect()
+        };
+        TypeTable {
+            types: substitute_all(&self.types),
+            conversions: substitute_all(&self.conversions),
+            calls: substitute_all(&self.calls),
+            narrowed: self.narrowed.clone(),
+        }
+    }
+
+    /// True when the variable read at `span` is optional, but a check has
+    /// ruled out `none` there, so the read has the type inside it.
+    pub fn is_narrowed(&self, span: Span) -> bool {
+        self.narrowed.contains(&span)
+    }
+}
+
 pub struct TypeChecker {
-    variables: HashMap<String, Type>,
-    functions: HashMap<String, (Vec<Type>, Type)>,
+    /// The scopes the statement being checked is nested in, innermost last.
+    scopes: Vec<Scope>,
+    /// Variables whose scope has ended, by name, to point at when they are
+    /// used afterwards.
+    ended: HashMap<String, Span>,
+    /// Function signatures by name, with the parameters of those declared
+    /// in the program.
+    functions: HashMap<String, (Scheme, Vec<Ident>)>,
+    records: HashMap<String, RecordDef>,
+    enums: Enums,
+    /// The enum each variant belongs to, and where the variant was declared.
+    variants: HashMap<String, (String, Span)>,
+    function: Option<FunctionContext>,
+    /// The signatures of the functions declared in the blocks being
+    /// checked whose bodies have not been checked yet.
+    signatures: HashMap<String, Signature>,
+    /// The type parameters in scope, each with the variable that stands for
+    /// it and where it was introduced.
+    type_params: Vec<(String, Type, Span)>,
+    /// Whether a capital letter that names no type introduces a type
+    /// parameter, as it does in a function signature.
+    implicit_params: bool,
+    /// What the type variables inferred so far stand for.
+    substitution: Substitution,
+    /// Problems that do not stop the program from compiling.
+    warnings: Vec<Diagnostic>,
+    /// The types found so far, resolved once the whole program is checked.
+    types: TypeTable,
+}
+
+/// What introduces a scope, which decides how its unused variables are
+/// described.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScopeKind {
+    /// A function's parameters.
+    Function,
+    /// The variable of a `for` loop.
+    Loop,
+    /// The program, a body, or a `match` arm with its pattern's bindings.
+    Block,
+}
+
+/// The variables declared in one scope.
+struct Scope {
+    kind: ScopeKind,
+    /// In declaration order. A later `let` of the same name shadows an
+    /// earlier one, which stays here so that it can still be reported as
+    /// unused.
+    variables: Vec<(String, Variable)>,
+    /// Where each name is first declared with `let` in the block, to tell a
+    /// variable used before its declaration from one never declared.
+    lets: HashMap<String, Span>,
+    /// Optional variables declared here or in an enclosing scope, by
+    /// declaration span, that a condition has shown are not `none` here.
+    narrowed: Vec<Span>,
+}
+
+/// A variable in scope.
+struct Variable {
+    ty: Type,
+    span: Span,
+    binding: Binding,
+    /// Whether it was declared with `let mut` and so may be assigned to.
+    mutable: bool,
+    /// Whether any expression reads it.
+    used: bool,
+}
+
+/// What introduced a variable, which decides how an assignment to it is
+/// reported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Binding {
+    /// A `let`, or a `let mut` when `true`.
+    Let(bool),
+    Parameter,
+    LoopVariable,
+    /// A name bound by a `match` pattern.
+    Pattern,
+}
+
+/// A record type declared with `type`.
+struct RecordDef {
+    span: Span,
+    /// The variables that stand for its type parameters in `fields`.
+    params: Vec<usize>,
+    /// Fields in declaration order.
+    fields: Vec<(String, Type)>,
+}
+
+/// An enum declared with `enum`.
+struct EnumDef {
+    span: Span,
+    /// The variables that stand for its type parameters in `variants`.
+    params: Vec<usize>,
+    /// Variants in declaration order, each with the types of its fields.
+    variants: Vec<(String, Vec<Type>)>,
+}
+
+/// Enums by name.
+type Enums = HashMap<String, EnumDef>;
+
+/// A function's signature, resolved before any statement of its block is
+/// checked so that it can be called above its declaration.
+struct Signature {
+    params: Vec<Type>,
+    ret: Type,
+    /// The return type annotation, if any.
+    origin: Option<Span>,
+    /// The type parameters its annotations introduce.
+    generic: Vec<(String, Type, Span)>,
+}
+
+/// The function whose body is being checked.
+struct FunctionContext {
+    /// The type its `return`s must produce: the annotated one, or a type
+    /// variable settled by the `return`s.
+    return_type: Type,
+    /// The return type annotation or, without one, the first `return`.
+    origin: Option<Span>,
+    annotated: bool,
+}
+
+/// True when `name` can stand for a type parameter of a function: a single
+/// capital letter such as `T`.
+fn is_type_param(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!((chars.next(), chars.next()), (Some(c), None) if c.is_ascii_uppercase())
+}
+
+/// True when `pattern` is the literal `none`.
+fn is_none(pattern: &Pattern) -> bool {
+    matches!(&pattern.kind, PatternKind::Literal(expr) if matches!(expr.kind, ExpressionKind::None))
+}
+
+/// The type named `name` when it is built in, such as `int`.
+fn resolve_builtin(name: &str) -> Option<Type> {
+    match name {
+        "int" => Some(Type::Number),
+        "float" => Some(Type::Float),
+        "string" => Some(Type::String),
+        "bool" => Some(Type::Boolean),
+        "char" => Some(Type::Char),
+        "none" => Some(Type::None),
+        "range" => Some(Type::Range),
+        _ => None,
+    }
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         let mut checker = Self {
-            variables: HashMap::new(),
+            scopes: Vec::new(),
+            ended: HashMap::new(),
             functions: HashMap::new(),
+            records: HashMap::new(),
+            enums: HashMap::new(),
+            variants: HashMap::new(),
+            function: None,
+            signatures: HashMap::new(),
+            type_params: Vec::new(),
+            implicit_params: false,
+            substitution: Substitution::new(),
+            warnings: Vec::new(),
+            types: TypeTable::default(),
         };
         checker.register_builtins();
         checker
@@ -31,134 +321,2166 @@ impl TypeChecker {
     fn register_builtins(&mut self) {
         self.functions.insert(
             "print".to_string(),
-            (vec![Type::String], Type::Unknown),
+            (Scheme::mono(vec![Type::String], Type::None), Vec::new()),
         );
+        // The list functions of the standard library.
+        let item = self.substitution.fresh();
+        let list = Type::List(Box::new(item.clone()));
+        let len = self.substitution.generalize(std::slice::from_ref(&list), &Type::Number, &[]);
+        let get = self.substitution.generalize(&[list, Type::Number], &item, &[]);
+        self.functions.insert("len".to_string(), (len, Vec::new()));
+        self.functions.insert("get".to_string(), (get, Vec::new()));
     }
 
-    pub fn check(&mut self, program: &Program) -> Result<()> {
-        for stmt in &program.statements {
-            self.check_statement(stmt)?;
+    pub fn check(&mut self, program: &Program) -> DiagnosticResult<()> {
+        self.declare_types(&program.statements)?;
+        self.check_block(&program.statements)?;
+        self.warnings
+            .sort_by_key(|warning| warning.labels.first().map(|label| label.span.start));
+        let table = &mut self.types;
+        let types = table.types.values_mut().chain(table.conversions.values_mut());
+        for ty in types.chain(table.calls.values_mut()) {
+            *ty = self.substitution.resolve(ty);
         }
         Ok(())
     }
 
-    fn check_statement(&mut self, stmt: &Statement) -> Result<()> {
-        match stmt {
-            Statement::Let { name, value } => {
-                let value_type = self.check_expression(value)?;
-                self.variables.insert(name.clone(), value_type);
+    /// The types of the program passed to the last successful `check`.
+    pub fn types(&self) -> &TypeTable {
+        &self.types
+    }
+
+    /// Warnings reported by the last call to `check`, such as unreachable
+    /// `match` arms and unused variables.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    fn check_statement(&mut self, stmt: &Statement) -> DiagnosticResult<()> {
+        match &stmt.kind {
+            StatementKind::Let {
+                name,
+                mutable,
+                ty,
+                value,
+            } => {
+                let mut value_type = self.check_expression(value)?;
+                if let Some(ty) = ty {
+                    let expected = self.resolve_type(ty)?;
+                    self.require_some(value, &value_type, &expected)?;
+                    if !self.substitution.coerce(&value_type, &expected) {
+                        let value_type = self.substitution.resolve(&value_type);
+                        return Err(Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!("mismatched types for `{}`", name),
+                        )
+                        .with_label(
+                            value.span,
+                            format!("expected `{}`, found `{}`", expected, value_type),
+                        )
+                        .with_secondary(ty.span, "expected because of this annotation"));
+                    }
+                    self.record_conversion(value, &value_type, &expected);
+                    value_type = expected;
+                }
+                self.declare(&name.name, value_type, name.span, Binding::Let(*mutable));
             }
-            Statement::Function { name, params, body } => {
-                let param_types: Vec<Type> = params.iter().map(|_| Type::Unknown).collect();
-                let return_type = self.infer_return_type(body)?;
-                self.functions.insert(
-                    name.clone(),
-                    (param_types, return_type),
-                );
+            StatementKind::Assign {
+                target,
+                fields,
+                op,
+                value,
+            } => {
+                self.check_assignment(target, fields, *op, value, stmt.span)?;
             }
-            Statement::If { condition, then_body, else_body } => {
-                let cond_type = self.check_expression(condition)?;
-                if cond_type != Type::Boolean {
-                    return Err(anyhow::anyhow!("If condition must be boolean"));
+            // Top-level types are declared before checking starts.
+            StatementKind::Type { name, .. } | StatementKind::Enum { name, .. } => {
+                if self.type_span(&name.name) != Some(name.span) {
+                    self.declare_types(std::slice::from_ref(stmt))?;
                 }
-                for stmt in then_body {
-                    self.check_statement(stmt)?;
+            }
+            // Blocks check their functions in an order of their own.
+            StatementKind::Function { .. } => {
+                self.check_functions(&[stmt])?;
+            }
+            StatementKind::If {
+                condition,
+                then_body,
+                else_ifs,
+                else_body,
+            } => {
+                let branches = std::iter::once((condition, then_body, "if")).chain(
+                    else_ifs
+                        .iter()
+                        .map(|else_if| (&else_if.condition, &else_if.body, "else if")),
+                );
+                // The variables that are not `none` once every condition so
+                // far has been false.
+                let mut otherwise = Vec::new();
+                let mut all_exit = true;
+                for (condition, body, keyword) in branches {
+                    self.push_narrowed(otherwise.clone());
+                    self.check_condition(condition, keyword)?;
+                    self.pop_scope();
+                    let mut narrowed = otherwise.clone();
+                    narrowed.extend(self.narrowings(condition, true));
+                    otherwise.extend(self.narrowings(condition, false));
+                    self.check_narrowed_block(body, narrowed)?;
+                    all_exit &= !flow::completes(body);
                 }
-                if let Some(else_body) = else_body {
-                    for stmt in else_body {
-                        self.check_statement(stmt)?;
+                match else_body {
+                    Some(else_body) => self.check_narrowed_block(else_body, otherwise)?,
+                    // The rest of the block only runs when every condition
+                    // was false, as in `if x == none` followed by `return`.
+                    None if all_exit => {
+                        if let Some(scope) = self.scopes.last_mut() {
+                            scope.narrowed.extend(otherwise);
+                        }
                     }
+                    None => {}
                 }
             }
-            Statement::For { var, iterable, body } => {
-                let iter_type = self.check_expression(iterable)?;
-                self.variables.insert(var.clone(), Type::Number);
-                for stmt in body {
-                    self.check_statement(stmt)?;
+            StatementKind::For {
+                var,
+                iterable,
+                body,
+                ..
+            } => {
+                let item_type = match self.check_value(iterable)? {
+                    Type::Range => Type::Number,
+                    Type::List(item) => *item,
+                    Type::String => Type::Char,
+                    // Taken to be a list when nothing else is known.
+                    var @ Type::Var { .. } => {
+                        let item = self.substitution.fresh();
+                        self.substitution.unify(&var, &Type::List(Box::new(item.clone())));
+                        item
+                    }
+                    other => {
+                        return Err(Diagnostic::error(
+                            codes::NOT_ITERABLE,
+                            format!("cannot iterate over `{}`", other),
+                        )
+                        .with_label(iterable.span, "expected a range, list or string")
+                        .with_help(if other == Type::Number {
+                            "to repeat `n` times, iterate over the range `0..n`"
+                        } else {
+                            "iterate over a range such as `0..n`"
+                        }));
+                    }
+                };
+                self.push_scope(ScopeKind::Loop, &[]);
+                self.declare(&var.name, item_type, var.span, Binding::LoopVariable);
+                self.check_block(body)?;
+                self.pop_scope();
+            }
+            StatementKind::While { condition, body, .. } => {
+                self.check_condition(condition, "while")?;
+                let narrowed = self.narrowings(condition, true);
+                self.check_narrowed_block(body, narrowed)?;
+            }
+            StatementKind::Match { subject, arms } => {
+                let subject_type = self.check_expression(subject)?;
+                // Arms with a guard may not match, so they cover nothing.
+                let mut covering: Vec<&Pattern> = Vec::new();
+                for arm in arms {
+                    // The pattern's bindings are only visible in its arm.
+                    self.push_scope(ScopeKind::Block, &arm.body);
+                    self.declare_functions(&arm.body)?;
+                    // Once a `none` arm has matched, the rest see the value.
+                    let pattern_type = match self.substitution.resolve(&subject_type) {
+                        Type::Option(inner) if covering.iter().any(|p| is_none(p)) => *inner,
+                        subject_type => subject_type,
+                    };
+                    self.check_pattern(&arm.pattern, &pattern_type)?;
+                    if let Some(guard) = &arm.guard {
+                        let guard_type = self.check_expression(guard)?;
+                        if !self.substitution.unify(&guard_type, &Type::Boolean) {
+                            return Err(Diagnostic::error(
+                                codes::NON_BOOLEAN_CONDITION,
+                                "match guard must be a boolean",
+                            )
+                            .with_label(
+                                guard.span,
+                                format!("expected `bool`, found `{}`", guard_type),
+                            ));
+                        }
+                    }
+                    // Patterns may have settled a subject type being inferred.
+                    let subject_type = self.substitution.resolve(&subject_type);
+                    if !exhaustiveness::is_reachable(
+                        &covering,
+                        &arm.pattern,
+                        &subject_type,
+                        &self.enums,
+                    ) {
+                        let label = if covering.is_empty() {
+                            "this pattern matches no value"
+                        } else {
+                            "earlier arms match every value this one does"
+                        };
+                        self.warnings.push(
+                            Diagnostic::warning(codes::UNREACHABLE_PATTERN, "unreachable pattern")
+                                .with_label(arm.pattern.span, label),
+                        );
+                    }
+                    if arm.guard.is_none() {
+                        covering.push(&arm.pattern);
+                    }
+                    self.check_statements(&arm.body)?;
+                    self.pop_scope();
+                }
+
+                let subject_type = self.substitution.resolve(&subject_type);
+                let missing =
+                    exhaustiveness::missing_pattern(&covering, &subject_type, &self.enums);
+                if let Some(missing) = missing {
+                    let mut diagnostic = Diagnostic::error(
+                        codes::NON_EXHAUSTIVE_MATCH,
+                        format!("non-exhaustive patterns: `{}` not covered", missing),
+                    )
+                    .with_label(subject.span, format!("pattern `{}` not covered", missing))
+                    .with_help("add an arm for it, or end the match with a `_` arm");
+                    if arms.iter().any(|arm| arm.guard.is_some()) {
+                        diagnostic = diagnostic
+                            .with_note("arms with an `if` guard do not count towards coverage");
+                    }
+                    return Err(diagnostic);
                 }
             }
-            Statement::Print { args } => {
+            StatementKind::Print { args } => {
                 for arg in args {
                     self.check_expression(arg)?;
                 }
             }
-            Statement::Return { value } => {
-                if let Some(v) = value {
-                    self.check_expression(v)?;
+            StatementKind::Return { value } => {
+                let found = match value {
+                    Some(v) => self.check_expression(v)?,
+                    None => Type::None,
+                };
+                let span = value.as_ref().map_or(stmt.span, |v| v.span);
+                let Some(context) = &self.function else {
+                    return Ok(());
+                };
+                let (return_type, origin, annotated) =
+                    (context.return_type.clone(), context.origin, context.annotated);
+                if let Some(value) = value {
+                    self.require_some(value, &found, &return_type)?;
+                }
+                if !self.substitution.coerce(&found, &return_type) {
+                    let expected = self.substitution.resolve(&return_type);
+                    let mut diagnostic =
+                        Diagnostic::error(codes::TYPE_MISMATCH, "mismatched return type")
+                            .with_label(
+                                span,
+                                format!("expected `{}`, found `{}`", expected, found),
+                            );
+                    // `none` and a value together need an optional type.
+                    match (&found, &expected) {
+                        (Type::None, value) | (value, Type::None)
+                            if !matches!(value, Type::Option(_) | Type::Var { .. }) =>
+                        {
+                            diagnostic = diagnostic.with_help(format!(
+                                "to return either, annotate the return type as `{}?`",
+                                value
+                            ));
+                        }
+                        _ => {}
+                    }
+                    return Err(match origin {
+                        Some(origin) if annotated => diagnostic
+                            .with_secondary(origin, "expected because of this return type"),
+                        Some(origin) => diagnostic.with_secondary(
+                            origin,
+                            format!("expected because this returns `{}`", expected),
+                        ),
+                        None => diagnostic,
+                    });
+                }
+                if let Some(value) = value {
+                    self.record_conversion(value, &found, &return_type);
+                }
+                if let Some(context) = &mut self.function {
+                    context.origin.get_or_insert(span);
                 }
             }
-            Statement::Expression(expr) => {
+            StatementKind::Expression(expr) => {
                 self.check_expression(expr)?;
             }
+            // The parser has already matched jumps to their loops.
+            StatementKind::Break { .. }
+            | StatementKind::Continue { .. }
+            | StatementKind::Comment(_)
+            | StatementKind::Error => {}
+        }
+        Ok(())
+    }
+
+    /// Checks `statements` in a scope of their own.
+    fn check_block(&mut self, statements: &[Statement]) -> DiagnosticResult<()> {
+        self.check_narrowed_block(statements, Vec::new())
+    }
+
+    /// Checks `statements` in a scope of their own, in which the variables
+    /// declared at `narrowed` are known not to be `none`.
+    fn check_narrowed_block(
+        &mut self,
+        statements: &[Statement],
+        narrowed: Vec<Span>,
+    ) -> DiagnosticResult<()> {
+        self.push_scope(ScopeKind::Block, statements);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.narrowed = narrowed;
+        }
+        self.check_statements(statements)?;
+        self.pop_scope();
+        Ok(())
+    }
+
+    /// Checks `statements` in the current scope, and warns about the first
+    /// one that follows a `return` or jump and so never runs. The functions
+    /// declared among them are checked first, in the order that
+    /// `order::function_groups` gives, except those that read a variable of
+    /// the block, which wait until it is declared.
+    fn check_statements(&mut self, statements: &[Statement]) -> DiagnosticResult<()> {
+        self.declare_functions(statements)?;
+        let lets = self.scopes.last().map(|scope| &scope.lets);
+        let groups = order::function_groups(statements, |name| {
+            lets.is_some_and(|lets| lets.contains_key(name))
+        });
+        let group = |group: &order::Group| -> Vec<&Statement> {
+            group.functions.iter().map(|&i| &statements[i]).collect()
+        };
+        for ahead in groups.iter().filter(|group| group.after.is_none()) {
+            self.check_functions(&group(ahead))?;
+        }
+
+        let mut exit: Option<Span> = None;
+        let mut warned = false;
+        for (i, stmt) in statements.iter().enumerate() {
+            match exit {
+                Some(exit) if !warned && !matches!(stmt.kind, StatementKind::Comment(_)) => {
+                    self.warnings.push(
+                        Diagnostic::warning(codes::UNREACHABLE_CODE, "unreachable statement")
+                            .with_label(stmt.span, "unreachable statement")
+                            .with_secondary(exit, "any code following this is unreachable"),
+                    );
+                    warned = true;
+                }
+                _ => {}
+            }
+            if !matches!(stmt.kind, StatementKind::Function { .. }) {
+                self.check_statement(stmt)?;
+            }
+            for waiting in groups.iter().filter(|group| group.after == Some(i)) {
+                self.check_functions(&group(waiting))?;
+            }
+            if exit.is_none() && !flow::statement_completes(stmt) {
+                exit = Some(stmt.span);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the bodies of `functions`, which may call each other, and then
+    /// makes each generic over the types that none of them settled.
+    fn check_functions(&mut self, functions: &[&Statement]) -> DiagnosticResult<()> {
+        let mut checked = Vec::new();
+        for &stmt in functions {
+            if let Some(signature) = self.check_function(stmt)? {
+                checked.push((stmt, signature));
+            }
+        }
+        let names: Vec<&str> = checked
+            .iter()
+            .filter_map(|(stmt, _)| match &stmt.kind {
+                StatementKind::Function { name, .. } => Some(name.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        let environment = self.environment_vars(&names);
+        for (stmt, (param_types, ret)) in checked {
+            let StatementKind::Function { name, params, .. } = &stmt.kind else {
+                continue;
+            };
+            let scheme = self.substitution.generalize(&param_types, &ret, &environment);
+            let names = params.iter().map(|param| param.name.clone()).collect();
+            self.functions.insert(name.name.clone(), (scheme, names));
+        }
+        Ok(())
+    }
+
+    /// Checks the body of the function declared by `stmt`, giving the types
+    /// of its parameters and result, not yet generalized.
+    fn check_function(&mut self, stmt: &Statement) -> DiagnosticResult<Option<(Vec<Type>, Type)>> {
+        let StatementKind::Function {
+            name, params, body, ..
+        } = &stmt.kind
+        else {
+            return Ok(None);
+        };
+        // The signature is normally resolved when the enclosing block is
+        // entered, unless a nested function of the same name has taken its
+        // place since.
+        if !self.signatures.contains_key(&name.name) {
+            self.declare_functions(std::slice::from_ref(stmt))?;
+        }
+        let Some(signature) = self.signatures.remove(&name.name) else {
+            return Ok(None);
+        };
+        let Signature {
+            params: param_types,
+            ret,
+            origin,
+            generic,
+        } = signature;
+        let function_type = Type::Function(param_types.clone(), Box::new(ret.clone()));
+        self.types.types.insert(name.span, function_type);
+        let outer_params = self.type_params.len();
+        self.type_params.extend(generic);
+
+        // Parameters are only visible inside the body.
+        self.push_scope(ScopeKind::Function, &[]);
+        for (param, ty) in params.iter().zip(param_types.iter()) {
+            self.declare(&param.name.name, ty.clone(), param.name.span, Binding::Parameter);
+        }
+        let context = FunctionContext {
+            return_type: ret.clone(),
+            origin,
+            annotated: origin.is_some(),
+        };
+        let enclosing = self.function.replace(context);
+        self.check_block(body)?;
+        let context = std::mem::replace(&mut self.function, enclosing);
+        self.pop_scope();
+        let generic = self.type_params.split_off(outer_params);
+        self.check_generic(name, &generic)?;
+
+        // A function without a `return` value returns `none`.
+        let returns = context.as_ref().is_some_and(|context| context.origin.is_some());
+        if !returns && !self.substitution.unify(&ret, &Type::None) {
+            return Err(Diagnostic::error(codes::TYPE_MISMATCH, "mismatched return type")
+                .with_label(
+                    name.span,
+                    format!(
+                        "`{}` returns nothing, but its result is used as `{}`",
+                        name,
+                        self.substitution.resolve(&ret)
+                    ),
+                ));
+        }
+        // One that returns a value must do so on every path.
+        let ret_type = self.substitution.resolve(&ret);
+        if let Some(context) = context.filter(|_| ret_type != Type::None) {
+            if flow::completes(body) {
+                let diagnostic = Diagnostic::error(
+                    codes::MISSING_RETURN,
+                    format!("`{}` does not return a value on every path", name),
+                )
+                .with_label(name.span, "some paths reach the end of the body")
+                .with_help("add a `return` to the end of the body");
+                return Err(match context.origin {
+                    Some(origin) if context.annotated => diagnostic.with_secondary(
+                        origin,
+                        format!("expected `{}` because of this return type", ret_type),
+                    ),
+                    Some(origin) => diagnostic
+                        .with_secondary(origin, format!("returns `{}` here", ret_type)),
+                    None => diagnostic,
+                });
+            }
+        }
+        Ok(Some((param_types, ret)))
+    }
+
+    /// Registers the signatures of the functions declared in `statements`
+    /// before any of their bodies is checked, so that they can call each
+    /// other in any order. Unannotated types are inferred later from how
+    /// the bodies use them, and a capital letter such as `T` that names no
+    /// type makes a function generic over it.
+    fn declare_functions(&mut self, statements: &[Statement]) -> DiagnosticResult<()> {
+        let mut declared: HashMap<&str, Span> = HashMap::new();
+        for stmt in statements {
+            let StatementKind::Function {
+                name,
+                params,
+                return_type,
+                ..
+            } = &stmt.kind
+            else {
+                continue;
+            };
+            if let Some(&first) = declared.get(name.name.as_str()) {
+                return Err(Diagnostic::error(
+                    codes::DUPLICATE_DEFINITION,
+                    format!("the function `{}` is defined more than once", name),
+                )
+                .with_label(name.span, "redefined here")
+                .with_secondary(first, "first defined here"));
+            }
+            declared.insert(&name.name, name.span);
+
+            let outer_params = self.type_params.len();
+            self.implicit_params = true;
+            let param_types = params
+                .iter()
+                .map(|param| match &param.ty {
+                    Some(ty) => self.resolve_type(ty),
+                    None => Ok(self.substitution.fresh()),
+                })
+                .collect::<DiagnosticResult<Vec<_>>>()?;
+            let (ret, origin) = match return_type {
+                Some(ty) => (self.resolve_type(ty)?, Some(ty.span)),
+                None => (self.substitution.fresh(), None),
+            };
+            self.implicit_params = false;
+            let generic = self.type_params.split_off(outer_params);
+
+            // Until its body is checked, a function is not generic: calls
+            // to it narrow its types like the body does.
+            let names = params.iter().map(|param| param.name.clone()).collect();
+            self.functions.insert(
+                name.name.clone(),
+                (Scheme::mono(param_types.clone(), ret.clone()), names),
+            );
+            let signature = Signature {
+                params: param_types,
+                ret,
+                origin,
+                generic,
+            };
+            self.signatures.insert(name.name.clone(), signature);
         }
         Ok(())
     }
 
-    fn check_expression(&self, expr: &Expression) -> Result<Type> {
-        match expr {
-            Expression::Number(_) => Ok(Type::Number),
-            Expression::Float(_) => Ok(Type::Float),
-            Expression::String(_) => Ok(Type::String),
-            Expression::Variable(name) => {
-                self.variables
-                    .get(name)
-                    .cloned()
-                    .ok_or_else(|| anyhow::anyhow!("Undefined variable: {}", name))
-            }
-            Expression::Binary { left, op, right } => {
-                let left_type = self.check_expression(left)?;
-                let right_type = self.check_expression(right)?;
+    /// Opens a scope for the block `statements`, or for the variables of a
+    /// function or loop when they are empty.
+    fn push_scope(&mut self, kind: ScopeKind, statements: &[Statement]) {
+        let mut lets = HashMap::new();
+        for stmt in statements {
+            if let StatementKind::Let { name, .. } = &stmt.kind {
+                lets.entry(name.name.clone()).or_insert(name.span);
+            }
+        }
+        self.scopes.push(Scope {
+            kind,
+            variables: Vec::new(),
+            lets,
+            narrowed: Vec::new(),
+        });
+    }
+
+    /// Closes the innermost scope, warning about the variables in it that
+    /// were never read. A name starting with `_` marks a variable as unused
+    /// on purpose.
+    fn pop_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        let what = match scope.kind {
+            ScopeKind::Function => "parameter",
+            ScopeKind::Loop => "loop variable",
+            ScopeKind::Block => "variable",
+        };
+        for (name, variable) in scope.variables {
+            if !variable.used && !name.starts_with('_') {
+                self.warnings.push(
+                    Diagnostic::warning(
+                        codes::UNUSED_VARIABLE,
+                        format!("unused {} `{}`", what, name),
+                    )
+                    .with_label(variable.span, "never read")
+                    .with_help(format!(
+                        "if this is intentional, prefix it with an underscore: `_{}`",
+                        name
+                    )),
+                );
+            }
+            self.ended.insert(name, variable.span);
+        }
+    }
+
+    /// Opens a scope in which the variables declared at `narrowed` are known
+    /// not to be `none`.
+    fn push_narrowed(&mut self, narrowed: Vec<Span>) {
+        self.push_scope(ScopeKind::Block, &[]);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.narrowed = narrowed;
+        }
+    }
+
+    /// The optional variables that `condition` shows are not `none` when its
+    /// value is `holds`: `x != none` when true, `x == none` when false, and
+    /// the same through `&&`, `||` and `!`. A `let mut` variable may be set
+    /// to `none` again after the check, so it is never narrowed.
+    fn narrowings(&self, condition: &Expression, holds: bool) -> Vec<Span> {
+        match &condition.kind {
+            ExpressionKind::Binary {
+                left,
+                op: op @ (BinaryOp::Equal | BinaryOp::NotEqual),
+                right,
+            } if (*op == BinaryOp::NotEqual) == holds => {
+                let name = match (&left.kind, &right.kind) {
+                    (ExpressionKind::Variable(name), ExpressionKind::None)
+                    | (ExpressionKind::None, ExpressionKind::Variable(name)) => name,
+                    _ => return Vec::new(),
+                };
+                self.lookup(name)
+                    .filter(|variable| !variable.mutable)
+                    .filter(|variable| {
+                        matches!(self.substitution.resolve(&variable.ty), Type::Option(_))
+                    })
+                    .map(|variable| variable.span)
+                    .into_iter()
+                    .collect()
+            }
+            ExpressionKind::Binary {
+                left,
+                op: BinaryOp::And,
+                right,
+            } if holds => [self.narrowings(left, true), self.narrowings(right, true)].concat(),
+            ExpressionKind::Binary {
+                left,
+                op: BinaryOp::Or,
+                right,
+            } if !holds => [self.narrowings(left, false), self.narrowings(right, false)].concat(),
+            ExpressionKind::Unary {
+                op: UnaryOp::Not,
+                expr,
+            } => self.narrowings(expr, !holds),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The type of a read of the variable declared at `span` with type `ty`:
+    /// the type without its `?` where a condition has ruled out `none`.
+    fn narrow(&self, ty: &Type, span: Span) -> Type {
+        match self.substitution.resolve(ty) {
+            Type::Option(inner)
+                if self.scopes.iter().any(|scope| scope.narrowed.contains(&span)) =>
+            {
+                *inner
+            }
+            ty => ty,
+        }
+    }
+
+    /// Declares a variable in the innermost scope, shadowing any variable
+    /// of the same name declared before it.
+    fn declare(&mut self, name: &str, ty: Type, span: Span, binding: Binding) {
+        self.types.types.insert(span, ty.clone());
+        let variable = Variable {
+            ty,
+            span,
+            binding,
+            mutable: binding == Binding::Let(true),
+            used: false,
+        };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.variables.push((name.to_string(), variable));
+        }
+    }
+
+    /// The variable that `name` refers to: the innermost one declared so far.
+    fn variable(&mut self, name: &str) -> Option<&mut Variable> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.variables.iter_mut().rev())
+            .find(|(declared, _)| declared == name)
+            .map(|(_, variable)| variable)
+    }
+
+    /// Like `variable`, without marking anything.
+    fn lookup(&self, name: &str) -> Option<&Variable> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.variables.iter().rev())
+            .find(|(declared, _)| declared == name)
+            .map(|(_, variable)| variable)
+    }
+
+    /// The error for `name` used at `span` where no variable of that name is
+    /// in scope: it is declared further down, or in a block that has ended,
+    /// or not at all.
+    fn undefined_variable(&self, name: &str, span: Span) -> Diagnostic {
+        let later = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.lets.get(name))
+            .filter(|declared| declared.start > span.start);
+        if let Some(&declared) = later {
+            return Diagnostic::error(
+                codes::USE_BEFORE_DECLARATION,
+                format!("cannot use `{}` before it is declared", name),
+            )
+            .with_label(span, "used here")
+            .with_secondary(declared, "declared here");
+        }
+        let diagnostic = Diagnostic::error(
+            codes::UNDEFINED_VARIABLE,
+            format!("undefined variable `{}`", name),
+        )
+        .with_label(span, "not found in this scope");
+        match self.ended.get(name) {
+            Some(&declared) => diagnostic.with_secondary(
+                declared,
+                format!("`{}` is declared here, but its block has ended", name),
+            ),
+            None => diagnostic,
+        }
+    }
+
+    /// Checks that `target` was declared with `let mut` and that the value
+    /// assigned to it, or to the field at the end of `fields`, keeps its type
+    /// when combined with `op` for a compound assignment.
+    fn check_assignment(
+        &mut self,
+        target: &Ident,
+        fields: &[Ident],
+        op: Option<BinaryOp>,
+        value: &Expression,
+        span: Span,
+    ) -> DiagnosticResult<()> {
+        let Some(variable) = self.variable(&target.name) else {
+            return Err(self
+                .undefined_variable(&target.name, target.span)
+                .with_help(format!("declare it first: `let mut {}  ...`", target)));
+        };
+        let (variable_type, declared, binding) =
+            (variable.ty.clone(), variable.span, variable.binding);
+        let immutable = |message: String, label: &str, secondary: &str| {
+            Diagnostic::error(codes::ASSIGN_TO_IMMUTABLE, message)
+                .with_label(span, label)
+                .with_secondary(declared, secondary)
+        };
+        match binding {
+            Binding::Let(true) => {}
+            Binding::Let(false) => {
+                return Err(immutable(
+                    format!("cannot assign twice to immutable variable `{}`", target),
+                    "cannot assign twice to immutable variable",
+                    "first assigned here",
+                )
+                .with_help(format!("make it mutable: `let mut {}`", target)));
+            }
+            // The loop sets it afresh on each pass, so it is never mutable.
+            Binding::LoopVariable => {
+                return Err(immutable(
+                    format!("cannot assign to loop variable `{}`", target),
+                    "cannot assign to a loop variable",
+                    "the loop sets it on each pass",
+                ));
+            }
+            Binding::Parameter => {
+                return Err(immutable(
+                    format!("cannot assign to parameter `{}`", target),
+                    "cannot assign to a parameter",
+                    "declared here",
+                )
+                .with_help(format!("copy it into a mutable variable: `let mut {0}  {0}`", target)));
+            }
+            Binding::Pattern => {
+                return Err(immutable(
+                    format!("cannot assign to `{}`, which a pattern binds", target),
+                    "cannot assign to a pattern binding",
+                    "bound here",
+                ));
+            }
+        }
+
+        // The place assigned to, as an expression: `p` or `p.x`.
+        let mut place = Expression::new(ExpressionKind::Variable(target.name.clone()), target.span);
+        let mut target_type = variable_type;
+        for field in fields {
+            if let Type::Option(_) = self.substitution.resolve(&target_type) {
+                return Err(self.possibly_none(&place, &target_type));
+            }
+            // The backends find the field in the record type of the place.
+            self.types.types.insert(place.span, target_type.clone());
+            target_type = self.field_type(&target_type, field)?;
+            place = Expression::new(
+                ExpressionKind::Field {
+                    target: Box::new(place),
+                    field: field.clone(),
+                },
+                target.span.to(field.span),
+            );
+        }
+
+        let value_type = match op {
+            // Reuse the operator rules by checking `place op value`.
+            Some(op) => self.check_expression(&Expression::new(
+                ExpressionKind::Binary {
+                    left: Box::new(place.clone()),
+                    op,
+                    right: Box::new(value.clone()),
+                },
+                span,
+            ))?,
+            None => self.check_expression(value)?,
+        };
+        self.require_some(value, &value_type, &target_type)?;
+        if !self.substitution.coerce(&value_type, &target_type) {
+            let target_type = self.substitution.resolve(&target_type);
+            let diagnostic = Diagnostic::error(
+                codes::TYPE_MISMATCH,
+                format!("cannot assign `{}` to `{}`", value_type, place.format()),
+            )
+            .with_label(
+                value.span,
+                format!("expected `{}`, found `{}`", target_type, value_type),
+            );
+            return Err(if fields.is_empty() {
+                diagnostic.with_secondary(
+                    declared,
+                    format!("`{}` is declared as `{}` here", target, target_type),
+                )
+            } else {
+                diagnostic
+            });
+        }
+        self.record_conversion(value, &value_type, &target_type);
+        Ok(())
+    }
+
+    /// Registers the record types and enums declared in `statements`. All
+    /// the names are known before any fields are read, so types can refer
+    /// to each other in any order.
+    fn declare_types(&mut self, statements: &[Statement]) -> DiagnosticResult<()> {
+        let declarations: Vec<(&Ident, &[Ident], &StatementKind)> = statements
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StatementKind::Type { name, params, .. }
+                | StatementKind::Enum { name, params, .. } => Some((name, &params[..], &stmt.kind)),
+                _ => None,
+            })
+            .collect();
+
+        for (name, params, kind) in &declarations {
+            let builtin = resolve_builtin(&name.name).is_some()
+                || matches!(name.name.as_str(), "List" | "Map");
+            if builtin {
+                return Err(Diagnostic::error(
+                    codes::DUPLICATE_DEFINITION,
+                    format!("`{}` is already a built-in type", name),
+                )
+                .with_label(name.span, "cannot be redefined"));
+            }
+            if let Some(first) = self.type_span(&name.name) {
+                return Err(Diagnostic::error(
+                    codes::DUPLICATE_DEFINITION,
+                    format!("the type `{}` is defined more than once", name),
+                )
+                .with_label(name.span, "redefined here")
+                .with_secondary(first, "first defined here"));
+            }
+            for (i, param) in params.iter().enumerate() {
+                if let Some(first) = params[..i].iter().find(|p| p.name == param.name) {
+                    return Err(Diagnostic::error(
+                        codes::DUPLICATE_DEFINITION,
+                        format!("the type parameter `{}` is declared more than once", param),
+                    )
+                    .with_label(param.span, "redeclared here")
+                    .with_secondary(first.span, "first declared here"));
+                }
+            }
+            let type_params = params.iter().map(|_| self.substitution.fresh_id()).collect();
+            let StatementKind::Enum { variants, .. } = kind else {
+                let record = RecordDef {
+                    span: name.span,
+                    params: type_params,
+                    fields: Vec::new(),
+                };
+                self.records.insert(name.name.clone(), record);
+                continue;
+            };
+            for variant in variants {
+                if let Some((_, first)) = self.variants.get(&variant.name.name) {
+                    return Err(Diagnostic::error(
+                        codes::DUPLICATE_DEFINITION,
+                        format!("the variant `{}` is defined more than once", variant.name),
+                    )
+                    .with_label(variant.name.span, "redefined here")
+                    .with_secondary(*first, "first defined here"));
+                }
+                self.variants.insert(
+                    variant.name.name.clone(),
+                    (name.name.clone(), variant.name.span),
+                );
+            }
+            let def = EnumDef {
+                span: name.span,
+                params: type_params,
+                variants: Vec::new(),
+            };
+            self.enums.insert(name.name.clone(), def);
+        }
+
+        // Fields are resolved with the type's own parameters in scope.
+        let enclosing = std::mem::take(&mut self.type_params);
+        for (name, params, kind) in declarations {
+            let vars = match kind {
+                StatementKind::Type { .. } => &self.records[&name.name].params,
+                _ => &self.enums[&name.name].params,
+            };
+            self.type_params = params
+                .iter()
+                .zip(vars)
+                .map(|(param, &id)| {
+                    let var = Type::Var { id, numeric: false };
+                    (param.name.clone(), var, param.span)
+                })
+                .collect();
+            match kind {
+                StatementKind::Type { fields, .. } => {
+                    let mut resolved: Vec<(String, Type)> = Vec::new();
+                    for field in fields {
+                        let first = fields.iter().find(|f| f.name.name == field.name.name);
+                        if let Some(first) = first {
+                            if first.span != field.span {
+                                return Err(Diagnostic::error(
+                                    codes::DUPLICATE_FIELD,
+                                    format!("field `{}` is declared more than once", field.name),
+                                )
+                                .with_label(field.name.span, "redeclared here")
+                                .with_secondary(first.name.span, "first declared here"));
+                            }
+                        }
+                        resolved.push((field.name.name.clone(), self.resolve_type(&field.ty)?));
+                    }
+                    if let Some(record) = self.records.get_mut(&name.name) {
+                        record.fields = resolved;
+                    }
+                }
+                StatementKind::Enum { variants, .. } => {
+                    let mut resolved: Vec<(String, Vec<Type>)> = Vec::new();
+                    for variant in variants {
+                        let fields = variant
+                            .fields
+                            .iter()
+                            .map(|field| self.resolve_type(field))
+                            .collect::<DiagnosticResult<Vec<_>>>()?;
+                        resolved.push((variant.name.name.clone(), fields));
+                    }
+                    if let Some(def) = self.enums.get_mut(&name.name) {
+                        def.variants = resolved;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.type_params = enclosing;
+        Ok(())
+    }
+
+    /// Where the record type or enum called `name` was declared.
+    fn type_span(&self, name: &str) -> Option<Span> {
+        let record = self.records.get(name).map(|record| record.span);
+        record.or_else(|| self.enums.get(name).map(|def| def.span))
+    }
+
+    /// Fresh type variables to fill the parameters `params` of a generic
+    /// type, and so create a new instance of it.
+    fn fresh_args(&mut self, params: &[usize]) -> Vec<Type> {
+        params.iter().map(|_| self.substitution.fresh()).collect()
+    }
+
+    /// A new instance of the enum that declares the variant `name`, and the
+    /// types of the variant's fields in that instance.
+    fn variant(&mut self, name: &Ident) -> DiagnosticResult<(Type, Vec<Type>)> {
+        let found = self.variants.get(&name.name).and_then(|(enum_name, _)| {
+            let def = self.enums.get(enum_name)?;
+            let (_, fields) = def.variants.iter().find(|(variant, _)| *variant == name.name)?;
+            Some((enum_name.clone(), def.params.clone(), fields.clone()))
+        });
+        let Some((enum_name, params, fields)) = found else {
+            return Err(Diagnostic::error(
+                codes::UNDEFINED_VARIANT,
+                format!("no enum variant named `{}`", name),
+            )
+            .with_label(name.span, "not found in this scope"));
+        };
+        let args = self.fresh_args(&params);
+        let fields = fields
+            .iter()
+            .map(|field| substitute(field, &params, &args))
+            .collect();
+        Ok((Type::Enum(enum_name, args), fields))
+    }
+
+    /// Turns a type written in the source into a `Type`.
+    fn resolve_type(&mut self, ty: &TypeExpr) -> DiagnosticResult<Type> {
+        let (name, args) = match &ty.kind {
+            TypeExprKind::Tuple(items) => {
+                return Ok(Type::Tuple(
+                    items
+                        .iter()
+                        .map(|item| self.resolve_type(item))
+                        .collect::<DiagnosticResult<Vec<_>>>()?,
+                ))
+            }
+            // `none` and types that are already optional stay as they are.
+            TypeExprKind::Optional(inner) => {
+                return Ok(match self.resolve_type(inner)? {
+                    inner @ (Type::None | Type::Option(_)) => inner,
+                    inner => Type::Option(Box::new(inner)),
+                })
+            }
+            TypeExprKind::Named { name, args } => (name, args),
+        };
+        let param = self
+            .type_params
+            .iter()
+            .find(|(param, _, _)| *param == name.name)
+            .map(|(_, var, _)| var.clone());
+        let expected_args = match name.name.as_str() {
+            _ if param.is_some() => 0,
+            "List" => 1,
+            "Map" => 2,
+            other => self
+                .records
+                .get(other)
+                .map(|record| record.params.len())
+                .or_else(|| self.enums.get(other).map(|def| def.params.len()))
+                .unwrap_or(0),
+        };
+        if args.len() != expected_args {
+            return Err(Diagnostic::error(
+                codes::ARGUMENT_COUNT,
+                format!(
+                    "`{}` takes {} type argument{}, found {}",
+                    name,
+                    expected_args,
+                    if expected_args == 1 { "" } else { "s" },
+                    args.len()
+                ),
+            )
+            .with_label(ty.span, "wrong number of type arguments"));
+        }
+        if let Some(param) = param {
+            return Ok(param);
+        }
+        let args = args
+            .iter()
+            .map(|arg| self.resolve_type(arg))
+            .collect::<DiagnosticResult<Vec<_>>>()?;
+        let other = name.name.as_str();
+        match (other, &args[..]) {
+            ("List", [item]) => Ok(Type::List(Box::new(item.clone()))),
+            ("Map", [key, value]) => Ok(Type::Map(Box::new(key.clone()), Box::new(value.clone()))),
+            _ => {
+                if let Some(builtin) = resolve_builtin(other) {
+                    Ok(builtin)
+                } else if self.records.contains_key(other) {
+                    Ok(Type::Record(other.to_string(), args))
+                } else if self.enums.contains_key(other) {
+                    Ok(Type::Enum(other.to_string(), args))
+                } else if self.implicit_params && is_type_param(other) {
+                    let var = self.substitution.fresh();
+                    self.type_params.push((other.to_string(), var.clone(), name.span));
+                    Ok(var)
+                } else {
+                    Err(Diagnostic::error(
+                        codes::UNDEFINED_TYPE,
+                        format!("undefined type `{}`", name),
+                    )
+                    .with_label(name.span, "not found in this scope"))
+                }
+            }
+        }
+    }
+
+    /// The type of `field` on a value of type `target_type`: a numbered
+    /// tuple item or a named record field.
+    fn field_type(&mut self, target_type: &Type, field: &Ident) -> DiagnosticResult<Type> {
+        let item = match target_type {
+            Type::Var { .. } => return self.infer_record(target_type, field),
+            Type::Tuple(items) => field
+                .name
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| items.get(n))
+                .cloned(),
+            // The field's type in this instance of the record type.
+            Type::Record(name, args) => self.records.get(name).and_then(|record| {
+                let (_, ty) = record.fields.iter().find(|(f, _)| *f == field.name)?;
+                Some(substitute(ty, &record.params, args))
+            }),
+            _ => None,
+        };
+        item.ok_or_else(|| {
+            let diagnostic = Diagnostic::error(
+                codes::UNKNOWN_FIELD,
+                format!("no field `{}` on type `{}`", field, target_type),
+            )
+            .with_label(field.span, "unknown field");
+            match target_type {
+                Type::Tuple(items) => diagnostic.with_note(format!(
+                    "the tuple has {} items, numbered from 0",
+                    items.len()
+                )),
+                Type::Record(name, _) => diagnostic.with_note(self.describe_fields(name)),
+                _ => diagnostic,
+            }
+        })
+    }
+
+    /// The type of `field` on a value whose type is still being inferred,
+    /// which must be the only record type with a field of that name.
+    fn infer_record(&mut self, target_type: &Type, field: &Ident) -> DiagnosticResult<Type> {
+        let mut records = self
+            .records
+            .iter()
+            .filter(|(_, record)| record.fields.iter().any(|(f, _)| *f == field.name));
+        match (records.next(), records.next()) {
+            (Some((name, record)), None) => {
+                let params = record.params.clone();
+                let record_type = Type::Record(name.clone(), self.fresh_args(&params));
+                self.substitution.unify(target_type, &record_type);
+                self.field_type(&record_type, field)
+            }
+            _ => Err(Diagnostic::error(
+                codes::ANNOTATION_NEEDED,
+                format!("cannot infer the type that has field `{}`", field),
+            )
+            .with_label(field.span, "the type must be known here")
+            .with_help("annotate the variable or parameter with its type")),
+        }
+    }
+
+    /// Reports that the variant `name` was given `found` fields instead of
+    /// `expected`.
+    fn variant_field_count(&self, name: &Ident, expected: usize, found: usize) -> Diagnostic {
+        Diagnostic::error(
+            codes::ARGUMENT_COUNT,
+            format!(
+                "`{}` has {} field{}, found {}",
+                name,
+                expected,
+                if expected == 1 { "" } else { "s" },
+                found
+            ),
+        )
+    }
+
+    /// Lists the fields of the record type `name`, for notes.
+    fn describe_fields(&self, name: &str) -> String {
+        let fields: Vec<String> = self
+            .records
+            .get(name)
+            .map(|record| record.fields.iter().map(|(f, _)| format!("`{}`", f)).collect())
+            .unwrap_or_default();
+        format!("`{}` has fields {}", name, fields.join(", "))
+    }
+
+    /// Checks that `items`, described by `what` such as "list items", have a
+    /// type that all of them coerce to, and returns it: `[1  2.5]` holds
+    /// floats and `[1  none]` holds `int?`s. The type of no items is left to
+    /// be inferred.
+    fn check_items<'a>(
+        &mut self,
+        items: impl Iterator<Item = &'a Expression> + Clone,
+        what: &str,
+    ) -> DiagnosticResult<Type> {
+        let mut first: Option<(Type, Span)> = None;
+        for item in items.clone() {
+            let item_type = self.check_expression(item)?;
+            // The items have the type that all of them coerce to.
+            match &first {
+                None => first = Some((item_type, item.span)),
+                Some((joined, span)) => match self.substitution.join(joined, &item_type) {
+                    Some(joined) => first = Some((joined, *span)),
+                    None => {
+                        let expected = self.substitution.resolve(joined);
+                        return Err(Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!("{} have different types", what),
+                        )
+                        .with_label(
+                            item.span,
+                            format!("expected `{}`, found `{}`", expected, item_type),
+                        )
+                        .with_secondary(*span, format!("this is `{}`", expected)));
+                    }
+                },
+            }
+        }
+        let Some((ty, _)) = first else {
+            return Ok(self.substitution.fresh());
+        };
+        for item in items {
+            if let Some(found) = self.types.get(item.span).cloned() {
+                self.record_conversion(item, &found, &ty);
+            }
+        }
+        Ok(self.substitution.resolve(&ty))
+    }
+
+    fn not_indexable(&self, target: &Expression, target_type: &Type) -> Diagnostic {
+        let diagnostic = Diagnostic::error(
+            codes::NOT_INDEXABLE,
+            format!("cannot index into a value of type `{}`", target_type),
+        )
+        .with_label(target.span, "expected a list, map or string");
+        match target_type {
+            Type::Tuple(_) => diagnostic.with_help("get a tuple item by its number: `pair.0`"),
+            _ => diagnostic,
+        }
+    }
+
+    /// Checks `expr` where its value is used directly, as an operand or
+    /// something to index, iterate over or read a field of, so that it may
+    /// not be `none`.
+    fn check_value(&mut self, expr: &Expression) -> DiagnosticResult<Type> {
+        let ty = self.check_expression(expr)?;
+        match ty {
+            Type::Option(_) => Err(self.possibly_none(expr, &ty)),
+            ty => Ok(ty),
+        }
+    }
+
+    /// Reports `expr`, of type `found`, when it may be `none` but is given
+    /// where a value of type `expected` is required.
+    fn require_some(
+        &self,
+        expr: &Expression,
+        found: &Type,
+        expected: &Type,
+    ) -> DiagnosticResult<()> {
+        match (self.substitution.resolve(found), self.substitution.resolve(expected)) {
+            (found @ Type::Option(_), expected)
+                if !matches!(expected, Type::Option(_) | Type::Var { numeric: false, .. }) =>
+            {
+                Err(self.possibly_none(expr, &found))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Records for the backends that `expr`, of type `found`, was accepted
+    /// where a value of type `expected` is, and so must be converted to it.
+    /// A list or tuple literal is instead built as the type it is given as,
+    /// with its items converted in turn, as `[1  none]` is for `List[int?]`.
+    fn record_conversion(&mut self, expr: &Expression, found: &Type, expected: &Type) {
+        let found = self.substitution.resolve(found);
+        let expected = self.substitution.resolve(expected);
+        // A value given where a `T?` is expected is wrapped in one.
+        let value_type = match &expected {
+            Type::Option(inner) if !matches!(found, Type::Option(_)) => {
+                self.types.conversions.insert(expr.span, expected.clone());
+                (**inner).clone()
+            }
+            _ => expected.clone(),
+        };
+        let items: Vec<(&Expression, Type)> = match (&expr.kind, &value_type) {
+            (ExpressionKind::List(items), Type::List(item)) => {
+                items.iter().map(|expr| (expr, (**item).clone())).collect()
+            }
+            (ExpressionKind::Tuple(items), Type::Tuple(types)) => {
+                items.iter().zip(types.iter().cloned()).collect()
+            }
+            // An `int` needs no conversion to be a `float`.
+            _ if found == value_type || (found == Type::Number && value_type == Type::Float) => {
+                return;
+            }
+            _ => {
+                self.types.conversions.entry(expr.span).or_insert(expected);
+                return;
+            }
+        };
+        self.types.types.insert(expr.span, value_type);
+        for (item, expected) in items {
+            if let Some(found) = self.types.get(item.span).cloned() {
+                self.record_conversion(item, &found, &expected);
+            }
+        }
+    }
+
+    /// The error for comparing values of types that cannot be compared.
+    fn cannot_compare(&self, expr: &Expression, left: &Type, right: &Type) -> Diagnostic {
+        Diagnostic::error(
+            codes::INVALID_OPERAND,
+            format!(
+                "cannot compare `{}` with `{}`",
+                self.substitution.resolve(left),
+                self.substitution.resolve(right)
+            ),
+        )
+        .with_label(expr.span, "both sides must have the same type")
+    }
+
+    /// The error for using `expr`, of the optional type `ty`, as a value.
+    fn possibly_none(&self, expr: &Expression, ty: &Type) -> Diagnostic {
+        let diagnostic = Diagnostic::error(
+            codes::POSSIBLY_NONE,
+            format!("`{}` may be `none`", expr.format()),
+        )
+        .with_label(expr.span, format!("this is `{}`", ty));
+        let ExpressionKind::Variable(name) = &expr.kind else {
+            return diagnostic
+                .with_help("store it with `let`, then check that it is not `none` first");
+        };
+        match self.lookup(name) {
+            Some(variable) if variable.mutable => diagnostic.with_help(format!(
+                "`{}` is mutable, so a check does not rule out `none`; check a `let` copy",
+                name
+            )),
+            _ => diagnostic.with_help(format!("check that `{} != none` first", name)),
+        }
+    }
+
+    /// Checks that the condition of an `if`, `while` or the like, named by
+    /// `keyword`, is a boolean.
+    fn check_condition(&mut self, condition: &Expression, keyword: &str) -> DiagnosticResult<()> {
+        let cond_type = self.check_value(condition)?;
+        if !self.substitution.unify(&cond_type, &Type::Boolean) {
+            return Err(Diagnostic::error(
+                codes::NON_BOOLEAN_CONDITION,
+                format!("`{}` condition must be a boolean", keyword),
+            )
+            .with_label(condition.span, format!("expected `bool`, found `{}`", cond_type)));
+        }
+        // `while true` is how a loop that only a `break` leaves is written.
+        match flow::constant(condition) {
+            Some(true) if keyword == "while" => {}
+            Some(value) => self.warnings.push(
+                Diagnostic::warning(
+                    codes::CONSTANT_CONDITION,
+                    format!("`{}` condition is always `{}`", keyword, value),
+                )
+                .with_label(
+                    condition.span,
+                    if value {
+                        "so its body always runs"
+                    } else {
+                        "so its body never runs"
+                    },
+                ),
+            ),
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Checks that `pattern` can match a value of type `expected`, and binds
+    /// the names it declares.
+    fn check_pattern(&mut self, pattern: &Pattern, expected: &Type) -> DiagnosticResult<()> {
+        // A pattern other than `none`, `_` or a binding matches the value
+        // inside an optional.
+        if let Type::Option(inner) = self.substitution.resolve(expected) {
+            match &pattern.kind {
+                PatternKind::Wildcard | PatternKind::Binding(_) => {}
+                PatternKind::Literal(expr) if matches!(expr.kind, ExpressionKind::None) => {
+                    self.check_expression(expr)?;
+                    return Ok(());
+                }
+                _ => return self.check_pattern(pattern, &inner),
+            }
+        }
+        let found = match &pattern.kind {
+            PatternKind::Wildcard => return Ok(()),
+            PatternKind::Binding(name) => {
+                self.declare(name, expected.clone(), pattern.span, Binding::Pattern);
+                return Ok(());
+            }
+            PatternKind::Literal(expr) => self.check_expression(expr)?,
+            PatternKind::Range { start, end, .. } => {
+                let start_type = self.check_expression(start)?;
+                if !matches!(start_type, Type::Number | Type::Char) {
+                    return Err(Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        "range patterns must be integers or characters",
+                    )
+                    .with_label(start.span, format!("found `{}`", start_type)));
+                }
+                let end_type = self.check_expression(end)?;
+                if end_type != start_type {
+                    return Err(Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        "mismatched types in range pattern",
+                    )
+                    .with_label(
+                        end.span,
+                        format!("expected `{}`, found `{}`", start_type, end_type),
+                    )
+                    .with_secondary(start.span, format!("this is `{}`", start_type)));
+                }
+                start_type
+            }
+            PatternKind::Tuple(items) => {
+                let expected = self.substitution.resolve(expected);
+                match &expected {
+                    Type::Tuple(types) if types.len() == items.len() => {
+                        for (item, item_type) in items.iter().zip(types) {
+                            self.check_pattern(item, item_type)?;
+                        }
+                    }
+                    Type::Var { .. } => {
+                        let types: Vec<Type> =
+                            items.iter().map(|_| self.substitution.fresh()).collect();
+                        self.substitution.unify(&expected, &Type::Tuple(types.clone()));
+                        for (item, item_type) in items.iter().zip(&types) {
+                            self.check_pattern(item, item_type)?;
+                        }
+                    }
+                    _ => {
+                        return Err(Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            "mismatched types in pattern",
+                        )
+                        .with_label(
+                            pattern.span,
+                            format!(
+                                "expected `{}`, found a tuple of {} items",
+                                expected,
+                                items.len()
+                            ),
+                        ));
+                    }
+                }
+                return Ok(());
+            }
+            PatternKind::Variant { name, fields } => {
+                let (found, field_types) = self.variant(name)?;
+                if !self.substitution.unify(&found, expected) {
+                    return Err(Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        "mismatched types in pattern",
+                    )
+                    .with_label(
+                        pattern.span,
+                        format!(
+                            "expected `{}`, found `{}`",
+                            self.substitution.resolve(expected),
+                            found
+                        ),
+                    ));
+                }
+                if fields.len() != field_types.len() {
+                    return Err(self.variant_field_count(name, field_types.len(), fields.len())
+                        .with_label(pattern.span, "wrong number of fields"));
+                }
+                for (field, field_type) in fields.iter().zip(&field_types) {
+                    self.check_pattern(field, field_type)?;
+                }
+                return Ok(());
+            }
+        };
+
+        if !self.substitution.unify(&found, expected) {
+            let expected = self.substitution.resolve(expected);
+            return Err(Diagnostic::error(codes::TYPE_MISMATCH, "mismatched types in pattern")
+                .with_label(pattern.span, format!("expected `{}`, found `{}`", expected, found)));
+        }
+        Ok(())
+    }
+
+    /// The type of `expr`, with the type variables settled so far resolved.
+    fn check_expression(&mut self, expr: &Expression) -> DiagnosticResult<Type> {
+        let ty = self.infer_expression(expr)?;
+        self.types.types.insert(expr.span, ty.clone());
+        Ok(self.substitution.resolve(&ty))
+    }
+
+    fn infer_expression(&mut self, expr: &Expression) -> DiagnosticResult<Type> {
+        match &expr.kind {
+            ExpressionKind::Number { .. } => Ok(Type::Number),
+            ExpressionKind::Float { .. } => Ok(Type::Float),
+            ExpressionKind::Bool(_) => Ok(Type::Boolean),
+            ExpressionKind::None => Ok(Type::None),
+            ExpressionKind::Char { .. } => Ok(Type::Char),
+            ExpressionKind::String { .. } => Ok(Type::String),
+            ExpressionKind::Interpolated { parts, .. } => {
+                for part in parts {
+                    if let InterpolatedPart::Expression(expr) = part {
+                        self.check_expression(expr)?;
+                    }
+                }
+                Ok(Type::String)
+            }
+            ExpressionKind::Tuple(items) => Ok(Type::Tuple(
+                items
+                    .iter()
+                    .map(|item| self.check_expression(item))
+                    .collect::<DiagnosticResult<Vec<_>>>()?,
+            )),
+            ExpressionKind::List(items) => {
+                Ok(Type::List(Box::new(self.check_items(items.iter(), "list items")?)))
+            }
+            ExpressionKind::Map(entries) => {
+                let key = self.check_items(entries.iter().map(|(k, _)| k), "map keys")?;
+                let value = self.check_items(entries.iter().map(|(_, v)| v), "map values")?;
+                Ok(Type::Map(Box::new(key), Box::new(value)))
+            }
+            ExpressionKind::Index { target, index } => {
+                let target_type = self.check_value(target)?;
+                let index_type = self.check_value(index)?;
+                let (expected, item) = match &target_type {
+                    Type::List(item) => (Type::Number, (**item).clone()),
+                    Type::String => (Type::Number, Type::Char),
+                    Type::Map(key, value) => ((**key).clone(), (**value).clone()),
+                    // A list when indexed by an integer, and otherwise a map.
+                    Type::Var { .. } => {
+                        let item = self.substitution.fresh();
+                        let (expected, container) = match index_type {
+                            Type::Number | Type::Var { .. } => {
+                                (Type::Number, Type::List(Box::new(item.clone())))
+                            }
+                            _ => (
+                                index_type.clone(),
+                                Type::Map(Box::new(index_type.clone()), Box::new(item.clone())),
+                            ),
+                        };
+                        self.substitution.unify(&target_type, &container);
+                        (expected, item)
+                    }
+                    other => return Err(self.not_indexable(target, other)),
+                };
+                if !self.substitution.unify(&index_type, &expected) {
+                    return Err(Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        format!("cannot index `{}` with `{}`", target_type, index_type),
+                    )
+                    .with_label(
+                        index.span,
+                        format!("expected `{}`, found `{}`", expected, index_type),
+                    ));
+                }
+                Ok(item)
+            }
+            ExpressionKind::Slice {
+                target, start, end, ..
+            } => {
+                let target_type = self.check_value(target)?;
+                for bound in [start, end].into_iter().flatten() {
+                    let bound_type = self.check_value(bound)?;
+                    if !self.substitution.unify(&bound_type, &Type::Number) {
+                        return Err(Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            "slice bounds must be integers",
+                        )
+                        .with_label(bound.span, format!("expected `int`, found `{}`", bound_type)));
+                    }
+                }
+                match target_type {
+                    Type::List(_) | Type::String => Ok(target_type),
+                    // Taken to be a list when nothing else is known.
+                    Type::Var { .. } => {
+                        let list = Type::List(Box::new(self.substitution.fresh()));
+                        self.substitution.unify(&target_type, &list);
+                        Ok(list)
+                    }
+                    other => Err(self.not_indexable(target, &other)),
+                }
+            }
+            ExpressionKind::Record { name, fields } => {
+                let Some(record) = self.records.get(&name.name) else {
+                    return Err(Diagnostic::error(
+                        codes::UNDEFINED_TYPE,
+                        format!("undefined type `{}`", name),
+                    )
+                    .with_label(name.span, "not found in this scope"));
+                };
+                let (params, declared) = (record.params.clone(), record.fields.clone());
+                let record_type = Type::Record(name.name.clone(), self.fresh_args(&params));
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    let earlier = fields[..i].iter().find(|(f, _)| f.name == field.name);
+                    if let Some((first, _)) = earlier {
+                        return Err(Diagnostic::error(
+                            codes::DUPLICATE_FIELD,
+                            format!("field `{}` is given more than once", field),
+                        )
+                        .with_label(field.span, "given again here")
+                        .with_secondary(first.span, "first given here"));
+                    }
+                    let expected = self.field_type(&record_type, field)?;
+                    let found = self.check_expression(value)?;
+                    self.require_some(value, &found, &expected)?;
+                    if !self.substitution.coerce(&found, &expected) {
+                        let expected = self.substitution.resolve(&expected);
+                        return Err(Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!("mismatched types for field `{}` of `{}`", field, name),
+                        )
+                        .with_label(
+                            value.span,
+                            format!("expected `{}`, found `{}`", expected, found),
+                        ));
+                    }
+                    self.record_conversion(value, &found, &expected);
+                }
+                let missing: Vec<String> = declared
+                    .iter()
+                    .filter(|(f, _)| !fields.iter().any(|(given, _)| given.name == *f))
+                    .map(|(f, _)| format!("`{}`", f))
+                    .collect();
+                if !missing.is_empty() {
+                    return Err(Diagnostic::error(
+                        codes::MISSING_FIELDS,
+                        format!(
+                            "missing field{} {} in `{}`",
+                            if missing.len() == 1 { "" } else { "s" },
+                            missing.join(", "),
+                            name
+                        ),
+                    )
+                    .with_label(name.span, "every field needs a value")
+                    .with_note(self.describe_fields(&name.name)));
+                }
+                Ok(record_type)
+            }
+            ExpressionKind::Variant { name, args } => {
+                let (enum_type, field_types) = self.variant(name)?;
+                if args.len() != field_types.len() {
+                    return Err(self.variant_field_count(name, field_types.len(), args.len())
+                        .with_label(expr.span, "wrong number of fields"));
+                }
+                for (i, (arg, expected)) in args.iter().zip(&field_types).enumerate() {
+                    let found = self.check_expression(arg)?;
+                    self.require_some(arg, &found, expected)?;
+                    if !self.substitution.coerce(&found, expected) {
+                        let expected = self.substitution.resolve(expected);
+                        return Err(Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!("mismatched types for field {} of `{}`", i, name),
+                        )
+                        .with_label(
+                            arg.span,
+                            format!("expected `{}`, found `{}`", expected, found),
+                        ));
+                    }
+                    self.record_conversion(arg, &found, expected);
+                }
+                Ok(enum_type)
+            }
+            ExpressionKind::Field { target, field } => {
+                let target_type = self.check_value(target)?;
+                self.field_type(&target_type, field)
+            }
+            ExpressionKind::Range {
+                start, end, step, ..
+            } => {
+                let bounds = [Some(start), Some(end), step.as_ref()];
+                for bound in bounds.into_iter().flatten() {
+                    let bound_type = self.check_value(bound)?;
+                    if !self.substitution.unify(&bound_type, &Type::Number) {
+                        return Err(Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            "range bounds and step must be integers",
+                        )
+                        .with_label(bound.span, format!("expected `int`, found `{}`", bound_type)));
+                    }
+                }
+                Ok(Type::Range)
+            }
+            ExpressionKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                self.check_condition(condition, "if")?;
+                self.push_narrowed(self.narrowings(condition, true));
+                let then_type = self.check_expression(then_value)?;
+                self.pop_scope();
+                self.push_narrowed(self.narrowings(condition, false));
+                let else_type = self.check_expression(else_value)?;
+                self.pop_scope();
+                // The value has the type that both branches coerce to, as
+                // the items of a list do.
+                if let Some(joined) = self.substitution.join(&then_type, &else_type) {
+                    self.record_conversion(then_value, &then_type, &joined);
+                    self.record_conversion(else_value, &else_type, &joined);
+                    Ok(self.substitution.resolve(&joined))
+                } else {
+                    Err(Diagnostic::error(
+                        codes::TYPE_MISMATCH,
+                        "`if` and `else` have incompatible types",
+                    )
+                    .with_label(
+                        else_value.span,
+                        format!("expected `{}`, found `{}`", then_type, else_type),
+                    )
+                    .with_secondary(then_value.span, format!("this is `{}`", then_type)))
+                }
+            }
+            ExpressionKind::Error => Ok(self.substitution.fresh()),
+            ExpressionKind::Variable(name) => match self.variable(name) {
+                Some(variable) => {
+                    variable.used = true;
+                    let (ty, span) = (variable.ty.clone(), variable.span);
+                    let read = self.narrow(&ty, span);
+                    if read != self.substitution.resolve(&ty) {
+                        self.types.narrowed.insert(expr.span);
+                    }
+                    Ok(read)
+                }
+                None => Err(self.undefined_variable(name, expr.span)),
+            },
+            ExpressionKind::Binary { left, op, right } => {
+                // Only `==` and `!=` accept a value that may be `none`.
+                let equality = matches!(op, BinaryOp::Equal | BinaryOp::NotEqual);
+                let left_type = if equality {
+                    self.check_expression(left)?
+                } else {
+                    self.check_value(left)?
+                };
+                // The right side of `&&` and `||` only runs when the left
+                // side has not decided the result.
+                let right_type = match op {
+                    BinaryOp::Equal | BinaryOp::NotEqual => self.check_expression(right)?,
+                    BinaryOp::And | BinaryOp::Or => {
+                        self.push_narrowed(self.narrowings(left, *op == BinaryOp::And));
+                        let right_type = self.check_value(right);
+                        self.pop_scope();
+                        right_type?
+                    }
+                    _ => self.check_value(right)?,
+                };
                 match op {
                     BinaryOp::Add | BinaryOp::Subtract | BinaryOp::Multiply | BinaryOp::Divide => {
+                        let numeric = |t: &Type| matches!(t, Type::Number | Type::Float);
                         if left_type == Type::Number && right_type == Type::Number {
                             Ok(Type::Number)
-                        } else if left_type == Type::Float || right_type == Type::Float {
+                        } else if numeric(&left_type) && numeric(&right_type) {
                             Ok(Type::Float)
+                        } else if self.substitution.require_number(&left_type)
+                            && self.substitution.require_number(&right_type)
+                            && self.substitution.unify(&left_type, &right_type)
+                        {
+                            // An operand still being inferred has the type
+                            // of the other.
+                            Ok(left_type)
+                        } else {
+                            Err(Diagnostic::error(
+                                codes::INVALID_OPERAND,
+                                format!(
+                                    "cannot apply `{}` to `{}` and `{}`",
+                                    op.format(),
+                                    left_type,
+                                    right_type
+                                ),
+                            )
+                            .with_label(expr.span, "arithmetic requires numeric operands")
+                            .with_secondary(left.span, format!("this is `{}`", left_type))
+                            .with_secondary(right.span, format!("this is `{}`", right_type)))
+                        }
+                    }
+                    BinaryOp::And | BinaryOp::Or => {
+                        if self.substitution.unify(&left_type, &Type::Boolean)
+                            && self.substitution.unify(&right_type, &Type::Boolean)
+                        {
+                            Ok(Type::Boolean)
+                        } else {
+                            Err(Diagnostic::error(
+                                codes::INVALID_OPERAND,
+                                format!(
+                                    "cannot apply `{}` to `{}` and `{}`",
+                                    op.format(),
+                                    left_type,
+                                    right_type
+                                ),
+                            )
+                            .with_label(expr.span, "logical operators require `bool` operands")
+                            .with_secondary(left.span, format!("this is `{}`", left_type))
+                            .with_secondary(right.span, format!("this is `{}`", right_type)))
+                        }
+                    }
+                    // Comparing a value still being inferred with `none` tells
+                    // that it is optional.
+                    _ if equality && (left_type == Type::None || right_type == Type::None) => {
+                        for side in [&left_type, &right_type] {
+                            if let Type::Var { numeric: false, .. } = side {
+                                let inner = self.substitution.fresh();
+                                self.substitution.unify(side, &Type::Option(Box::new(inner)));
+                            }
+                        }
+                        Ok(Type::Boolean)
+                    }
+                    // Comparing a value still being inferred tells that it
+                    // has the type of the other side. An `int` equals a
+                    // `float` of the same value, and a `T` may be compared
+                    // with a `T?`.
+                    _ if equality => {
+                        if self.substitution.coerce(&left_type, &right_type)
+                            || self.substitution.coerce(&right_type, &left_type)
+                        {
+                            Ok(Type::Boolean)
+                        } else {
+                            Err(self.cannot_compare(expr, &left_type, &right_type))
+                        }
+                    }
+                    // Only numbers, strings and characters are ordered, and an
+                    // `int` and a `float` are ordered by their values.
+                    _ => {
+                        let left_type = self.substitution.resolve(&left_type);
+                        let right_type = self.substitution.resolve(&right_type);
+                        let number = |t: &Type| matches!(t, Type::Number | Type::Float);
+                        let numbers = number(&left_type) && number(&right_type);
+                        if !numbers && !self.substitution.unify(&left_type, &right_type) {
+                            return Err(self.cannot_compare(expr, &left_type, &right_type));
+                        }
+                        match self.substitution.resolve(&left_type) {
+                            Type::Number
+                            | Type::Float
+                            | Type::String
+                            | Type::Char
+                            | Type::Var { .. } => Ok(Type::Boolean),
+                            ty => Err(Diagnostic::error(
+                                codes::INVALID_OPERAND,
+                                format!("cannot apply `{}` to `{}`", op.format(), ty),
+                            )
+                            .with_label(
+                                expr.span,
+                                "only numbers, strings and characters are ordered",
+                            )),
+                        }
+                    }
+                }
+            }
+            ExpressionKind::Unary { op, expr } => {
+                let expr_type = self.check_value(expr)?;
+                match op {
+                    UnaryOp::Negate => {
+                        if self.substitution.require_number(&expr_type) {
+                            Ok(expr_type)
+                        } else {
+                            Err(Diagnostic::error(
+                                codes::INVALID_OPERAND,
+                                format!("cannot apply `-` to `{}`", expr_type),
+                            )
+                            .with_label(expr.span, "expected `int` or `float`"))
+                        }
+                    }
+                    UnaryOp::Not => {
+                        if self.substitution.unify(&expr_type, &Type::Boolean) {
+                            Ok(Type::Boolean)
                         } else {
-                            Err(anyhow::anyhow!("Cannot perform arithmetic on non-numeric types"))
+                            Err(Diagnostic::error(
+                                codes::INVALID_OPERAND,
+                                format!("cannot apply `!` to `{}`", expr_type),
+                            )
+                            .with_label(expr.span, "expected `bool`"))
                         }
                     }
-                    BinaryOp::Greater | BinaryOp::Less | BinaryOp::Equal => Ok(Type::Boolean),
                 }
             }
-            Expression::Unary { op: _, expr } => self.check_expression(expr),
-            Expression::Call { name, args } => {
-                let (param_types, return_type) = self
+            ExpressionKind::Call { name, args } => {
+                let (scheme, names) = self
                     .functions
-                    .get(name)
-                    .ok_or_else(|| anyhow::anyhow!("Undefined function: {}", name))?
+                    .get(&name.name)
+                    .ok_or_else(|| {
+                        Diagnostic::error(
+                            codes::UNDEFINED_FUNCTION,
+                            format!("undefined function `{}`", name),
+                        )
+                        .with_label(name.span, "not found in this scope")
+                    })?
                     .clone();
+                let (param_types, return_type) = self.substitution.instantiate(&scheme);
 
                 if args.len() != param_types.len() {
-                    return Err(anyhow::anyhow!(
-                        "Function {} expects {} arguments, got {}",
-                        name,
-                        param_types.len(),
-                        args.len()
-                    ));
+                    return Err(Diagnostic::error(
+                        codes::ARGUMENT_COUNT,
+                        format!(
+                            "function `{}` expects {} arguments, got {}",
+                            name,
+                            param_types.len(),
+                            args.len()
+                        ),
+                    )
+                    .with_label(expr.span, format!("expected {} arguments", param_types.len())));
                 }
 
-                for (arg, param_type) in args.iter().zip(param_types.iter()) {
+                for (i, (arg, param_type)) in args.iter().zip(param_types.iter()).enumerate() {
                     let arg_type = self.check_expression(arg)?;
-                    if arg_type != *param_type && *param_type != Type::Unknown {
-                        return Err(anyhow::anyhow!("Type mismatch in function call"));
+                    self.require_some(arg, &arg_type, param_type)?;
+                    if !self.substitution.coerce(&arg_type, param_type) {
+                        let param_type = self.substitution.resolve(param_type);
+                        let diagnostic = Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!("mismatched argument type in call to `{}`", name),
+                        )
+                        .with_label(
+                            arg.span,
+                            format!("expected `{}`, found `{}`", param_type, arg_type),
+                        );
+                        return Err(match names.get(i) {
+                            Some(param) => diagnostic.with_secondary(
+                                param.span,
+                                format!("parameter `{}` is `{}` here", param, param_type),
+                            ),
+                            None => diagnostic,
+                        });
                     }
+                    self.record_conversion(arg, &arg_type, param_type);
                 }
+                let call_type = Type::Function(param_types, Box::new(return_type.clone()));
+                self.types.calls.insert(name.span, call_type);
 
                 Ok(return_type)
             }
         }
     }
 
-    fn infer_return_type(&self, body: &[Statement]) -> Result<Type> {
-        for stmt in body {
-            if let Statement::Return { value } = stmt {
-                if let Some(v) = value {
-                    return self.check_expression(v);
-                }
+    /// Checks that the type parameters `params` of the function `name` can
+    /// still be any type once its body is checked.
+    fn check_generic(&self, name: &Ident, params: &[(String, Type, Span)]) -> DiagnosticResult<()> {
+        let mut seen: Vec<(usize, &str)> = Vec::new();
+        for (param, var, span) in params {
+            let used_as = match self.substitution.resolve(var) {
+                Type::Var { id, .. } => match seen.iter().find(|(other, _)| *other == id) {
+                    Some((_, other)) => other.to_string(),
+                    None => {
+                        seen.push((id, param));
+                        continue;
+                    }
+                },
+                other => other.to_string(),
+            };
+            return Err(Diagnostic::error(
+                codes::TYPE_MISMATCH,
+                format!("type parameter `{}` of `{}` is not generic", param, name),
+            )
+            .with_label(
+                *span,
+                format!("`{}` stands for any type, but the body uses it as `{}`", param, used_as),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The type variables that the functions `names` must not be generic
+    /// over: those of the variables in scope and of the other functions
+    /// still being checked.
+    fn environment_vars(&self, names: &[&str]) -> Vec<usize> {
+        let mut vars = Vec::new();
+        for scope in &self.scopes {
+            for (_, variable) in &scope.variables {
+                self.substitution.free_vars(&variable.ty, &mut vars);
+            }
+        }
+        for (function, (scheme, _)) in &self.functions {
+            if names.contains(&function.as_str()) {
+                continue;
+            }
+            let mut free = Vec::new();
+            for ty in scheme.params.iter().chain([&scheme.ret]) {
+                self.substitution.free_vars(ty, &mut free);
             }
+            vars.extend(free.into_iter().filter(|var| !scheme.vars.contains(var)));
         }
-        Ok(Type::Unknown)
+        vars
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// The error that checking `source` reports.
+    fn error(source: &str) -> Diagnostic {
+        let tokens = Lexer::new().tokenize(source).expect("source should lex");
+        let program = Parser::new().parse(tokens).expect("source should parse");
+        TypeChecker::new()
+            .check(&program)
+            .expect_err("source should not typecheck")
+    }
+
+    /// The type that checking `source` gives the top-level variable `name`.
+    fn type_of(source: &str, name: &str) -> String {
+        let tokens = Lexer::new().tokenize(source).expect("source should lex");
+        let program = Parser::new().parse(tokens).expect("source should parse");
+        let mut checker = TypeChecker::new();
+        checker.check(&program).expect("source should typecheck");
+        let span = program
+            .statements
+            .iter()
+            .find_map(|stmt| match &stmt.kind {
+                StatementKind::Let { name: let_name, .. } if let_name.name == name => {
+                    Some(let_name.span)
+                }
+                _ => None,
+            })
+            .expect("the variable should be declared");
+        checker.types().get(span).expect("the variable should have a type").to_string()
+    }
+
+    #[test]
+    fn assignments_to_immutable_names_say_what_was_assigned() {
+        let cases = [
+            ("let a  1\na = 2\n", "immutable variable `a`", true),
+            ("for i in 0..3\n\ti = 2\n", "loop variable `i`", false),
+            ("func f x:int\n\tx = 2\n", "parameter `x`", true),
+            (
+                "let v  1  2\nmatch v\n\tn  _\n\t\tn = 2\n",
+                "`n`, which a pattern binds",
+                false,
+            ),
+        ];
+        for (source, message, help) in cases {
+            let error = error(source);
+            assert_eq!(error.code, codes::ASSIGN_TO_IMMUTABLE);
+            assert!(error.message.ends_with(message), "{}", error.message);
+            assert_eq!(error.help.is_some(), help, "{}", source);
+        }
+    }
+
+    #[test]
+    fn comparisons_need_operands_of_one_type_that_is_ordered() {
+        let record = "type P\n\tx:int\nlet a  P x: 1\n";
+        for source in [
+            "print 1 < \"s\"\n".to_string(),
+            "print 1 == \"s\"\n".to_string(),
+            "print [1] <= [2]\n".to_string(),
+            format!("{}print a > a\n", record),
+        ] {
+            assert_eq!(error(&source).code, codes::INVALID_OPERAND, "{}", source);
+        }
+        let source = format!(
+            "{}let b:string?  \"s\"\nlet ok  1 < 2.5 && 'a' < 'b' && a == a && b == \"s\"\n",
+            record
+        );
+        assert_eq!(type_of(&source, "ok"), "bool");
+    }
+
+    #[test]
+    fn ints_widen_to_floats_inside_other_types() {
+        let cases = [
+            ("let xs:List[float]  [1  2]\n", "xs", "List[float]"),
+            ("let xs  [1  2.5]\n", "xs", "List[float]"),
+            ("let xs:List[int?]  [1  none]\n", "xs", "List[int?]"),
+            ("let xs  [none  1]\n", "xs", "List[int?]"),
+            ("let t:(float, int?)  1  2\n", "t", "(float, int?)"),
+            ("func add a  b\n\treturn a + b\nlet n  add 1  2.5\n", "n", "float"),
+            ("func add a  b\n\treturn a + b\nlet n  add 1  2\n", "n", "int"),
+        ];
+        for (source, name, ty) in cases {
+            assert_eq!(type_of(source, name), ty, "{}", source);
+        }
+    }
+
+    #[test]
+    fn unread_variables_and_parameters_are_warned_about() {
+        let source = "func f a  _b\n\tlet x  1\n\tlet _y  2\n\treturn 0\n\nprint f 1  2\n";
+        let tokens = Lexer::new().tokenize(source).expect("source should lex");
+        let program = Parser::new().parse(tokens).expect("source should parse");
+        let mut checker = TypeChecker::new();
+        checker.check(&program).expect("source should typecheck");
+        let mut warnings: Vec<(&str, &str)> = checker
+            .warnings()
+            .iter()
+            .map(|warning| (warning.code, warning.message.as_str()))
+            .collect();
+        warnings.sort_unstable();
+        // `_b` and `_y` are unused on purpose.
+        assert_eq!(
+            warnings,
+            vec![
+                (codes::UNUSED_VARIABLE, "unused parameter `a`"),
+                (codes::UNUSED_VARIABLE, "unused variable `x`"),
+            ]
+        );
+    }
+
+    #[test]
+    fn variables_used_before_their_declaration_point_at_it() {
+        let diagnostic = error("print total\nlet total  1\n");
+        assert_eq!(diagnostic.code, codes::USE_BEFORE_DECLARATION);
+        assert_eq!(diagnostic.labels[1].message, "declared here");
+    }
+
+    #[test]
+    fn variables_of_an_ended_block_say_so() {
+        let diagnostic = error("if true\n\tlet inner  1\n\tprint inner\nprint inner\n");
+        assert_eq!(diagnostic.code, codes::UNDEFINED_VARIABLE);
+        assert_eq!(
+            diagnostic.labels[1].message,
+            "`inner` is declared here, but its block has ended"
+        );
+    }
+
+    #[test]
+    fn conditional_branches_widen_to_one_type() {
+        let cases = [
+            ("let c  true\nlet x  if c then 1.5 else 2\n", "x", "float"),
+            ("let c  true\nlet x  if c then 2 else 1.5\n", "x", "float"),
+            ("let c  true\nlet x  if c then 1 else none\n", "x", "int?"),
+        ];
+        for (source, name, ty) in cases {
+            assert_eq!(type_of(source, name), ty, "{}", source);
+        }
+        let source = "let c  true\nlet x  if c then 1 else \"a\"\n";
+        assert_eq!(error(source).code, codes::TYPE_MISMATCH);
+    }
+
+    #[test]
+    fn suffixed_numbers_are_ints_or_floats() {
+        let cases = [
+            ("let n  10u8 + 1\n", "n", "int"),
+            ("let n  0xffi64\n", "n", "int"),
+            ("let x  2.0f32 * 2\n", "x", "float"),
+            ("let x:float  1f64\n", "x", "float"),
+        ];
+        for (source, name, ty) in cases {
+            assert_eq!(type_of(source, name), ty, "{}", source);
+        }
+    }
+
+    #[test]
+    fn functions_are_generic_wherever_they_are_called() {
+        let called_above = "let a:int  id 1\nlet b:string  id \"s\"\n\nfunc id x\n\treturn x\n";
+        assert_eq!(type_of(called_above, "b"), "string");
+        let mutual = "func even n  x\n\tif n == 0\n\t\treturn x\n\treturn odd n - 1  x\n\n\
+                      func odd n  x\n\tif n == 0\n\t\treturn x\n\treturn even n - 1  x\n\n\
+                      let a  even 4  1\nlet b  odd 3  \"s\"\n";
+        assert_eq!(type_of(mutual, "a"), "int");
+        assert_eq!(type_of(mutual, "b"), "string");
+        // One that reads a variable is checked where it is declared.
+        let reads = "let limit  3\nlet a  over 1\n\nfunc over n\n\treturn n > limit\n";
+        assert_eq!(type_of(reads, "a"), "bool");
+    }
+
+    #[test]
+    fn none_checks_narrow_optional_variables() {
+        let optional = "let a:int?  none\n";
+        let narrowed = [
+            "if a != none\n\tprint a + 1\n",
+            "if a == none\n\tprint 0\nelse\n\tprint a + 1\n",
+            "if !(a == none)\n\tprint a + 1\n",
+            "let b  a != none && a > 2\nprint b\n",
+            "let b  a == none || a > 2\nprint b\n",
+        ];
+        for source in narrowed {
+            let source = format!("{}{}", optional, source);
+            let tokens = Lexer::new().tokenize(&source).expect("source should lex");
+            let program = Parser::new().parse(tokens).expect("source should parse");
+            let checked = TypeChecker::new().check(&program);
+            assert!(checked.is_ok(), "{}: {:?}", source, checked);
+        }
+        let not_narrowed = [
+            "print a + 1\n",
+            "if a == none\n\tprint a + 1\n",
+            "let b  a != none || a > 2\nprint b\n",
+            // The check no longer holds once its block has ended.
+            "if a != none\n\tprint 1\nprint a + 1\n",
+        ];
+        for source in not_narrowed {
+            let source = format!("{}{}", optional, source);
+            assert_eq!(error(&source).code, codes::POSSIBLY_NONE, "{}", source);
+        }
+        // A `let mut` may be set to `none` after the check.
+        let mutable = "let mut a:int?  none\nif a != none\n\tprint a + 1\n";
+        assert_eq!(error(mutable).code, codes::POSSIBLY_NONE);
+        // After an `if` that leaves when `none`, the rest of the block is
+        // narrowed.
+        let early = "func f a:int? -> int\n\tif a == none\n\t\treturn 0\n\treturn a + 1\n\
+                     let b  f 1\n";
+        assert_eq!(type_of(early, "b"), "int");
+    }
+}
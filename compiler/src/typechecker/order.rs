@@ -0,0 +1,244 @@
+//! The order in which the functions of a block are checked.
+//!
+//! A function is generic over the types its body leaves open, which are
+//! only known once the body is checked, so each body is checked before the
+//! calls to it wherever they are: ahead of the other statements of its
+//! block, and after the functions it calls. Functions that call each other,
+//! directly or not, are checked together. One whose body reads a variable
+//! of the block has to wait until the variable is declared, and so does any
+//! function that calls it.
+
+use crate::ast::*;
+use std::collections::{HashMap, HashSet};
+
+/// Functions of a block that call each other.
+pub(super) struct Group {
+    /// The positions in the block of the functions' declarations, in order.
+    pub functions: Vec<usize>,
+    /// The position of the statement after which the group is checked, or
+    /// `None` when it is checked before any statement of the block.
+    pub after: Option<usize>,
+}
+
+/// The groups of the functions declared in `statements`, each after the
+/// groups it calls. `is_variable` tells whether a name is declared by a
+/// `let` of the block.
+pub(super) fn function_groups(
+    statements: &[Statement],
+    is_variable: impl Fn(&str) -> bool,
+) -> Vec<Group> {
+    let functions: Vec<(usize, &Statement)> = statements
+        .iter()
+        .enumerate()
+        .filter(|(_, stmt)| matches!(stmt.kind, StatementKind::Function { .. }))
+        .collect();
+    let by_name: HashMap<&str, usize> = functions
+        .iter()
+        .enumerate()
+        .filter_map(|(node, (_, stmt))| match &stmt.kind {
+            StatementKind::Function { name, .. } => Some((name.name.as_str(), node)),
+            _ => None,
+        })
+        .collect();
+
+    let mut calls = Vec::new();
+    let mut reads = Vec::new();
+    for (_, stmt) in &functions {
+        let body = std::slice::from_ref(*stmt);
+        let local = declared_names(body);
+        let mut callees = Vec::new();
+        let mut read = false;
+        for_each_expression(body, &mut |expr| match &expr.kind {
+            ExpressionKind::Call { name, .. } => {
+                callees.extend(by_name.get(name.name.as_str()).copied());
+            }
+            ExpressionKind::Variable(name) => {
+                read |= !local.contains(name.as_str()) && is_variable(name);
+            }
+            _ => {}
+        });
+        calls.push(callees);
+        reads.push(read);
+    }
+
+    let mut components = Components::new(&calls);
+    for node in 0..functions.len() {
+        components.visit(node);
+    }
+    let mut group_of = vec![0; functions.len()];
+    let mut groups: Vec<Group> = Vec::new();
+    for component in components.found {
+        let mut after = None;
+        for &node in &component {
+            if reads[node] {
+                after = after.max(Some(functions[node].0));
+            }
+            for &callee in &calls[node] {
+                // Callees in the component itself are not grouped yet.
+                if !component.contains(&callee) {
+                    after = after.max(groups[group_of[callee]].after);
+                }
+            }
+        }
+        // Waiting for a variable also waits for the declarations.
+        if after.is_some() {
+            after = after.max(component.iter().map(|&node| functions[node].0).max());
+        }
+        for &node in &component {
+            group_of[node] = groups.len();
+        }
+        let mut members: Vec<usize> = component.iter().map(|&node| functions[node].0).collect();
+        members.sort_unstable();
+        groups.push(Group {
+            functions: members,
+            after,
+        });
+    }
+    groups
+}
+
+/// The names that `statements` declare: the functions' parameters and the
+/// variables of `let`s, loops and patterns.
+fn declared_names(statements: &[Statement]) -> HashSet<&str> {
+    let mut names = HashSet::new();
+    for_each_statement(statements, &mut |stmt| match &stmt.kind {
+        StatementKind::Let { name, .. } | StatementKind::For { var: name, .. } => {
+            names.insert(name.name.as_str());
+        }
+        StatementKind::Function { params, .. } => {
+            names.extend(params.iter().map(|param| param.name.name.as_str()));
+        }
+        StatementKind::Match { arms, .. } => {
+            for arm in arms {
+                pattern_names(&arm.pattern, &mut names);
+            }
+        }
+        _ => {}
+    });
+    names
+}
+
+fn pattern_names<'a>(pattern: &'a Pattern, names: &mut HashSet<&'a str>) {
+    match &pattern.kind {
+        PatternKind::Binding(name) => {
+            names.insert(name);
+        }
+        PatternKind::Tuple(items) | PatternKind::Variant { fields: items, .. } => {
+            for item in items {
+                pattern_names(item, names);
+            }
+        }
+        PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+    }
+}
+
+/// The strongly connected components of a call graph, found with Tarjan's
+/// algorithm. Each is found after the components it calls.
+struct Components<'a> {
+    calls: &'a [Vec<usize>],
+    /// The order in which each node was first visited.
+    index: Vec<Option<usize>>,
+    /// The earliest visited node that each node reaches on the stack.
+    low: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    visited: usize,
+    found: Vec<Vec<usize>>,
+}
+
+impl<'a> Components<'a> {
+    fn new(calls: &'a [Vec<usize>]) -> Self {
+        Self {
+            calls,
+            index: vec![None; calls.len()],
+            low: vec![0; calls.len()],
+            stack: Vec::new(),
+            on_stack: vec![false; calls.len()],
+            visited: 0,
+            found: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, node: usize) {
+        if self.index[node].is_some() {
+            return;
+        }
+        self.index[node] = Some(self.visited);
+        self.low[node] = self.visited;
+        self.visited += 1;
+        self.stack.push(node);
+        self.on_stack[node] = true;
+        let calls = self.calls;
+        for &callee in &calls[node] {
+            match self.index[callee] {
+                None => {
+                    self.visit(callee);
+                    self.low[node] = self.low[node].min(self.low[callee]);
+                }
+                Some(index) if self.on_stack[callee] => {
+                    self.low[node] = self.low[node].min(index);
+                }
+                Some(_) => {}
+            }
+        }
+        if Some(self.low[node]) == self.index[node] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack[member] = false;
+                component.push(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.found.push(component);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// The groups of the top-level functions of `source`, as the positions
+    /// of their functions and of the statement they wait for.
+    fn groups(source: &str) -> Vec<(Vec<usize>, Option<usize>)> {
+        let tokens = Lexer::new().tokenize(source).expect("source should lex");
+        let program = Parser::new().parse(tokens).expect("source should parse");
+        let lets: HashSet<String> = program
+            .statements
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StatementKind::Let { name, .. } => Some(name.name.clone()),
+                _ => None,
+            })
+            .collect();
+        function_groups(&program.statements, |name| lets.contains(name))
+            .into_iter()
+            .map(|group| (group.functions, group.after))
+            .collect()
+    }
+
+    #[test]
+    fn functions_come_after_those_they_call() {
+        let source = "func a\n\treturn b 1\nfunc b x\n\treturn x\n";
+        assert_eq!(groups(source), vec![(vec![1], None), (vec![0], None)]);
+    }
+
+    #[test]
+    fn functions_that_call_each_other_are_one_group() {
+        let source = "func even n\n\treturn odd n\nfunc odd n\n\treturn even n\n";
+        assert_eq!(groups(source), vec![(vec![0, 1], None)]);
+    }
+
+    #[test]
+    fn functions_that_read_variables_wait_for_them() {
+        let source = "let limit  3\nfunc over n\n\treturn n > limit\n\
+                      func check n\n\treturn over n\nfunc own limit\n\treturn limit\n";
+        assert_eq!(
+            groups(source),
+            vec![(vec![1], Some(1)), (vec![2], Some(2)), (vec![3], None)]
+        );
+    }
+}
@@ -1,50 +1,1444 @@
 use crate::ast::*;
+use crate::codegen::{bind_vars, for_each_call, type_expr};
+use crate::diagnostics::{codes, Diagnostic};
+use crate::source::Span;
+use crate::typechecker::{Type, TypeTable};
 use anyhow::Result;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 
+#[derive(Clone)]
 pub struct WasmGenerator {
-    // WASM generation state
+    /// Field names of each record type, in declaration order.
+    records: HashMap<String, Vec<String>>,
+    /// The tag and number of fields of each enum variant.
+    variants: HashMap<String, (usize, usize)>,
+    /// The functions the program declares, by name, with the type that the
+    /// typechecker found for them, generic over the type variables left in
+    /// it.
+    functions: HashMap<String, Option<Type>>,
+    /// The types that each generic function is called with, as the types
+    /// its type variables stand for. Each list becomes an instance of the
+    /// function, `$func.first.0` for the first list found.
+    instances: HashMap<String, Vec<Vec<(usize, Type)>>>,
+    /// The type that the function being compiled returns, or `None` in
+    /// `main`.
+    returns: Option<Type>,
+    /// The types the typechecker found, which tell floats from integers.
+    /// Empty when the program was not checked.
+    types: TypeTable,
+}
+
+impl Default for WasmGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl WasmGenerator {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            records: HashMap::new(),
+            variants: HashMap::new(),
+            functions: HashMap::new(),
+            instances: HashMap::new(),
+            returns: None,
+            types: TypeTable::default(),
+        }
     }
 
-    pub fn generate(&self, program: &Program, output: &Path) -> Result<()> {
-        // Generate WAT (WebAssembly Text) format
+    /// Compiles `program`, as `Compiler::lower` leaves it, to WASM at
+    /// `output`.
+    pub fn generate(&mut self, program: &Program, types: &TypeTable, output: &Path) -> Result<()> {
+        // Generate WAT (WebAssembly Text) format. Every value is an i64:
+        // booleans are 0 or 1, `none` is 0 and characters are code points.
+        // A float is the i64 with the same bits, and an optional is the
+        // address of a flag and a value, as a record is.
+        self.types = types.clone();
+        collect_types(&program.statements, &mut self.records, &mut self.variants);
+        let mut declared: Vec<(&Ident, &[Param], &[Statement])> = Vec::new();
+        for_each_statement(&program.statements, &mut |stmt| {
+            if let StatementKind::Function {
+                name, params, body, ..
+            } = &stmt.kind
+            {
+                declared.push((name, params, body));
+            }
+        });
+        let mut bodies = HashMap::new();
+        for (name, _, body) in &declared {
+            if bodies.insert(name.name.clone(), *body).is_some() {
+                return Err(unsupported(
+                    name.span,
+                    format!("the WASM backend needs `{}` to be the only function so named", name),
+                ));
+            }
+            let ty = self.types.get(name.span).cloned();
+            self.functions.insert(name.name.clone(), ty);
+        }
+        self.collect_function_instances(&program.statements, &bodies);
+
+        let mut functions = String::new();
+        for (name, params, body) in &declared {
+            self.for_each_version(name, &mut |generator, wat_name| {
+                functions.push_str(&generator.function_wat(wat_name, name, params, body)?);
+                Ok(())
+            })?;
+        }
+        let mut main = String::new();
+        for stmt in &program.statements {
+            main.push_str(&self.generate_statement_wat(stmt, 4, &[])?);
+        }
+        // Optionals are printed and compared by helpers, which only
+        // programs that use them get.
+        let code = format!("{}{}", functions, main);
+        let mut helpers = String::new();
+        for (name, helper) in optional_helpers() {
+            if code.contains(&format!("call ${}\n", name)) {
+                helpers.push_str(&helper);
+            }
+        }
+        let code = format!("{}{}", helpers, code);
+        let optionals = code.contains("call $new.optional\n");
+
         let mut wat = String::from("(module\n");
-        
+
+        wat.push_str("  (import \"env\" \"print\" (func $print (param i64)))\n");
+        // The other printers are only imported by programs that use them.
+        for (name, params) in PRINTERS {
+            if code.contains(&format!("call ${}\n", name)) {
+                wat.push_str(&format!(
+                    "  (import \"env\" \"{0}\" (func ${0}{1}))\n",
+                    name, params
+                ));
+            }
+        }
         wat.push_str("  (memory 1)\n");
         wat.push_str("  (export \"memory\" (memory 0))\n");
-        wat.push_str("  (func $print (param i32))\n");
+
+        // Records live in linear memory, one i64 per field in declaration
+        // order, and are never freed. A record value is its address. A
+        // variant is laid out the same way, after an i64 holding its tag.
+        // An optional is laid out like a record of a flag, 1 when it holds
+        // a value, and the value, which is 0 when it holds none.
+        if !self.records.is_empty() || !self.variants.is_empty() || optionals {
+            wat.push_str("  (global $heap (mut i32) (i32.const 0))\n");
+        }
+        if optionals {
+            let fields = ["some".to_string(), "value".to_string()];
+            wat.push_str(&constructor_wat("optional", None, &fields));
+        }
+        let mut names: Vec<&String> = self.records.keys().collect();
+        names.sort();
+        for name in names {
+            wat.push_str(&constructor_wat(name, None, &self.records[name]));
+        }
+        let mut names: Vec<&String> = self.variants.keys().collect();
+        names.sort();
+        for name in names {
+            let (tag, count) = self.variants[name];
+            let fields: Vec<String> = (0..count).map(|i| i.to_string()).collect();
+            wat.push_str(&constructor_wat(name, Some(tag), &fields));
+        }
+        wat.push_str(&helpers);
+        wat.push_str(&functions);
+
         wat.push_str("  (func (export \"main\")\n");
-        
-        for stmt in &program.statements {
-            wat.push_str(&self.generate_statement_wat(stmt, 2)?);
+
+        let mut locals = Vec::new();
+        collect_locals(&program.statements, 0, &mut locals);
+        for local in &locals {
+            wat.push_str(&format!("    (local ${} i64)\n", local));
         }
-        
+
+        wat.push_str(&main);
+
         wat.push_str("  )\n");
         wat.push_str(")\n");
-        
+
         // Convert WAT to WASM binary
         let wasm_bytes = wat::parse_str(&wat)?;
         std::fs::write(output, wasm_bytes)?;
-        
+
+        Ok(())
+    }
+
+    /// Finds the types that each generic function is called with, from the
+    /// calls in `statements` and then from those in each instance found,
+    /// whose types are known once the function's are. `bodies` holds the
+    /// body of each function.
+    fn collect_function_instances(
+        &mut self,
+        statements: &[Statement],
+        bodies: &HashMap<String, &[Statement]>,
+    ) {
+        // The statements to look for calls in, with the types that the
+        // type variables in them stand for.
+        let mut pending = vec![(Vec::new(), statements)];
+        while let Some((context, statements)) = pending.pop() {
+            let generator = self.instance(&context);
+            let mut calls = Vec::new();
+            for_each_call(statements, &mut |name| calls.push(name.clone()));
+            for name in calls {
+                let Some(body) = bodies.get(&name.name) else {
+                    continue;
+                };
+                let Some(bindings) = generator.call_bindings(&name) else {
+                    continue;
+                };
+                let known = self.instances.entry(name.name.clone()).or_default();
+                if bindings.is_empty() || known.contains(&bindings) {
+                    continue;
+                }
+                known.push(bindings.clone());
+                let context: Vec<_> = context.iter().cloned().chain(bindings).collect();
+                pending.push((context, body));
+            }
+        }
+    }
+
+    /// The types that the call by `name` gives the type variables of the
+    /// function it calls, in their order in its type. No types when the
+    /// function is not generic, and `None` when they are not all known.
+    fn call_bindings(&self, name: &Ident) -> Option<Vec<(usize, Type)>> {
+        let Some(Some(declared)) = self.functions.get(&name.name) else {
+            return Some(Vec::new());
+        };
+        let mut vars = Vec::new();
+        bind_vars(declared, declared, &mut vars);
+        if vars.is_empty() {
+            return Some(Vec::new());
+        }
+        let mut bindings = Vec::new();
+        bind_vars(declared, self.types.call(name.span)?, &mut bindings);
+        let known = bindings.len() == vars.len()
+            && bindings.iter().all(|(_, ty)| type_expr(ty, name.span).is_some());
+        known.then_some(bindings)
+    }
+
+    /// This generator with each type variable in `bindings` standing for
+    /// its type, as in one instance of a generic function.
+    fn instance(&self, bindings: &[(usize, Type)]) -> WasmGenerator {
+        let (vars, types): (Vec<usize>, Vec<Type>) = bindings.iter().cloned().unzip();
+        WasmGenerator {
+            types: self.types.substitute(&vars, &types),
+            ..self.clone()
+        }
+    }
+
+    /// Calls `f` with each version of the function `name` to emit, and the
+    /// generator to emit it with: the function itself or, when it is
+    /// generic, an instance for each list of types that it is called with.
+    fn for_each_version(
+        &self,
+        name: &Ident,
+        f: &mut impl FnMut(&WasmGenerator, &str) -> Result<()>,
+    ) -> Result<()> {
+        let mut vars = Vec::new();
+        if let Some(declared) = self.types.get(name.span) {
+            bind_vars(declared, declared, &mut vars);
+        }
+        if vars.is_empty() {
+            return f(self, &format!("func.{}", name));
+        }
+        let instances = self.instances.get(&name.name).into_iter().flatten();
+        for (index, bindings) in instances.enumerate() {
+            f(&self.instance(bindings), &format!("func.{}.{}", name, index))?;
+        }
         Ok(())
     }
 
-    fn generate_statement_wat(&self, stmt: &Statement, indent: usize) -> Result<String> {
+    /// The WASM name of the function or instance that the call by `name`
+    /// calls.
+    fn called_function(&self, name: &Ident) -> Result<String> {
+        if !self.functions.contains_key(&name.name) {
+            return Err(unsupported(
+                name.span,
+                format!("the built-in function `{}` is not supported by the WASM backend", name),
+            ));
+        }
+        let unknown = || {
+            unsupported(
+                name.span,
+                format!("the WASM backend needs the types this call gives `{}`", name),
+            )
+        };
+        let bindings = self.call_bindings(name).ok_or_else(unknown)?;
+        if bindings.is_empty() {
+            return Ok(format!("func.{}", name));
+        }
+        let index = self.instances[&name.name]
+            .iter()
+            .position(|known| *known == bindings)
+            .ok_or_else(unknown)?;
+        Ok(format!("func.{}.{}", name, index))
+    }
+
+    /// The WASM function `$wat_name` for the function `name`. Every
+    /// parameter and result is an `i64`; a function that returns nothing
+    /// returns 0.
+    fn function_wat(
+        &self,
+        wat_name: &str,
+        name: &Ident,
+        params: &[Param],
+        body: &[Statement],
+    ) -> Result<String> {
+        check_captures(params, body)?;
+        let returns = match self.types.get(name.span) {
+            Some(Type::Function(_, returns)) => (**returns).clone(),
+            _ => Type::Unknown,
+        };
+        let generator = WasmGenerator {
+            returns: Some(returns),
+            ..self.clone()
+        };
+        let mut code = format!("  (func ${}", wat_name);
+        for param in params {
+            code.push_str(&format!(" (param ${} i64)", param.name));
+        }
+        code.push_str(" (result i64)\n");
+        let mut locals = Vec::new();
+        collect_locals(body, 0, &mut locals);
+        for local in &locals {
+            if !params.iter().any(|param| param.name.name == *local) {
+                code.push_str(&format!("    (local ${} i64)\n", local));
+            }
+        }
+        for stmt in body {
+            code.push_str(&generator.generate_statement_wat(stmt, 4, &[])?);
+        }
+        code.push_str("    i64.const 0\n  )\n");
+        Ok(code)
+    }
+
+    /// Byte offset of `field` within the record that `target` is, found
+    /// from the record's type.
+    fn field_offset(&self, target: &Expression, field: &Ident) -> Result<usize> {
+        let Some(Type::Record(name, _)) = self.types.get(target.span) else {
+            return Err(unsupported(
+                target.span,
+                "the WASM backend needs the record type of this value, which is not known",
+            ));
+        };
+        self.records
+            .get(name)
+            .and_then(|fields| fields.iter().position(|f| *f == field.name))
+            .map(|index| index * 8)
+            .ok_or_else(|| unsupported(field.span, format!("`{}` has no field `{}`", name, field)))
+    }
+
+    /// `loops` holds the labels of the enclosing loops, outermost first. A
+    /// loop at nesting depth `d` is wrapped in blocks named `$break.d` and
+    /// `$continue.d`; the dots keep them apart from Tabula names.
+    fn generate_statement_wat(
+        &self,
+        stmt: &Statement,
+        indent: usize,
+        loops: &[Option<String>],
+    ) -> Result<String> {
+        let spaces = " ".repeat(indent);
+        match &stmt.kind {
+            StatementKind::Let { name, value, .. } => {
+                let mut code = self.generate_as_wat(value, self.types.get(name.span), indent)?;
+                code.push_str(&format!("{}local.set ${}\n", spaces, name));
+                Ok(code)
+            }
+            StatementKind::Assign {
+                target,
+                fields,
+                op,
+                value,
+            } => {
+                // `x += v` is lowered as `x = x + v`. The places have the
+                // spans that the typechecker gave them.
+                let mut place =
+                    Expression::new(ExpressionKind::Variable(target.name.clone()), target.span);
+                for field in fields {
+                    place = Expression::new(
+                        ExpressionKind::Field {
+                            target: Box::new(place),
+                            field: field.clone(),
+                        },
+                        target.span.to(field.span),
+                    );
+                }
+                let code = match op {
+                    Some(op) => self.generate_expr_wat(
+                        &Expression::new(
+                            ExpressionKind::Binary {
+                                left: Box::new(place.clone()),
+                                op: *op,
+                                right: Box::new(value.clone()),
+                            },
+                            stmt.span,
+                        ),
+                        indent,
+                    )?,
+                    None => self.generate_expr_wat(value, indent)?,
+                };
+                // A field is stored through the address of the record that
+                // holds it.
+                let ExpressionKind::Field { target: record, field } = &place.kind else {
+                    return Ok(format!("{}{}local.set ${}\n", code, spaces, target));
+                };
+                let offset = self.field_offset(record, field)?;
+                Ok(format!(
+                    "{}{}i32.wrap_i64\n{}{}i64.store offset={}\n",
+                    self.generate_expr_wat(record, indent)?,
+                    spaces,
+                    code,
+                    spaces,
+                    offset
+                ))
+            }
+            StatementKind::Print { args } => {
+                let mut code = String::new();
+                for arg in args {
+                    code.push_str(&self.print_wat(arg, indent)?);
+                }
+                Ok(code)
+            }
+            StatementKind::If {
+                condition,
+                then_body,
+                else_ifs,
+                else_body,
+            } => {
+                let mut code = self.generate_expr_wat(condition, indent)?;
+                code.push_str(&format!("{}i32.wrap_i64\n{}if\n", spaces, spaces));
+                for stmt in then_body {
+                    code.push_str(&self.generate_statement_wat(stmt, indent + 2, loops)?);
+                }
+                // Each `else if` is an `if` nested in the previous `else`.
+                let mut depth = 0;
+                for else_if in else_ifs {
+                    let spaces = " ".repeat(indent + depth * 2);
+                    code.push_str(&format!("{}else\n", spaces));
+                    depth += 1;
+                    let nested = indent + depth * 2;
+                    code.push_str(&self.generate_expr_wat(&else_if.condition, nested)?);
+                    let inner = " ".repeat(nested);
+                    code.push_str(&format!("{}i32.wrap_i64\n{}if\n", inner, inner));
+                    for stmt in &else_if.body {
+                        code.push_str(&self.generate_statement_wat(stmt, nested + 2, loops)?);
+                    }
+                }
+                if let Some(else_body) = else_body {
+                    let spaces = " ".repeat(indent + depth * 2);
+                    code.push_str(&format!("{}else\n", spaces));
+                    for stmt in else_body {
+                        let nested = indent + depth * 2 + 2;
+                        code.push_str(&self.generate_statement_wat(stmt, nested, loops)?);
+                    }
+                }
+                for level in (0..=depth).rev() {
+                    code.push_str(&format!("{}end\n", " ".repeat(indent + level * 2)));
+                }
+                Ok(code)
+            }
+            StatementKind::While {
+                label,
+                condition,
+                body,
+            } => {
+                let depth = loops.len();
+                let inner = " ".repeat(indent + 2);
+                let mut code = format!("{}block $break.{}\n", spaces, depth);
+                code.push_str(&format!("{}loop $continue.{}\n", inner, depth));
+                code.push_str(&self.generate_expr_wat(condition, indent + 4)?);
+                code.push_str(&format!("{}  i64.eqz\n", inner));
+                code.push_str(&format!("{}  br_if $break.{}\n", inner, depth));
+                code.push_str(&self.generate_loop_body_wat(label, body, indent + 4, loops)?);
+                code.push_str(&format!("{}  br $continue.{}\n", inner, depth));
+                code.push_str(&format!("{}end\n{}end\n", inner, spaces));
+                Ok(code)
+            }
+            StatementKind::For {
+                label,
+                var,
+                iterable:
+                    Expression {
+                        kind:
+                            ExpressionKind::Range {
+                                start,
+                                end,
+                                inclusive,
+                                step,
+                            },
+                        ..
+                    },
+                body,
+            } => {
+                // The end and step are evaluated once, into hidden locals.
+                // `$continue.d` wraps only the body, so `continue` still
+                // advances the loop variable.
+                let depth = loops.len();
+                let inner = " ".repeat(indent + 2);
+                let deeper = " ".repeat(indent + 4);
+                let mut code = self.generate_expr_wat(start, indent)?;
+                code.push_str(&format!("{}local.set ${}\n", spaces, var));
+                code.push_str(&self.generate_expr_wat(end, indent)?);
+                code.push_str(&format!("{}local.set $range.{}.end\n", spaces, depth));
+                match step {
+                    Some(step) => code.push_str(&self.generate_expr_wat(step, indent)?),
+                    None => code.push_str(&format!("{}i64.const 1\n", spaces)),
+                }
+                code.push_str(&format!("{}local.set $range.{}.step\n", spaces, depth));
+
+                let (up, down) = if *inclusive { ("gt", "lt") } else { ("ge", "le") };
+                code.push_str(&format!("{}block $break.{}\n", spaces, depth));
+                code.push_str(&format!("{}loop $loop.{}\n", inner, depth));
+                code.push_str(&format!("{}local.get $range.{}.step\n", deeper, depth));
+                code.push_str(&format!("{}i64.const 0\n{}i64.gt_s\n", deeper, deeper));
+                // Stop once the variable passes the end, in the direction
+                // of the step.
+                let finished = |comparison: &str| {
+                    format!(
+                        "{}  local.get ${}\n{}  local.get $range.{}.end\n{}  i64.{}_s\n",
+                        deeper, var, deeper, depth, deeper, comparison
+                    )
+                };
+                code.push_str(&format!("{}if (result i32)\n", deeper));
+                code.push_str(&finished(up));
+                code.push_str(&format!("{}else\n", deeper));
+                code.push_str(&finished(down));
+                code.push_str(&format!("{}end\n", deeper));
+                code.push_str(&format!("{}br_if $break.{}\n", deeper, depth));
+                code.push_str(&format!("{}block $continue.{}\n", deeper, depth));
+                code.push_str(&self.generate_loop_body_wat(label, body, indent + 6, loops)?);
+                code.push_str(&format!("{}end\n", deeper));
+                code.push_str(&format!(
+                    "{}local.get ${}\n{}local.get $range.{}.step\n{}i64.add\n{}local.set ${}\n",
+                    deeper, var, deeper, depth, deeper, deeper, var
+                ));
+                code.push_str(&format!("{}br $loop.{}\n", deeper, depth));
+                code.push_str(&format!("{}end\n{}end\n", inner, spaces));
+                Ok(code)
+            }
+            StatementKind::Match { subject, arms } => {
+                // Each arm sits in a block that is left early when its
+                // pattern or guard fails; a matching arm leaves the match.
+                let value = format!("match.{}", stmt.span.start);
+                let inner = " ".repeat(indent + 2);
+                let deeper = " ".repeat(indent + 4);
+                let mut code = self.generate_expr_wat(subject, indent)?;
+                code.push_str(&format!("{}local.set ${}\n", spaces, value));
+                code.push_str(&format!("{}block ${}.end\n", spaces, value));
+                let optional = matches!(self.types.get(subject.span), Some(Type::Option(_)));
+                for arm in arms {
+                    code.push_str(&format!("{}block\n", inner));
+                    let load = vec![format!("local.get ${}", value)];
+                    let (condition, load) = if optional {
+                        self.optional_pattern_wat(&arm.pattern, &load, indent + 4)?
+                    } else {
+                        (self.pattern_condition_wat(&arm.pattern, &load, indent + 4)?, load)
+                    };
+                    code.push_str(&condition);
+                    code.push_str(&format!("{}i32.eqz\n{}br_if 0\n", deeper, deeper));
+                    let mut bindings = Vec::new();
+                    pattern_bindings_wat(&arm.pattern, &load, &mut bindings);
+                    for (name, load) in bindings {
+                        for instruction in load {
+                            code.push_str(&format!("{}{}\n", deeper, instruction));
+                        }
+                        code.push_str(&format!("{}local.set ${}\n", deeper, name));
+                    }
+                    if let Some(guard) = &arm.guard {
+                        code.push_str(&self.generate_expr_wat(guard, indent + 4)?);
+                        code.push_str(&format!("{}i64.eqz\n{}br_if 0\n", deeper, deeper));
+                    }
+                    for stmt in &arm.body {
+                        code.push_str(&self.generate_statement_wat(stmt, indent + 4, loops)?);
+                    }
+                    code.push_str(&format!("{}br ${}.end\n", deeper, value));
+                    code.push_str(&format!("{}end\n", inner));
+                }
+                code.push_str(&format!("{}end\n", spaces));
+                Ok(code)
+            }
+            StatementKind::Break { label } | StatementKind::Continue { label } => {
+                let kind = if matches!(stmt.kind, StatementKind::Break { .. }) {
+                    "break"
+                } else {
+                    "continue"
+                };
+                // The parser guarantees a matching loop encloses the jump.
+                let depth = match label {
+                    Some(label) => loops
+                        .iter()
+                        .rposition(|l| l.as_deref() == Some(label.name.as_str()))
+                        .unwrap_or_default(),
+                    None => loops.len().saturating_sub(1),
+                };
+                Ok(format!("{}br ${}.{}\n", spaces, kind, depth))
+            }
+            StatementKind::Return { value } => {
+                let Some(returns) = &self.returns else {
+                    // `main` returns nothing.
+                    let mut code = String::new();
+                    if let Some(value) = value {
+                        code.push_str(&self.generate_expr_wat(value, indent)?);
+                        code.push_str(&format!("{}drop\n", spaces));
+                    }
+                    return Ok(format!("{}{}return\n", code, spaces));
+                };
+                let mut code = match value {
+                    Some(value) => self.generate_as_wat(value, Some(returns), indent)?,
+                    None => format!("{}i64.const 0\n", spaces),
+                };
+                code.push_str(&format!("{}return\n", spaces));
+                Ok(code)
+            }
+            StatementKind::Expression(expr) => Ok(format!(
+                "{}{}drop\n",
+                self.generate_expr_wat(expr, indent)?,
+                spaces
+            )),
+            StatementKind::Comment(comment) => Ok(format!("{};;{}\n", spaces, comment.text)),
+            // Types and functions are declared ahead of `main`.
+            StatementKind::Type { .. }
+            | StatementKind::Enum { .. }
+            | StatementKind::Function { .. } => Ok(String::new()),
+            StatementKind::For { iterable, .. } => Err(unsupported(
+                iterable.span,
+                "the WASM backend only supports a range as what a `for` loop iterates over",
+            )),
+            StatementKind::Error => Err(unsupported(stmt.span, "the statement has syntax errors")),
+        }
+    }
+
+    fn generate_loop_body_wat(
+        &self,
+        label: &Option<Ident>,
+        body: &[Statement],
+        indent: usize,
+        loops: &[Option<String>],
+    ) -> Result<String> {
+        let mut loops = loops.to_vec();
+        loops.push(label.as_ref().map(|label| label.name.clone()));
+        let mut code = String::new();
+        for stmt in body {
+            code.push_str(&self.generate_statement_wat(stmt, indent, &loops)?);
+        }
+        Ok(code)
+    }
+
+    /// Emits instructions that leave the value of `expr` on the stack.
+    fn generate_expr_wat(&self, expr: &Expression, indent: usize) -> Result<String> {
+        // A value given where an optional type is expected is wrapped in it.
+        match self.types.conversion(expr.span) {
+            Some(Type::Option(inner))
+                if !matches!(self.types.get(expr.span), Some(Type::Option(_))) =>
+            {
+                self.wrap_optional_wat(expr, inner, indent)
+            }
+            _ => self.generate_value_wat(expr, indent),
+        }
+    }
+
+    /// Pushes `expr`, which is not optional, as an optional holding a value
+    /// of type `inner`, or holding nothing when `expr` is `none`.
+    fn wrap_optional_wat(&self, expr: &Expression, inner: &Type, indent: usize) -> Result<String> {
+        let spaces = " ".repeat(indent);
+        let mut code = match self.types.get(expr.span) {
+            Some(Type::None) => format!("{}i64.const 0\n{}i64.const 0\n", spaces, spaces),
+            _ => {
+                let mut code = format!("{}i64.const 1\n", spaces);
+                code.push_str(&self.generate_value_wat(expr, indent)?);
+                if *inner == Type::Float && !self.is_float(expr) {
+                    code.push_str(&format!(
+                        "{}f64.convert_i64_s\n{}i64.reinterpret_f64\n",
+                        spaces, spaces
+                    ));
+                }
+                code
+            }
+        };
+        code.push_str(&format!("{}call $new.optional\n", spaces));
+        Ok(code)
+    }
+
+    /// Pushes `left == right` or `left != right` when either side is
+    /// optional or `none`, with both sides as optionals: they are equal when
+    /// both are `none` or both hold equal values.
+    fn optional_equality_wat(
+        &self,
+        left: &Expression,
+        op: &BinaryOp,
+        right: &Expression,
+        indent: usize,
+    ) -> Result<Option<String>> {
+        let types = (self.types.get(left.span), self.types.get(right.span));
+        let optional = |ty: Option<&Type>| matches!(ty, Some(Type::Option(_) | Type::None));
+        if !optional(types.0) && !optional(types.1) {
+            return Ok(None);
+        }
+        let inner = match types {
+            (Some(Type::Option(inner)), _) | (_, Some(Type::Option(inner))) => &**inner,
+            _ => &Type::None,
+        };
+        let spaces = " ".repeat(indent);
+        let mut code = String::new();
+        for side in [left, right] {
+            code.push_str(&match self.types.get(side.span) {
+                Some(Type::Option(_)) => self.generate_expr_wat(side, indent)?,
+                _ => self.wrap_optional_wat(side, inner, indent)?,
+            });
+        }
+        let helper = match inner {
+            Type::Float => "optional.equal_float",
+            _ => "optional.equal",
+        };
+        code.push_str(&format!("{}call ${}\n", spaces, helper));
+        if matches!(op, BinaryOp::NotEqual) {
+            code.push_str(&format!("{}i64.eqz\n{}i64.extend_i32_u\n", spaces, spaces));
+        }
+        Ok(Some(code))
+    }
+
+    /// Emits instructions that leave the value of `expr` on the stack, as
+    /// the type the typechecker found for it.
+    fn generate_value_wat(&self, expr: &Expression, indent: usize) -> Result<String> {
         let spaces = " ".repeat(indent);
-        match stmt {
-            Statement::Let { name, value } => {
-                Ok(format!("{};; let {}\n", spaces, name))
+        match &expr.kind {
+            ExpressionKind::Number { value, .. } => Ok(format!("{}i64.const {}\n", spaces, value)),
+            ExpressionKind::Float { value, .. } => Ok(format!(
+                "{}f64.const {:?}\n{}i64.reinterpret_f64\n",
+                spaces, value, spaces
+            )),
+            ExpressionKind::Bool(value) => {
+                Ok(format!("{}i64.const {}\n", spaces, *value as i64))
+            }
+            ExpressionKind::None => Ok(format!("{}i64.const 0\n", spaces)),
+            ExpressionKind::Char { value, raw } => Ok(format!(
+                "{}i64.const {} ;; {}\n",
+                spaces, *value as u32, raw
+            )),
+            // An optional that a condition found not to be `none` is read
+            // for the value inside.
+            ExpressionKind::Variable(v) if self.types.is_narrowed(expr.span) => Ok(format!(
+                "{0}local.get ${1}\n{0}i32.wrap_i64\n{0}i64.load offset=8\n",
+                spaces, v
+            )),
+            ExpressionKind::Variable(v) => Ok(format!("{}local.get ${}\n", spaces, v)),
+            // An integer given for a `float` parameter becomes a float.
+            ExpressionKind::Call { name, args } => {
+                let function = self.called_function(name)?;
+                let params = match self.types.call(name.span) {
+                    Some(Type::Function(params, _)) => params.clone(),
+                    _ => Vec::new(),
+                };
+                let mut code = String::new();
+                for (i, arg) in args.iter().enumerate() {
+                    code.push_str(&self.generate_as_wat(arg, params.get(i), indent)?);
+                }
+                code.push_str(&format!("{}call ${}\n", spaces, function));
+                Ok(code)
+            }
+            ExpressionKind::Binary { left, op, right } => {
+                if matches!(op, BinaryOp::Equal | BinaryOp::NotEqual) {
+                    if let Some(code) = self.optional_equality_wat(left, op, right, indent)? {
+                        return Ok(code);
+                    }
+                }
+                if self.is_float(left) || self.is_float(right) {
+                    if let Some((instruction, compares)) = float_instruction(op) {
+                        // Mixed operands are both taken as floats.
+                        let mut code = self.generate_f64_wat(left, indent)?;
+                        code.push_str(&self.generate_f64_wat(right, indent)?);
+                        code.push_str(&format!("{}{}\n", spaces, instruction));
+                        let back = if compares {
+                            "i64.extend_i32_u"
+                        } else {
+                            "i64.reinterpret_f64"
+                        };
+                        code.push_str(&format!("{}{}\n", spaces, back));
+                        return Ok(code);
+                    }
+                }
+                // Comparisons produce an i32, which is widened back to i64.
+                let (instruction, widen) = match op {
+                    BinaryOp::Add => ("i64.add", false),
+                    BinaryOp::Subtract => ("i64.sub", false),
+                    BinaryOp::Multiply => ("i64.mul", false),
+                    BinaryOp::Divide => ("i64.div_s", false),
+                    BinaryOp::Greater => ("i64.gt_s", true),
+                    BinaryOp::Less => ("i64.lt_s", true),
+                    BinaryOp::GreaterEqual => ("i64.ge_s", true),
+                    BinaryOp::LessEqual => ("i64.le_s", true),
+                    BinaryOp::Equal => ("i64.eq", true),
+                    BinaryOp::NotEqual => ("i64.ne", true),
+                    BinaryOp::And | BinaryOp::Or => {
+                        return self.generate_logical_wat(left, op, right, indent)
+                    }
+                };
+                let mut code = self.generate_expr_wat(left, indent)?;
+                code.push_str(&self.generate_expr_wat(right, indent)?);
+                code.push_str(&format!("{}{}\n", spaces, instruction));
+                if widen {
+                    code.push_str(&format!("{}i64.extend_i32_u\n", spaces));
+                }
+                Ok(code)
             }
-            Statement::Print { args } => {
-                Ok(format!("{};; print\n", spaces))
+            ExpressionKind::Conditional {
+                condition,
+                then_value,
+                else_value,
+            } => {
+                // An integer branch of a `float` conditional becomes a float.
+                let ty = self.types.get(expr.span);
+                let branch = |value| self.generate_as_wat(value, ty, indent + 2);
+                let mut code = self.generate_expr_wat(condition, indent)?;
+                code.push_str(&format!("{}i32.wrap_i64\n{}if (result i64)\n", spaces, spaces));
+                code.push_str(&branch(then_value)?);
+                code.push_str(&format!("{}else\n", spaces));
+                code.push_str(&branch(else_value)?);
+                code.push_str(&format!("{}end\n", spaces));
+                Ok(code)
             }
-            _ => Ok(format!("{};; TODO: statement\n", spaces)),
+            ExpressionKind::Unary { op, expr } => {
+                let operand = self.generate_expr_wat(expr, indent)?;
+                Ok(match op {
+                    UnaryOp::Negate if self.is_float(expr) => format!(
+                        "{}{}f64.reinterpret_i64\n{}f64.neg\n{}i64.reinterpret_f64\n",
+                        operand, spaces, spaces, spaces
+                    ),
+                    UnaryOp::Negate => {
+                        format!("{}i64.const 0\n{}{}i64.sub\n", spaces, operand, spaces)
+                    }
+                    UnaryOp::Not => {
+                        format!("{}{}i64.eqz\n{}i64.extend_i32_u\n", operand, spaces, spaces)
+                    }
+                })
+            }
+            // Fields are evaluated in declaration order.
+            ExpressionKind::Record { name, fields } if self.records.contains_key(&name.name) => {
+                let mut code = String::new();
+                for field in &self.records[&name.name] {
+                    if let Some((_, value)) = fields.iter().find(|(f, _)| f.name == *field) {
+                        code.push_str(&self.generate_expr_wat(value, indent)?);
+                    }
+                }
+                code.push_str(&format!("{}call $new.{}\n", spaces, name));
+                Ok(code)
+            }
+            ExpressionKind::Variant { name, args } if self.variants.contains_key(&name.name) => {
+                let mut code = String::new();
+                for arg in args {
+                    code.push_str(&self.generate_expr_wat(arg, indent)?);
+                }
+                code.push_str(&format!("{}call $new.{}\n", spaces, name));
+                Ok(code)
+            }
+            ExpressionKind::Field { target, field } => Ok(format!(
+                "{}{}i32.wrap_i64\n{}i64.load offset={}\n",
+                self.generate_expr_wat(target, indent)?,
+                spaces,
+                spaces,
+                self.field_offset(target, field)?
+            )),
+            _ => Err(unsupported(
+                expr.span,
+                "this expression is not supported by the WASM backend",
+            )),
         }
     }
+
+    /// Prints `arg` with the host function for its type.
+    fn print_wat(&self, arg: &Expression, indent: usize) -> Result<String> {
+        let spaces = " ".repeat(indent);
+        let mut code = self.generate_expr_wat(arg, indent)?;
+        let printer = match self.types.get(arg.span) {
+            None => "call $print".to_string(),
+            Some(Type::None) => "drop\ncall $print_none".to_string(),
+            Some(Type::Float) => "f64.reinterpret_i64\ncall $print_float".to_string(),
+            Some(ty) => {
+                // An optional is printed by a helper that looks at its flag.
+                let (helper, inner) = match ty {
+                    Type::Option(inner) => ("optional.", &**inner),
+                    ty => ("", ty),
+                };
+                match printer(inner) {
+                    Some(printer) => format!("call ${}{}", helper, printer),
+                    None => {
+                        return Err(unsupported(
+                            arg.span,
+                            format!("printing a `{}` is not supported by the WASM backend", ty),
+                        ))
+                    }
+                }
+            }
+        };
+        for instruction in printer.lines() {
+            code.push_str(&format!("{}{}\n", spaces, instruction));
+        }
+        Ok(code)
+    }
+
+    /// Pushes `left && right` or `left || right`. The right side only runs
+    /// when the left does not decide the result, so it may rely on the left.
+    fn generate_logical_wat(
+        &self,
+        left: &Expression,
+        op: &BinaryOp,
+        right: &Expression,
+        indent: usize,
+    ) -> Result<String> {
+        let spaces = " ".repeat(indent);
+        let decided = matches!(op, BinaryOp::Or) as i64;
+        let decided = format!("{}  i64.const {}\n", spaces, decided);
+        let right = self.generate_expr_wat(right, indent + 2)?;
+        let (then_code, else_code) = match op {
+            BinaryOp::Or => (decided, right),
+            _ => (right, decided),
+        };
+        let mut code = self.generate_expr_wat(left, indent)?;
+        code.push_str(&format!("{}i32.wrap_i64\n{}if (result i64)\n", spaces, spaces));
+        code.push_str(&then_code);
+        code.push_str(&format!("{}else\n", spaces));
+        code.push_str(&else_code);
+        code.push_str(&format!("{}end\n", spaces));
+        Ok(code)
+    }
+
+    /// Whether the typechecker found `expr` to be a float.
+    fn is_float(&self, expr: &Expression) -> bool {
+        matches!(self.types.get(expr.span), Some(Type::Float))
+    }
+
+    /// Pushes `expr` as a value of type `expected`: an integer where a
+    /// float is expected is converted to one.
+    fn generate_as_wat(
+        &self,
+        expr: &Expression,
+        expected: Option<&Type>,
+        indent: usize,
+    ) -> Result<String> {
+        if !matches!(expected, Some(Type::Float)) || self.is_float(expr) {
+            return self.generate_expr_wat(expr, indent);
+        }
+        let mut code = self.generate_f64_wat(expr, indent)?;
+        code.push_str(&format!("{}i64.reinterpret_f64\n", " ".repeat(indent)));
+        Ok(code)
+    }
+
+    /// Pushes `expr` as an f64, converting an integer.
+    fn generate_f64_wat(&self, expr: &Expression, indent: usize) -> Result<String> {
+        let mut code = self.generate_expr_wat(expr, indent)?;
+        let instruction = if self.is_float(expr) {
+            "f64.reinterpret_i64"
+        } else {
+            "f64.convert_i64_s"
+        };
+        code.push_str(&format!("{}{}\n", " ".repeat(indent), instruction));
+        Ok(code)
+    }
+
+    /// Emits the condition under which the optional that `load` pushes
+    /// matches `pattern`, and the instructions that push what the pattern's
+    /// bindings take apart: `none` only matches an optional holding nothing,
+    /// and other patterns match the value inside.
+    fn optional_pattern_wat(
+        &self,
+        pattern: &Pattern,
+        load: &[String],
+        indent: usize,
+    ) -> Result<(String, Vec<String>)> {
+        let spaces = " ".repeat(indent);
+        let mut inner = load.to_vec();
+        inner.push("i32.wrap_i64".to_string());
+        let mut flag: String = inner
+            .iter()
+            .map(|instruction| format!("{}{}\n", spaces, instruction))
+            .collect();
+        flag.push_str(&format!("{}i64.load\n", spaces));
+        inner.push("i64.load offset=8".to_string());
+        let always = format!("{}i32.const 1\n", spaces);
+        Ok(match &pattern.kind {
+            PatternKind::Wildcard => (always, load.to_vec()),
+            // A binding after a `none` arm holds the value inside.
+            PatternKind::Binding(_) => match self.types.get(pattern.span) {
+                Some(Type::Option(_)) => (always, load.to_vec()),
+                _ => (always, inner),
+            },
+            PatternKind::Literal(expr) if matches!(expr.kind, ExpressionKind::None) => {
+                (format!("{}{}i64.eqz\n", flag, spaces), load.to_vec())
+            }
+            // An optional holding nothing has a 0 where the value would be.
+            _ => {
+                let mut condition = format!("{}{}i32.wrap_i64\n", flag, spaces);
+                condition.push_str(&self.pattern_condition_wat(pattern, &inner, indent)?);
+                condition.push_str(&format!("{}i32.and\n", spaces));
+                (condition, inner)
+            }
+        })
+    }
+
+    /// Emits instructions that leave 1 on the stack when the value that
+    /// `load` pushes matches `pattern`, and 0 otherwise.
+    fn pattern_condition_wat(
+        &self,
+        pattern: &Pattern,
+        load: &[String],
+        indent: usize,
+    ) -> Result<String> {
+        let spaces = " ".repeat(indent);
+        let push: String = load
+            .iter()
+            .map(|instruction| format!("{}{}\n", spaces, instruction))
+            .collect();
+        let compare = |push: &str, literal: i64, instruction: &str| {
+            format!(
+                "{}{}i64.const {}\n{}{}\n",
+                push, spaces, literal, spaces, instruction
+            )
+        };
+        let unsupported_pattern =
+            || unsupported(pattern.span, "this pattern is not supported by the WASM backend");
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding(_) => {
+                Ok(format!("{}i32.const 1\n", spaces))
+            }
+            PatternKind::Literal(expr) => match literal_wat(expr) {
+                Some(literal) => Ok(compare(&push, literal, "i64.eq")),
+                None => Err(unsupported_pattern()),
+            },
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => match (literal_wat(start), literal_wat(end)) {
+                (Some(start), Some(end)) => {
+                    let below = if *inclusive { "i64.le_s" } else { "i64.lt_s" };
+                    let mut code = compare(&push, start, "i64.ge_s");
+                    code.push_str(&compare(&push, end, below));
+                    code.push_str(&format!("{}i32.and\n", spaces));
+                    Ok(code)
+                }
+                _ => Err(unsupported_pattern()),
+            },
+            // The tag is the first i64 of the variant.
+            PatternKind::Variant { name, fields } => {
+                let Some((tag, _)) = self.variants.get(&name.name) else {
+                    return Err(unsupported(name.span, format!("undefined variant `{}`", name)));
+                };
+                let tag_push = format!("{}{}i32.wrap_i64\n{}i64.load\n", push, spaces, spaces);
+                let mut code = compare(&tag_push, *tag as i64, "i64.eq");
+                for (i, field) in fields.iter().enumerate() {
+                    if matches!(field.kind, PatternKind::Wildcard | PatternKind::Binding(_)) {
+                        continue;
+                    }
+                    let load = variant_field_wat(load, i);
+                    code.push_str(&self.pattern_condition_wat(field, &load, indent)?);
+                    code.push_str(&format!("{}i32.and\n", spaces));
+                }
+                Ok(code)
+            }
+            PatternKind::Tuple(_) => Err(unsupported_pattern()),
+        }
+    }
+}
+
+/// The host functions besides `env.print` that print a value, with their
+/// parameters.
+const PRINTERS: [(&str, &str); 4] = [
+    ("print_float", " (param f64)"),
+    ("print_char", " (param i64)"),
+    ("print_bool", " (param i64)"),
+    ("print_none", ""),
+];
+
+/// The host function that prints a value of type `ty`, for the types that
+/// have one.
+fn printer(ty: &Type) -> Option<&'static str> {
+    match ty {
+        Type::Float => Some("print_float"),
+        Type::Char => Some("print_char"),
+        Type::Boolean => Some("print_bool"),
+        Type::Record(..) | Type::Enum(..) | Type::Option(_) | Type::None => None,
+        _ => Some("print"),
+    }
+}
+
+/// The error for a construct at `span` that the WASM backend cannot lower.
+fn unsupported(span: Span, message: impl Into<String>) -> anyhow::Error {
+    Diagnostic::error(codes::UNSUPPORTED_BY_BACKEND, message)
+        .with_label(span, "cannot be compiled to WASM")
+        .into()
+}
+
+/// The f64 instruction for `op`, and whether it compares rather than
+/// computes a float. `and` and `or` have none.
+fn float_instruction(op: &BinaryOp) -> Option<(&'static str, bool)> {
+    Some(match op {
+        BinaryOp::Add => ("f64.add", false),
+        BinaryOp::Subtract => ("f64.sub", false),
+        BinaryOp::Multiply => ("f64.mul", false),
+        BinaryOp::Divide => ("f64.div", false),
+        BinaryOp::Greater => ("f64.gt", true),
+        BinaryOp::Less => ("f64.lt", true),
+        BinaryOp::GreaterEqual => ("f64.ge", true),
+        BinaryOp::LessEqual => ("f64.le", true),
+        BinaryOp::Equal => ("f64.eq", true),
+        BinaryOp::NotEqual => ("f64.ne", true),
+        BinaryOp::And | BinaryOp::Or => return None,
+    })
+}
+
+/// Collects the names that `pattern` binds, each with the instructions
+/// that push the part of the value it binds to. `load` pushes the whole
+/// value.
+fn pattern_bindings_wat(
+    pattern: &Pattern,
+    load: &[String],
+    bindings: &mut Vec<(String, Vec<String>)>,
+) {
+    match &pattern.kind {
+        PatternKind::Binding(name) => bindings.push((name.clone(), load.to_vec())),
+        PatternKind::Variant { fields, .. } => {
+            for (i, field) in fields.iter().enumerate() {
+                pattern_bindings_wat(field, &variant_field_wat(load, i), bindings);
+            }
+        }
+        _ => {}
+    }
 }
 
+/// Instructions that push field `i` of the variant that `load` pushes.
+fn variant_field_wat(load: &[String], i: usize) -> Vec<String> {
+    let mut field = load.to_vec();
+    field.push("i32.wrap_i64".to_string());
+    field.push(format!("i64.load offset={}", (i + 1) * 8));
+    field
+}
+
+/// The `i64` a literal pattern compares against, for the literals that have
+/// one.
+fn literal_wat(expr: &Expression) -> Option<i64> {
+    match &expr.kind {
+        ExpressionKind::Number { value, .. } => Some(*value),
+        ExpressionKind::Bool(value) => Some(*value as i64),
+        ExpressionKind::None => Some(0),
+        ExpressionKind::Char { value, .. } => Some(*value as i64),
+        ExpressionKind::Unary {
+            op: UnaryOp::Negate,
+            expr,
+        } => literal_wat(expr).map(|value| -value),
+        _ => None,
+    }
+}
+
+/// Reports a variable that the function with `params` and `body` reads or
+/// assigns but does not declare. A WASM function cannot reach the locals
+/// of the function around it, nor those of `main`.
+fn check_captures(params: &[Param], body: &[Statement]) -> Result<()> {
+    let mut declared: HashSet<String> = params.iter().map(|p| p.name.name.clone()).collect();
+    let mut assigned = Vec::new();
+    for_each_statement(body, &mut |stmt| match &stmt.kind {
+        StatementKind::Let { name, .. } | StatementKind::For { var: name, .. } => {
+            declared.insert(name.name.clone());
+        }
+        StatementKind::Function { params, .. } => {
+            declared.extend(params.iter().map(|p| p.name.name.clone()));
+        }
+        StatementKind::Match { arms, .. } => {
+            for arm in arms {
+                let mut bindings = Vec::new();
+                pattern_bindings_wat(&arm.pattern, &[], &mut bindings);
+                declared.extend(bindings.into_iter().map(|(name, _)| name));
+            }
+        }
+        StatementKind::Assign { target, .. } => assigned.push((target.name.clone(), target.span)),
+        _ => {}
+    });
+    let mut used = assigned;
+    for_each_expression(body, &mut |expr| {
+        if let ExpressionKind::Variable(name) = &expr.kind {
+            used.push((name.clone(), expr.span));
+        }
+    });
+    match used.into_iter().find(|(name, _)| !declared.contains(name)) {
+        Some((name, span)) => Err(unsupported(
+            span,
+            format!(
+                "`{}` is declared outside the function, which the WASM backend does not support",
+                name
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Gathers the field names of every record type, and the tag and number of
+/// fields of every enum variant, declared in `statements`.
+fn collect_types(
+    statements: &[Statement],
+    records: &mut HashMap<String, Vec<String>>,
+    variants: &mut HashMap<String, (usize, usize)>,
+) {
+    for stmt in statements {
+        match &stmt.kind {
+            StatementKind::Type { name, fields, .. } => {
+                let fields = fields.iter().map(|f| f.name.name.clone()).collect();
+                records.insert(name.name.clone(), fields);
+            }
+            StatementKind::Enum { variants: declared, .. } => {
+                for (tag, variant) in declared.iter().enumerate() {
+                    variants.insert(variant.name.name.clone(), (tag, variant.fields.len()));
+                }
+            }
+            StatementKind::Function { body, .. }
+            | StatementKind::For { body, .. }
+            | StatementKind::While { body, .. } => collect_types(body, records, variants),
+            StatementKind::If {
+                then_body,
+                else_ifs,
+                else_body,
+                ..
+            } => {
+                collect_types(then_body, records, variants);
+                for else_if in else_ifs {
+                    collect_types(&else_if.body, records, variants);
+                }
+                if let Some(else_body) = else_body {
+                    collect_types(else_body, records, variants);
+                }
+            }
+            StatementKind::Match { arms, .. } => {
+                for arm in arms {
+                    collect_types(&arm.body, records, variants);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Builds `$new.Name`, which takes the fields of a record or variant in
+/// declaration order, stores them at the top of the heap after the `tag` of
+/// a variant and returns their address.
+fn constructor_wat(name: &str, tag: Option<usize>, fields: &[String]) -> String {
+    let params: Vec<String> = fields.iter().map(|f| format!("(param ${} i64)", f)).collect();
+    let first = if tag.is_some() { 8 } else { 0 };
+    let mut code = format!(
+        "  (func $new.{} {} (result i64) (local $record i32)\n",
+        name,
+        params.join(" ")
+    );
+    code.push_str("    global.get $heap\n    local.set $record\n");
+    code.push_str(&format!(
+        "    global.get $heap\n    i32.const {}\n    i32.add\n    global.set $heap\n",
+        first + fields.len() * 8
+    ));
+    if let Some(tag) = tag {
+        code.push_str(&format!(
+            "    local.get $record\n    i64.const {}\n    i64.store\n",
+            tag
+        ));
+    }
+    for (i, field) in fields.iter().enumerate() {
+        code.push_str(&format!(
+            "    local.get $record\n    local.get ${}\n    i64.store offset={}\n",
+            field,
+            first + i * 8
+        ));
+    }
+    code.push_str("    local.get $record\n    i64.extend_i32_u\n  )\n");
+    code
+}
+
+/// The helpers that optionals need, by name: `$optional.print` and the
+/// like print an optional with a host function, and `$optional.equal`
+/// and `$optional.equal_float` tell whether two optionals are equal.
+fn optional_helpers() -> Vec<(String, String)> {
+    let mut helpers = Vec::new();
+    let load = |side: &str, offset: &str, indent: &str| {
+        format!(
+            "{0}local.get ${1}\n{0}i32.wrap_i64\n{0}i64.load{2}\n",
+            indent, side, offset
+        )
+    };
+    for printer in ["print", "print_float", "print_char", "print_bool"] {
+        let name = format!("optional.{}", printer);
+        let mut code = format!("  (func ${} (param $optional i64)\n", name);
+        code.push_str(&load("optional", "", "    "));
+        code.push_str("    i32.wrap_i64\n    if\n");
+        code.push_str(&load("optional", " offset=8", "      "));
+        if printer == "print_float" {
+            code.push_str("      f64.reinterpret_i64\n");
+        }
+        code.push_str(&format!("      call ${}\n", printer));
+        code.push_str("    else\n      call $print_none\n    end\n  )\n");
+        helpers.push((name, code));
+    }
+    for (name, compare, convert) in [
+        ("optional.equal", "i64.eq", ""),
+        ("optional.equal_float", "f64.eq", "    f64.reinterpret_i64\n"),
+    ] {
+        let mut code = format!(
+            "  (func ${} (param $left i64) (param $right i64) (result i64)\n",
+            name
+        );
+        // Optionals with different flags differ, and two that hold
+        // nothing are equal.
+        code.push_str(&load("left", "", "    "));
+        code.push_str(&load("right", "", "    "));
+        code.push_str("    i64.ne\n    if\n      i64.const 0\n      return\n    end\n");
+        code.push_str(&load("left", "", "    "));
+        code.push_str("    i64.eqz\n    if\n      i64.const 1\n      return\n    end\n");
+        code.push_str(&load("left", " offset=8", "    "));
+        code.push_str(convert);
+        code.push_str(&load("right", " offset=8", "    "));
+        code.push_str(convert);
+        code.push_str(&format!("    {}\n    i64.extend_i32_u\n  )\n", compare));
+        helpers.push((name.to_string(), code));
+    }
+    helpers
+}
+
+/// Collects the locals used anywhere in `statements`, in order of first
+/// appearance: names bound by `let`, `for` and `match` arms, the hidden end
+/// and step of each range loop at nesting `depth` or deeper, and the hidden
+/// subject of each `match`. WASM declares all locals at
+/// the top of a function.
+fn collect_locals(statements: &[Statement], depth: usize, locals: &mut Vec<String>) {
+    fn add(locals: &mut Vec<String>, name: String) {
+        if !locals.contains(&name) {
+            locals.push(name);
+        }
+    }
+    for stmt in statements {
+        match &stmt.kind {
+            StatementKind::Let { name, .. } => add(locals, name.name.clone()),
+            StatementKind::If {
+                then_body,
+                else_ifs,
+                else_body,
+                ..
+            } => {
+                collect_locals(then_body, depth, locals);
+                for else_if in else_ifs {
+                    collect_locals(&else_if.body, depth, locals);
+                }
+                if let Some(else_body) = else_body {
+                    collect_locals(else_body, depth, locals);
+                }
+            }
+            StatementKind::For {
+                var,
+                iterable,
+                body,
+                ..
+            } => {
+                if matches!(iterable.kind, ExpressionKind::Range { .. }) {
+                    add(locals, var.name.clone());
+                    add(locals, format!("range.{}.end", depth));
+                    add(locals, format!("range.{}.step", depth));
+                }
+                collect_locals(body, depth + 1, locals);
+            }
+            StatementKind::While { body, .. } => collect_locals(body, depth + 1, locals),
+            StatementKind::Match { arms, .. } => {
+                add(locals, format!("match.{}", stmt.span.start));
+                for arm in arms {
+                    let mut bindings = Vec::new();
+                    pattern_bindings_wat(&arm.pattern, &[], &mut bindings);
+                    for (name, _) in bindings {
+                        add(locals, name);
+                    }
+                    collect_locals(&arm.body, depth, locals);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::typechecker::TypeChecker;
+
+    /// Checks `source` and compiles it to WASM in a file named after
+    /// `name`, which is removed again.
+    fn generate(name: &str, source: &str) -> Result<()> {
+        let tokens = Lexer::new().tokenize(source)?;
+        let program = Parser::new().parse(tokens)?;
+        let mut checker = TypeChecker::new();
+        checker.check(&program)?;
+        let file = format!("tabula-{}-{}.wasm", std::process::id(), name);
+        let output = std::env::temp_dir().join(file);
+        let generated = WasmGenerator::new().generate(&program, checker.types(), &output);
+        let _ = std::fs::remove_file(&output);
+        generated
+    }
+
+    /// The code of the error that compiling `source` reports.
+    fn error(source: &str) -> &'static str {
+        let error = generate("error", source).expect_err("source should not compile to WASM");
+        error
+            .downcast_ref::<Diagnostic>()
+            .expect("a diagnostic")
+            .code
+    }
+
+    #[test]
+    fn values_of_every_printable_type_compile() {
+        let source = "print 1  2.5  'a'  true  none\n";
+        generate("print", source).expect("source should compile to WASM");
+    }
+
+    #[test]
+    fn optionals_compile_with_a_flag() {
+        // `none` and `0` must be told apart.
+        let source = "let x:int?  0\nif x != none\n\tprint x + 1\nprint x  x == 0\n\
+                      let f:float?  2\nprint f == 2.0\nmatch x\n\tnone\n\t\tprint 0\n\
+                      \t1..3\n\t\tprint 1\n\tn\n\t\tprint n\n";
+        generate("optional", source).expect("source should compile to WASM");
+    }
+
+    #[test]
+    fn generic_functions_compile_to_an_instance_per_use() {
+        let source = "func add a  b\n\treturn a + b\n\nprint add 1  2\nprint add 1  2.5\n";
+        generate("generic", source).expect("source should compile to WASM");
+    }
+
+    #[test]
+    fn functions_that_need_what_wasm_lacks_are_reported() {
+        let cases = [
+            // Reads a local of `main`.
+            "let limit  3\nfunc over n\n\treturn n > limit\n\nprint over 5\n",
+            "print len [1]\n",
+        ];
+        for source in cases {
+            assert_eq!(error(source), codes::UNSUPPORTED_BY_BACKEND, "{}", source);
+        }
+    }
+
+    #[test]
+    fn unsupported_patterns_are_reported() {
+        let cases = [
+            "let s  \"a\"\nmatch s\n\t\"a\"\n\t\tprint 1\n\t_\n\t\tprint 2\n",
+            "let t  1  2\nmatch t\n\t1  b\n\t\tprint b\n\t_\n\t\tprint 2\n",
+        ];
+        for source in cases {
+            assert_eq!(error(source), codes::UNSUPPORTED_BY_BACKEND, "{}", source);
+        }
+    }
+
+    #[test]
+    fn unsupported_expressions_are_reported() {
+        let cases = [
+            "let s  \"hi\"\n",
+            "let xs  [1  2]\n",
+            "print 1..3\n",
+            "type P\n\tx:int\nlet p:P?  none\nprint p\n",
+        ];
+        for source in cases {
+            assert_eq!(error(source), codes::UNSUPPORTED_BY_BACKEND, "{}", source);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use clap::Parser;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tabula_compiler::lexer::{Token, TokenWithPos};
 use tabula_compiler::Compiler;
 
 #[derive(Parser)]
@@ -48,8 +50,8 @@ impl DocGenerator {
         }
 
         match format {
-            "html" => self.generate_html(&all_functions, &all_modules, output)?,
-            "markdown" => self.generate_markdown(&all_functions, &all_modules, output)?,
+            "html" => self.generate_html(&all_modules, output)?,
+            "markdown" => self.generate_markdown(&all_modules, output)?,
             _ => return Err(anyhow::anyhow!("Unknown format: {}", format)),
         }
 
@@ -65,6 +67,7 @@ impl DocGenerator {
     ) -> anyhow::Result<()> {
         let source = std::fs::read_to_string(file)?;
         let tokens = self.compiler.lexer.tokenize(&source)?;
+        let descriptions = self.extract_doc_comments(&tokens);
         let ast = self.compiler.parser.parse(tokens)?;
 
         let module_name = file
@@ -76,16 +79,18 @@ impl DocGenerator {
         let mut module_functions = Vec::new();
 
         for stmt in &ast.statements {
-            if let tabula_compiler::ast::Statement::Function {
+            if let tabula_compiler::ast::StatementKind::Function {
                 name,
                 params,
+                return_type,
                 body: _,
-            } = stmt
+            } = &stmt.kind
             {
                 let doc = FunctionDoc {
-                    name: name.clone(),
-                    params: params.clone(),
-                    description: self.extract_comment(&source, name),
+                    name: name.name.clone(),
+                    params: params.iter().map(|p| p.to_string()).collect(),
+                    return_type: return_type.as_ref().map(|ty| ty.to_string()),
+                    description: descriptions.get(&name.name).cloned(),
                 };
                 module_functions.push(doc.clone());
                 functions.push(doc);
@@ -95,7 +100,6 @@ impl DocGenerator {
         if !module_functions.is_empty() {
             modules.push(ModuleDoc {
                 name: module_name,
-                path: file.clone(),
                 functions: module_functions,
             });
         }
@@ -103,25 +107,32 @@ impl DocGenerator {
         Ok(())
     }
 
-    fn extract_comment(&self, source: &str, function_name: &str) -> Option<String> {
-        // Simple comment extraction - look for comments before function
-        let lines: Vec<&str> = source.lines().collect();
-        for (i, line) in lines.iter().enumerate() {
-            if line.contains(&format!("func {}", function_name)) {
-                // Look backwards for comments
-                if i > 0 && lines[i - 1].trim().starts_with("#") {
-                    return Some(lines[i - 1].trim_start_matches('#').trim().to_string());
-                }
+    /// Collects the `##` doc comments attached to each `func` keyword.
+    fn extract_doc_comments(&self, tokens: &[TokenWithPos]) -> HashMap<String, String> {
+        let mut descriptions = HashMap::new();
+        for (i, token) in tokens.iter().enumerate() {
+            if token.token != Token::Word("func".to_string()) {
+                continue;
+            }
+            let docs = token.doc_comments();
+            if docs.is_empty() {
+                continue;
+            }
+            let name = tokens[i + 1..].iter().find_map(|t| match &t.token {
+                Token::Word(w) => Some(w.clone()),
+                _ => None,
+            });
+            if let Some(name) = name {
+                descriptions.insert(name, docs.join("\n"));
             }
         }
-        None
+        descriptions
     }
 
     fn generate_html(
         &self,
-        functions: &[FunctionDoc],
         modules: &[ModuleDoc],
-        output: &PathBuf,
+        output: &Path,
     ) -> anyhow::Result<()> {
         let mut html = String::from(
             r#"<!DOCTYPE html>
@@ -147,9 +158,8 @@ impl DocGenerator {
             for func in &module.functions {
                 html.push_str("<div class=\"function\">\n");
                 html.push_str(&format!(
-                    "<div class=\"function-name\">func {}({})</div>\n",
-                    func.name,
-                    func.params.join(", ")
+                    "<div class=\"function-name\">func {}</div>\n",
+                    func.signature()
                 ));
                 if let Some(desc) = &func.description {
                     html.push_str(&format!("<p>{}</p>\n", desc));
@@ -165,16 +175,15 @@ impl DocGenerator {
 
     fn generate_markdown(
         &self,
-        functions: &[FunctionDoc],
         modules: &[ModuleDoc],
-        output: &PathBuf,
+        output: &Path,
     ) -> anyhow::Result<()> {
         let mut md = String::from("# Tabula Documentation\n\n");
 
         for module in modules {
             md.push_str(&format!("## Module: {}\n\n", module.name));
             for func in &module.functions {
-                md.push_str(&format!("### `func {}({})`\n\n", func.name, func.params.join(", ")));
+                md.push_str(&format!("### `func {}`\n\n", func.signature()));
                 if let Some(desc) = &func.description {
                     md.push_str(&format!("{}\n\n", desc));
                 }
@@ -190,13 +199,24 @@ impl DocGenerator {
 struct FunctionDoc {
     name: String,
     params: Vec<String>,
+    return_type: Option<String>,
     description: Option<String>,
 }
 
+impl FunctionDoc {
+    /// `name(a:int, b) -> int`, with whichever annotations were written.
+    fn signature(&self) -> String {
+        let mut signature = format!("{}({})", self.name, self.params.join(", "));
+        if let Some(return_type) = &self.return_type {
+            signature.push_str(&format!(" -> {}", return_type));
+        }
+        signature
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ModuleDoc {
     name: String,
-    path: PathBuf,
     functions: Vec<FunctionDoc>,
 }
 
@@ -1,6 +1,7 @@
 use clap::Parser;
-use regex::Regex;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tabula_compiler::ast::{Statement, StatementKind};
+use tabula_compiler::source::{SourceFile, Span};
 use tabula_compiler::Compiler;
 
 #[derive(Parser)]
@@ -61,17 +62,31 @@ impl Linter {
         // Check for long lines
         self.check_line_length(path, &lines);
         
+        // Parse, reporting every syntax error; naming checks still run over
+        // the statements that parsed.
+        let file = SourceFile::new(path.display().to_string(), source.as_str());
+        let (statements, errors) = match self.compiler.lexer.tokenize(&source) {
+            Ok(tokens) => {
+                let (program, errors) = self.compiler.parser.parse_with_recovery(tokens);
+                (program.statements, errors)
+            }
+            Err(diagnostic) => (Vec::new(), vec![diagnostic]),
+        };
+
         // Check naming conventions
-        self.check_naming(path, &source);
-        
-        // Try to parse and check for syntax issues
-        if let Err(e) = self.compiler.lexer.tokenize(&source) {
+        self.check_naming(path, &file, &statements);
+
+        for diagnostic in errors {
+            let start = diagnostic
+                .primary_span()
+                .map(|span| file.line_col(span.start))
+                .unwrap_or_default();
             self.issues.push(LintIssue {
                 file: path.clone(),
-                line: 1,
-                column: 1,
+                line: start.line + 1,
+                column: start.column + 1,
                 severity: Severity::Error,
-                message: format!("Parse error: {}", e),
+                message: format!("[{}] {}", diagnostic.code, diagnostic.message),
                 rule: "syntax-error".to_string(),
             });
         }
@@ -79,11 +94,11 @@ impl Linter {
         Ok(())
     }
 
-    fn check_indentation(&mut self, path: &PathBuf, lines: &[&str]) {
+    fn check_indentation(&mut self, path: &Path, lines: &[&str]) {
         let mut has_tabs = false;
         let mut has_spaces = false;
 
-        for (i, line) in lines.iter().enumerate() {
+        for line in lines {
             if line.starts_with('\t') {
                 has_tabs = true;
             }
@@ -94,7 +109,7 @@ impl Linter {
 
         if has_tabs && has_spaces {
             self.issues.push(LintIssue {
-                file: path.clone(),
+                file: path.to_path_buf(),
                 line: 1,
                 column: 1,
                 severity: Severity::Warning,
@@ -104,11 +119,11 @@ impl Linter {
         }
     }
 
-    fn check_trailing_whitespace(&mut self, path: &PathBuf, lines: &[&str]) {
+    fn check_trailing_whitespace(&mut self, path: &Path, lines: &[&str]) {
         for (i, line) in lines.iter().enumerate() {
             if line.ends_with(' ') || line.ends_with('\t') {
                 self.issues.push(LintIssue {
-                    file: path.clone(),
+                    file: path.to_path_buf(),
                     line: i + 1,
                     column: line.len(),
                     severity: Severity::Warning,
@@ -119,11 +134,11 @@ impl Linter {
         }
     }
 
-    fn check_line_length(&mut self, path: &PathBuf, lines: &[&str]) {
+    fn check_line_length(&mut self, path: &Path, lines: &[&str]) {
         for (i, line) in lines.iter().enumerate() {
             if line.len() > 100 {
                 self.issues.push(LintIssue {
-                    file: path.clone(),
+                    file: path.to_path_buf(),
                     line: i + 1,
                     column: 100,
                     severity: Severity::Info,
@@ -134,41 +149,82 @@ impl Linter {
         }
     }
 
-    fn check_naming(&mut self, path: &PathBuf, source: &str) {
-        let func_re = Regex::new(r"func\s+([a-z_][a-z0-9_]*)").unwrap();
-        let var_re = Regex::new(r"let\s+([a-z_][a-z0-9_]*)").unwrap();
-
-        for cap in func_re.captures_iter(source) {
-            let name = &cap[1];
-            if name.contains("__") {
-                self.issues.push(LintIssue {
-                    file: path.clone(),
-                    line: 1,
-                    column: 1,
-                    severity: Severity::Warning,
-                    message: format!("Function name '{}' contains double underscores", name),
-                    rule: "naming-convention".to_string(),
-                });
+    fn check_naming(&mut self, path: &Path, file: &SourceFile, statements: &[Statement]) {
+        for stmt in statements {
+            match &stmt.kind {
+                StatementKind::Function { name, body, .. } => {
+                    if name.name.contains("__") {
+                        self.push_at(
+                            path,
+                            file,
+                            name.span,
+                            Severity::Warning,
+                            format!("Function name '{}' contains double underscores", name),
+                            "naming-convention",
+                        );
+                    }
+                    self.check_naming(path, file, body);
+                }
+                StatementKind::Let { name, .. } => {
+                    // Allow single letter in loops
+                    let is_loop_counter = matches!(name.name.as_str(), "i" | "j" | "k");
+                    if name.name.len() == 1 && !is_loop_counter {
+                        self.push_at(
+                            path,
+                            file,
+                            name.span,
+                            Severity::Info,
+                            format!("Variable '{}' is too short", name),
+                            "naming-convention",
+                        );
+                    }
+                }
+                StatementKind::If {
+                    then_body,
+                    else_ifs,
+                    else_body,
+                    ..
+                } => {
+                    self.check_naming(path, file, then_body);
+                    for else_if in else_ifs {
+                        self.check_naming(path, file, &else_if.body);
+                    }
+                    if let Some(else_body) = else_body {
+                        self.check_naming(path, file, else_body);
+                    }
+                }
+                StatementKind::For { body, .. } | StatementKind::While { body, .. } => {
+                    self.check_naming(path, file, body)
+                }
+                StatementKind::Match { arms, .. } => {
+                    for arm in arms {
+                        self.check_naming(path, file, &arm.body);
+                    }
+                }
+                _ => {}
             }
         }
+    }
 
-        for cap in var_re.captures_iter(source) {
-            let name = &cap[1];
-            if name == "i" || name == "j" || name == "k" {
-                // Allow single letter in loops
-                continue;
-            }
-            if name.len() == 1 {
-                self.issues.push(LintIssue {
-                    file: path.clone(),
-                    line: 1,
-                    column: 1,
-                    severity: Severity::Info,
-                    message: format!("Variable '{}' is too short", name),
-                    rule: "naming-convention".to_string(),
-                });
-            }
-        }
+    /// Records an issue at the start of `span`, using 1-based positions.
+    fn push_at(
+        &mut self,
+        path: &Path,
+        file: &SourceFile,
+        span: Span,
+        severity: Severity,
+        message: String,
+        rule: &str,
+    ) {
+        let position = file.line_col(span.start);
+        self.issues.push(LintIssue {
+            file: path.to_path_buf(),
+            line: position.line + 1,
+            column: position.column + 1,
+            severity,
+            message,
+            rule: rule.to_string(),
+        });
     }
 
     fn print_issues(&self) {
@@ -1,24 +1,23 @@
-use lsp_types::{
-    CompletionItem, CompletionItemKind, CompletionParams, CompletionResponse,
-    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
-    DidOpenTextDocumentParams, DidSaveTextDocumentParams, DocumentSymbolParams,
-    DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse,
-    Hover, HoverParams, HoverResponse, InitializeParams, InitializeResult,
-    Location, Position, Range, ServerCapabilities, ServerInfo, SymbolKind,
-    TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
-    Url,
-};
 use std::collections::HashMap;
-use tower_lsp::{jsonrpc::Result, lsp_types::*, LanguageServer, LspService, Server};
+use std::sync::Mutex;
+use tabula_compiler::ast::{Program, StatementKind};
+use tabula_compiler::diagnostics::{self, Severity};
+use tabula_compiler::lexer::Lexer;
+use tabula_compiler::parser::Parser;
+use tabula_compiler::source::{SourceFile, Span};
+use tabula_compiler::typechecker::TypeChecker;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{Client, LanguageServer, LspService, Server};
 
 struct TabulaLanguageServer {
-    documents: HashMap<Url, String>,
-    compiler: tabula_compiler::Compiler,
+    client: Client,
+    documents: Mutex<HashMap<Url, String>>,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for TabulaLanguageServer {
-    async fn initialize(&self, _: InitializeParams) -> jsonrpc::Result<InitializeResult> {
+    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
         Ok(InitializeResult {
             server_info: Some(ServerInfo {
                 name: "tabula-lsp".to_string(),
@@ -31,7 +30,7 @@ impl LanguageServer for TabulaLanguageServer {
                         change: Some(TextDocumentSyncKind::FULL),
                         will_save: None,
                         will_save_wait_until: None,
-                        save: None,
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
                     },
                 )),
                 completion_provider: Some(CompletionOptions {
@@ -43,7 +42,6 @@ impl LanguageServer for TabulaLanguageServer {
                 document_symbol_provider: Some(OneOf::Left(true)),
                 ..Default::default()
             },
-            ..Default::default()
         })
     }
 
@@ -51,25 +49,46 @@ impl LanguageServer for TabulaLanguageServer {
         eprintln!("Tabula LSP initialized");
     }
 
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let text = params.text_document.text;
         self.documents
-            .insert(params.text_document.uri, params.text_document.text);
+            .lock()
+            .unwrap()
+            .insert(uri.clone(), text.clone());
+        self.validate_document(uri, &text).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
-        if let Some(text) = params.content_changes.into_iter().next() {
-            self.documents.insert(params.text_document.uri, text.text);
+        if let Some(change) = params.content_changes.into_iter().next() {
+            let uri = params.text_document.uri;
+            self.documents
+                .lock()
+                .unwrap()
+                .insert(uri.clone(), change.text.clone());
+            self.validate_document(uri, &change.text).await;
         }
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
         let uri = params.text_document.uri;
-        if let Some(text) = self.documents.get(&uri) {
-            self.validate_document(&uri, text).await;
+        let text = self.documents.lock().unwrap().get(&uri).cloned();
+        if let Some(text) = text {
+            self.validate_document(uri, &text).await;
         }
     }
 
-    async fn completion(&self, params: CompletionParams) -> jsonrpc::Result<Option<CompletionResponse>> {
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents.lock().unwrap().remove(&uri);
+        self.client.publish_diagnostics(uri, Vec::new(), None).await;
+    }
+
+    async fn completion(&self, _: CompletionParams) -> Result<Option<CompletionResponse>> {
         let items = vec![
             CompletionItem {
                 label: "let".to_string(),
@@ -105,7 +124,7 @@ impl LanguageServer for TabulaLanguageServer {
         Ok(Some(CompletionResponse::Array(items)))
     }
 
-    async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
+    async fn hover(&self, _: HoverParams) -> Result<Option<Hover>> {
         Ok(Some(Hover {
             contents: HoverContents::Scalar(MarkedString::String(
                 "Tabula language symbol".to_string(),
@@ -116,57 +135,124 @@ impl LanguageServer for TabulaLanguageServer {
 
     async fn goto_definition(
         &self,
-        params: GotoDefinitionParams,
-    ) -> jsonrpc::Result<Option<GotoDefinitionResponse>> {
+        _: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
         // TODO: Implement definition lookup
         Ok(None)
     }
 
+    #[allow(deprecated)]
     async fn document_symbol(
         &self,
         params: DocumentSymbolParams,
-    ) -> jsonrpc::Result<Option<DocumentSymbolResponse>> {
+    ) -> Result<Option<DocumentSymbolResponse>> {
         let uri = params.text_document.uri;
-        if let Some(text) = self.documents.get(&uri) {
-            if let Ok(tokens) = self.compiler.lexer.tokenize(text) {
-                if let Ok(ast) = self.compiler.parser.parse(tokens) {
-                    let symbols: Vec<DocumentSymbol> = ast
-                        .statements
-                        .iter()
-                        .filter_map(|stmt| match stmt {
-                            tabula_compiler::ast::Statement::Function { name, .. } => {
-                                Some(DocumentSymbol {
-                                    name: name.clone(),
-                                    kind: SymbolKind::FUNCTION,
-                                    range: Range::default(),
-                                    selection_range: Range::default(),
-                                    ..Default::default()
-                                })
-                            }
-                            _ => None,
-                        })
-                        .collect();
-                    return Ok(Some(DocumentSymbolResponse::Flat(symbols)));
-                }
-            }
-        }
-        Ok(None)
+        let text = match self.documents.lock().unwrap().get(&uri).cloned() {
+            Some(text) => text,
+            None => return Ok(None),
+        };
+        // A half-edited document still yields symbols for the statements
+        // that parsed.
+        let (program, _) = match parse(&text) {
+            Ok(parsed) => parsed,
+            Err(_) => return Ok(None),
+        };
+
+        let file = SourceFile::new(uri.as_str(), text.as_str());
+        let symbols: Vec<DocumentSymbol> = program
+            .statements
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StatementKind::Function { name, .. } => Some(DocumentSymbol {
+                    name: name.name.clone(),
+                    detail: None,
+                    kind: SymbolKind::FUNCTION,
+                    tags: None,
+                    deprecated: None,
+                    range: span_to_range(&file, stmt.span),
+                    selection_range: span_to_range(&file, name.span),
+                    children: None,
+                }),
+                _ => None,
+            })
+            .collect();
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
     }
 }
 
 impl TabulaLanguageServer {
-    fn new() -> Self {
+    fn new(client: Client) -> Self {
         Self {
-            documents: HashMap::new(),
-            compiler: tabula_compiler::Compiler::new(),
+            client,
+            documents: Mutex::new(HashMap::new()),
         }
     }
 
-    async fn validate_document(&self, uri: &Url, text: &str) {
-        // Validate and send diagnostics
-        if let Err(e) = self.compiler.lexer.tokenize(text) {
-            // Send diagnostic
-        }
+    /// Runs the front end over `text` and publishes every syntax error, or
+    /// the type error and warnings when the document parses cleanly.
+    async fn validate_document(&self, uri: Url, text: &str) {
+        let found = match parse(text) {
+            Ok((program, errors)) if errors.is_empty() => {
+                let mut checker = TypeChecker::new();
+                let mut found: Vec<_> = checker.check(&program).err().into_iter().collect();
+                found.extend(checker.warnings().iter().cloned());
+                found
+            }
+            Ok((_, errors)) => errors,
+            Err(diagnostic) => vec![diagnostic],
+        };
+        let file = SourceFile::new(uri.as_str(), text);
+        let diagnostics = found
+            .iter()
+            .map(|diagnostic| to_lsp_diagnostic(&file, diagnostic))
+            .collect();
+        self.client.publish_diagnostics(uri, diagnostics, None).await;
+    }
+}
+
+/// Lexes and parses `text`. Lexer errors are fatal; syntax errors come back
+/// alongside the partial program.
+fn parse(text: &str) -> diagnostics::DiagnosticResult<(Program, Vec<diagnostics::Diagnostic>)> {
+    let tokens = Lexer::new().tokenize(text)?;
+    Ok(Parser::new().parse_with_recovery(tokens))
+}
+
+fn to_lsp_diagnostic(file: &SourceFile, diagnostic: &diagnostics::Diagnostic) -> Diagnostic {
+    let range = diagnostic
+        .primary_span()
+        .map(|span| span_to_range(file, span))
+        .unwrap_or_default();
+    let severity = match diagnostic.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Note => DiagnosticSeverity::INFORMATION,
+    };
+
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message.push_str(&format!("\nnote: {}", note));
+    }
+    if let Some(help) = &diagnostic.help {
+        message.push_str(&format!("\nhelp: {}", help));
+    }
+
+    Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(diagnostic.code.to_string())),
+        source: Some("tabula".to_string()),
+        message,
+        ..Default::default()
+    }
+}
+
+/// Converts a byte span into an LSP range, which counts UTF-16 code units.
+fn span_to_range(file: &SourceFile, span: Span) -> Range {
+    let start = file.line_col_utf16(span.start);
+    let end = file.line_col_utf16(span.end);
+    Range {
+        start: Position::new(start.line as u32, start.column as u32),
+        end: Position::new(end.line as u32, end.column as u32),
     }
 }
 
@@ -175,7 +261,6 @@ async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(TabulaLanguageServer::new());
+    let (service, socket) = LspService::new(TabulaLanguageServer::new);
     Server::new(stdin, stdout, socket).serve(service).await;
 }
-
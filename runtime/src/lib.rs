@@ -1,6 +1,6 @@
 pub mod value;
 pub mod vm;
 
-pub use value::Value;
+pub use value::{Range, Value};
 pub use vm::VM;
 
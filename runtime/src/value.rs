@@ -6,11 +6,92 @@ pub enum Value {
     Float(f64),
     String(String),
     Boolean(bool),
+    Char(char),
     List(Vec<Value>),
+    /// Key and value pairs in insertion order, with unique keys.
+    Map(Vec<(Value, Value)>),
+    Tuple(Vec<Value>),
+    /// A value of a record type: its type name and its fields in
+    /// declaration order.
+    Record {
+        name: String,
+        fields: Vec<(String, Value)>,
+    },
+    /// A variant of an enum: its name and its fields in order.
+    Variant {
+        name: String,
+        fields: Vec<Value>,
+    },
+    Range(Range),
     None,
 }
 
+/// An integer range such as `0..10` or `10..=0 step -2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool,
+    /// Distance between items; never zero. A negative step counts down.
+    pub step: i64,
+}
+
+impl Range {
+    pub fn iter(&self) -> impl Iterator<Item = i64> {
+        let Range {
+            start,
+            end,
+            inclusive,
+            step,
+        } = *self;
+        let mut next = Some(start);
+        std::iter::from_fn(move || {
+            let current = next?;
+            let within = match (step > 0, inclusive) {
+                (true, false) => current < end,
+                (true, true) => current <= end,
+                (false, false) => current > end,
+                (false, true) => current >= end,
+            };
+            if !within {
+                return None;
+            }
+            next = current.checked_add(step);
+            Some(current)
+        })
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = if self.inclusive { "..=" } else { ".." };
+        write!(f, "{}{}{}", self.start, operator, self.end)?;
+        if self.step != 1 {
+            write!(f, " step {}", self.step)?;
+        }
+        Ok(())
+    }
+}
+
 impl Value {
+    /// Name of the value's type, as written in Tabula.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "int",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "bool",
+            Value::Char(_) => "char",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Tuple(_) => "tuple",
+            Value::Record { .. } => "record",
+            Value::Variant { .. } => "variant",
+            Value::Range(_) => "range",
+            Value::None => "none",
+        }
+    }
+
     pub fn as_number(&self) -> Option<i64> {
         match self {
             Value::Number(n) => Some(*n),
@@ -51,6 +132,7 @@ impl fmt::Display for Value {
             Value::Float(fl) => write!(f, "{}", fl),
             Value::String(s) => write!(f, "{}", s),
             Value::Boolean(b) => write!(f, "{}", b),
+            Value::Char(c) => write!(f, "{}", c),
             Value::List(items) => {
                 write!(f, "[")?;
                 for (i, item) in items.iter().enumerate() {
@@ -61,7 +143,49 @@ impl fmt::Display for Value {
                 }
                 write!(f, "]")
             }
-            Value::None => write!(f, "None"),
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+            Value::Record { name, fields } => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, value)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Variant { name, fields } if fields.is_empty() => write!(f, "{}", name),
+            Value::Variant { name, fields } => {
+                write!(f, "{}(", name)?;
+                for (i, value) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, ")")
+            }
+            Value::Range(range) => write!(f, "{}", range),
+            Value::None => write!(f, "none"),
         }
     }
 }
@@ -1,20 +1,20 @@
 use tabula_runtime::Value;
 use anyhow::Result;
 
-pub fn len(list: &[Value]) -> Result<Value> {
+pub fn len<T>(list: &[T]) -> Result<Value> {
     Ok(Value::Number(list.len() as i64))
 }
 
-pub fn push(list: &mut Vec<Value>, item: Value) -> Result<Value> {
+pub fn push<T>(list: &mut Vec<T>, item: T) -> Result<Value> {
     list.push(item);
     Ok(Value::None)
 }
 
-pub fn pop(list: &mut Vec<Value>) -> Result<Value> {
+pub fn pop<T>(list: &mut Vec<T>) -> Result<T> {
     list.pop().ok_or_else(|| anyhow::anyhow!("List is empty"))
 }
 
-pub fn get(list: &[Value], index: i64) -> Result<Value> {
+pub fn get<T: Clone>(list: &[T], index: i64) -> Result<T> {
     let idx = index as usize;
     if idx >= list.len() {
         return Err(anyhow::anyhow!("Index out of bounds"));
@@ -22,7 +22,7 @@ pub fn get(list: &[Value], index: i64) -> Result<Value> {
     Ok(list[idx].clone())
 }
 
-pub fn set(list: &mut Vec<Value>, index: i64, value: Value) -> Result<Value> {
+pub fn set<T>(list: &mut Vec<T>, index: i64, value: T) -> Result<Value> {
     let idx = index as usize;
     if idx >= list.len() {
         return Err(anyhow::anyhow!("Index out of bounds"));
@@ -30,4 +30,3 @@ pub fn set(list: &mut Vec<Value>, index: i64, value: Value) -> Result<Value> {
     list[idx] = value;
     Ok(Value::None)
 }
-